@@ -68,6 +68,7 @@ interface WASMAIInstance {
   get_ml_move: (state: unknown) => unknown;
   evaluate_position: (state: unknown) => number;
   load_ml_weights: (value_weights: unknown, policy_weights: unknown) => void;
+  load_ml_model_binary: (model_bytes: Uint8Array) => void;
   clear_transposition_table: () => void;
   get_transposition_table_size: () => number;
 }
@@ -376,6 +377,19 @@ class WASMAIService {
     }
   }
 
+  async loadMLModelBinary(modelBytes: Uint8Array): Promise<void> {
+    if (!this.isLoaded || !this.ai) {
+      throw new Error('WASM AI not loaded');
+    }
+
+    try {
+      this.ai.load_ml_model_binary(modelBytes);
+      console.log('✅ Binary ML model loaded successfully');
+    } catch (error) {
+      throw new Error(`Failed to load binary ML model: ${error}`);
+    }
+  }
+
   get isReady(): boolean {
     return this.isLoaded;
   }
@@ -415,29 +429,47 @@ export async function initializeWASMAI(): Promise<void> {
 
   // Try to load ML weights (trained Connect Four model)
   try {
-    console.log('🔍 Loading ML weights from /ml/data/weights/ml_ai_weights_best.json...');
-    const weightsResponse = await fetch('/ml/data/weights/ml_ai_weights_best.json');
-    console.log('🔍 Weights response status:', weightsResponse.status, weightsResponse.ok);
-
     let weightsLoaded = false;
 
-    if (weightsResponse.ok) {
-      const model = (await weightsResponse.json()) as {
-        value_network?: { weights: number[] };
-        policy_network?: { weights: number[] };
-      };
-      console.log('🔍 Model structure:', Object.keys(model));
-      console.log('🔍 Value network exists:', !!model.value_network);
-      console.log('🔍 Policy network exists:', !!model.policy_network);
-      console.log('🔍 Value weights length:', model.value_network?.weights?.length);
-      console.log('🔍 Policy weights length:', model.policy_network?.weights?.length);
-
-      if (model.value_network?.weights && model.policy_network?.weights) {
-        await service.loadMLWeights(model.value_network.weights, model.policy_network.weights);
-        console.log('✅ ML weights loaded successfully (trained Connect Four model)');
+    // Prefer the compact binary model, which is smaller and faster to parse than the JSON
+    try {
+      console.log('🔍 Loading binary ML model from /ml/data/weights/ml_ai_weights_best.bin...');
+      const binaryResponse = await fetch('/ml/data/weights/ml_ai_weights_best.bin');
+      if (binaryResponse.ok) {
+        const modelBytes = new Uint8Array(await binaryResponse.arrayBuffer());
+        await service.loadMLModelBinary(modelBytes);
+        console.log('✅ ML model loaded successfully (binary trained Connect Four model)');
         weightsLoaded = true;
       } else {
-        console.warn('Model format not recognized - missing weights arrays');
+        console.warn('Binary ML model not available:', binaryResponse.status);
+      }
+    } catch (binaryError) {
+      console.warn('Could not load binary ML model, falling back to JSON:', binaryError);
+    }
+
+    if (!weightsLoaded) {
+      console.log('🔍 Loading ML weights from /ml/data/weights/ml_ai_weights_best.json...');
+      const weightsResponse = await fetch('/ml/data/weights/ml_ai_weights_best.json');
+      console.log('🔍 Weights response status:', weightsResponse.status, weightsResponse.ok);
+
+      if (weightsResponse.ok) {
+        const model = (await weightsResponse.json()) as {
+          value_network?: { weights: number[] };
+          policy_network?: { weights: number[] };
+        };
+        console.log('🔍 Model structure:', Object.keys(model));
+        console.log('🔍 Value network exists:', !!model.value_network);
+        console.log('🔍 Policy network exists:', !!model.policy_network);
+        console.log('🔍 Value weights length:', model.value_network?.weights?.length);
+        console.log('🔍 Policy weights length:', model.policy_network?.weights?.length);
+
+        if (model.value_network?.weights && model.policy_network?.weights) {
+          await service.loadMLWeights(model.value_network.weights, model.policy_network.weights);
+          console.log('✅ ML weights loaded successfully (trained Connect Four model)');
+          weightsLoaded = true;
+        } else {
+          console.warn('Model format not recognized - missing weights arrays');
+        }
       }
     }
 
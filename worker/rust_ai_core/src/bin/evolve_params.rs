@@ -3,6 +3,7 @@
 use connect_four_ai_core::{genetic_params::GeneticParams, GameState, Player, AI};
 
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Write;
 
@@ -16,6 +17,50 @@ const SEARCH_DEPTH: u8 = 1; // Using depth 1 for faster evolution while maintain
 const ELITE_SIZE: usize = 3; // Keep top 3 individuals instead of just 1
 const TOURNAMENT_SIZE: usize = 7; // Increased from 5 for better selection pressure
 
+/// One line of the evolution history kept in checkpoints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GenerationSummary {
+    generation: usize,
+    best_id: String,
+    best_fitness: f64,
+    avg_fitness: f64,
+    diversity: f64,
+}
+
+/// Everything the generation loop carries from one generation to the next,
+/// saved after each so `--resume <checkpoint>` can continue a stopped run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EvolutionCheckpoint {
+    /// Generations completed.
+    generation: usize,
+    population: Vec<GeneticParams>,
+    best_fitness: f64,
+    best_params: GeneticParams,
+    previous_best_params: GeneticParams,
+    hall_of_fame_fitness: f64,
+    generations_without_improvement: usize,
+    consecutive_stagnation_count: usize,
+    history: Vec<GenerationSummary>,
+    params_csv_path: String,
+    convergence_csv_path: String,
+}
+
+impl EvolutionCheckpoint {
+    fn load(path: &str) -> Result<Self, String> {
+        let json =
+            fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: invalid checkpoint: {}", path, e))
+    }
+
+    // Written through a temporary file so a crash never tears the checkpoint
+    fn save(&self, path: &str) -> Result<(), String> {
+        let partial = format!("{}.partial", path);
+        let json = serde_json::to_string_pretty(self).unwrap();
+        fs::write(&partial, json).map_err(|e| format!("failed to write {}: {}", partial, e))?;
+        fs::rename(&partial, path).map_err(|e| format!("failed to rename {}: {}", partial, e))
+    }
+}
+
 fn optimize_cpu_usage() {
     if cfg!(target_os = "macos") {
         let num_cores = std::thread::available_parallelism()
@@ -600,58 +645,8 @@ fn inject_diversity(population: &mut Vec<GeneticParams>, target_diversity: f64)
     }
 }
 
-fn main() {
-    optimize_cpu_usage();
-    println!("🧬 Starting Connect Four AI parameter evolution");
-    println!("Population size: {}", POPULATION_SIZE);
-    println!("Generations: {}", GENERATIONS);
-    println!("Games per evaluation: {}", GAMES_PER_EVAL);
-    println!("Search depth: {}", SEARCH_DEPTH);
-    println!("⚠️  NOTE: Each generation plays against the previous generation's best, not default params");
-
-    // Show starting parameters (default)
-    let default_params = GeneticParams::default();
-    println!("\n🎯 Starting parameters (default):");
-    println!("  Win score: {}", default_params.win_score);
-    println!("  Loss score: {}", default_params.loss_score);
-    println!(
-        "  Center column value: {}",
-        default_params.center_column_value
-    );
-    println!(
-        "  Adjacent center value: {}",
-        default_params.adjacent_center_value
-    );
-    println!(
-        "  Outer column value: {}",
-        default_params.outer_column_value
-    );
-    println!("  Edge column value: {}", default_params.edge_column_value);
-    println!(
-        "  Row height weight: {:.3}",
-        default_params.row_height_weight
-    );
-    println!(
-        "  Center control weight: {:.3}",
-        default_params.center_control_weight
-    );
-    println!(
-        "  Piece count weight: {:.3}",
-        default_params.piece_count_weight
-    );
-    println!("  Threat weight: {:.3}", default_params.threat_weight);
-    println!("  Mobility weight: {:.3}", default_params.mobility_weight);
-    println!(
-        "  Vertical control weight: {:.3}",
-        default_params.vertical_control_weight
-    );
-    println!(
-        "  Horizontal control weight: {:.3}",
-        default_params.horizontal_control_weight
-    );
-    println!("  Defensive weight: {:.3}", default_params.defensive_weight);
-
-    // Create a more diverse initial population
+// A diverse starting population: defaults, extremes, strategies and random
+fn initial_population() -> Vec<GeneticParams> {
     let mut population: Vec<GeneticParams> = Vec::new();
 
     // Add default parameters as one individual
@@ -747,28 +742,141 @@ fn main() {
         population.push(GeneticParams::random());
     }
 
-    // Create CSV files for parameter tracking
-    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-    let params_csv_path = format!("evolution_params_{}.csv", timestamp);
-    let convergence_csv_path = format!("evolution_convergence_{}.csv", timestamp);
+    population
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let resume = args
+        .iter()
+        .position(|arg| arg == "--resume")
+        .map(|idx| match args.get(idx + 1) {
+            Some(path) => path.clone(),
+            None => {
+                eprintln!("Usage: {} [--resume <checkpoint>]", args[0]);
+                std::process::exit(1);
+            }
+        });
+
+    optimize_cpu_usage();
+    println!("🧬 Starting Connect Four AI parameter evolution");
+    println!("Population size: {}", POPULATION_SIZE);
+    println!("Generations: {}", GENERATIONS);
+    println!("Games per evaluation: {}", GAMES_PER_EVAL);
+    println!("Search depth: {}", SEARCH_DEPTH);
+    println!("⚠️  NOTE: Each generation plays against the previous generation's best, not default params");
+
+    // Show starting parameters (default)
+    let default_params = GeneticParams::default();
+    println!("\n🎯 Starting parameters (default):");
+    println!("  Win score: {}", default_params.win_score);
+    println!("  Loss score: {}", default_params.loss_score);
+    println!(
+        "  Center column value: {}",
+        default_params.center_column_value
+    );
+    println!(
+        "  Adjacent center value: {}",
+        default_params.adjacent_center_value
+    );
+    println!(
+        "  Outer column value: {}",
+        default_params.outer_column_value
+    );
+    println!("  Edge column value: {}", default_params.edge_column_value);
+    println!(
+        "  Row height weight: {:.3}",
+        default_params.row_height_weight
+    );
+    println!(
+        "  Center control weight: {:.3}",
+        default_params.center_control_weight
+    );
+    println!(
+        "  Piece count weight: {:.3}",
+        default_params.piece_count_weight
+    );
+    println!("  Threat weight: {:.3}", default_params.threat_weight);
+    println!("  Mobility weight: {:.3}", default_params.mobility_weight);
+    println!(
+        "  Vertical control weight: {:.3}",
+        default_params.vertical_control_weight
+    );
+    println!(
+        "  Horizontal control weight: {:.3}",
+        default_params.horizontal_control_weight
+    );
+    println!("  Defensive weight: {:.3}", default_params.defensive_weight);
 
+    // A resumed run picks up its population, progress and CSV files from the
+    // checkpoint; a new one starts from the initial population
+    let (checkpoint_path, checkpoint) = match resume {
+        Some(path) => {
+            let checkpoint = EvolutionCheckpoint::load(&path).unwrap_or_else(|e| {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            });
+            println!(
+                "⏩ Resuming from {} after generation {} (best fitness {:.3})",
+                path, checkpoint.generation, checkpoint.best_fitness
+            );
+            (path, checkpoint)
+        }
+        None => {
+            let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+            let checkpoint = EvolutionCheckpoint {
+                generation: 0,
+                population: initial_population(),
+                best_fitness: 0.0,
+                best_params: GeneticParams::default(),
+                // Track previous generation's best
+                previous_best_params: GeneticParams::default(),
+                hall_of_fame_fitness: 0.0,
+                generations_without_improvement: 0,
+                // Track consecutive generations with stagnation
+                consecutive_stagnation_count: 0,
+                history: Vec::new(),
+                params_csv_path: format!("evolution_params_{}.csv", timestamp),
+                convergence_csv_path: format!("evolution_convergence_{}.csv", timestamp),
+            };
+            (
+                format!("evolution_checkpoint_{}.json", timestamp),
+                checkpoint,
+            )
+        }
+    };
+    let EvolutionCheckpoint {
+        generation: start_generation,
+        mut population,
+        mut best_fitness,
+        mut best_params,
+        mut previous_best_params,
+        mut hall_of_fame_fitness,
+        mut generations_without_improvement,
+        mut consecutive_stagnation_count,
+        mut history,
+        params_csv_path,
+        convergence_csv_path,
+    } = checkpoint;
+
+    // Create CSV files for parameter tracking, appending when resuming
+    let open_csv = |path: &str| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+    };
     let mut params_csv_file =
-        std::fs::File::create(&params_csv_path).expect("Failed to create parameters CSV file");
-    let mut convergence_csv_file = std::fs::File::create(&convergence_csv_path)
-        .expect("Failed to create convergence CSV file");
+        open_csv(&params_csv_path).expect("Failed to create parameters CSV file");
+    let mut convergence_csv_file =
+        open_csv(&convergence_csv_path).expect("Failed to create convergence CSV file");
 
     println!("📊 Parameter tracking enabled:");
     println!("  Parameters: {}", params_csv_path);
     println!("  Convergence: {}", convergence_csv_path);
+    println!("  Checkpoint: {}", checkpoint_path);
 
-    let mut best_fitness = 0.0;
-    let mut best_params = GeneticParams::default();
-    let mut previous_best_params = GeneticParams::default(); // Track previous generation's best
-    let mut hall_of_fame_fitness = 0.0;
-    let mut generations_without_improvement = 0;
-    let mut consecutive_stagnation_count = 0; // Track consecutive generations with stagnation
-
-    for generation in 0..GENERATIONS {
+    for generation in start_generation..GENERATIONS {
         println!("\n🔄 Generation {}", generation + 1);
 
         // Evaluate fitness for all individuals with progress reporting
@@ -1141,6 +1249,13 @@ fn main() {
         // Log diversity information
         let diversity = calculate_population_diversity(&population);
         println!("🌱 Population diversity: {:.3}", diversity);
+        history.push(GenerationSummary {
+            generation: generation + 1,
+            best_id: current_winner.id.clone(),
+            best_fitness: best_score,
+            avg_fitness,
+            diversity,
+        });
 
         // Log parameters to CSV for graphing
         if let Err(e) = log_generation_parameters(
@@ -1325,6 +1440,27 @@ fn main() {
             inject_diversity(&mut new_population, target_diversity);
         }
 
+        // Update previous best for next generation (track current generation's winner)
+        previous_best_params = current_winner.clone();
+        population = new_population;
+
+        let checkpoint = EvolutionCheckpoint {
+            generation: generation + 1,
+            population: population.clone(),
+            best_fitness,
+            best_params: best_params.clone(),
+            previous_best_params: previous_best_params.clone(),
+            hall_of_fame_fitness,
+            generations_without_improvement,
+            consecutive_stagnation_count,
+            history: history.clone(),
+            params_csv_path: params_csv_path.clone(),
+            convergence_csv_path: convergence_csv_path.clone(),
+        };
+        if let Err(e) = checkpoint.save(&checkpoint_path) {
+            eprintln!("⚠️  Failed to save checkpoint: {}", e);
+        }
+
         // Check for early stopping due to stagnation or convergence
         if consecutive_stagnation_count >= 5 {
             println!(
@@ -1347,10 +1483,6 @@ fn main() {
             break;
         }
 
-        // Update previous best for next generation (track current generation's winner)
-        previous_best_params = current_winner.clone();
-        population = new_population;
-
         // Generation summary
         if (generation + 1) % 10 == 0 {
             println!(
@@ -0,0 +1,107 @@
+//! Converts a model to the compact binary format and reports how far each
+//! precision drifts from the f32 model on positions from random games.
+
+use connect_four_ai_core::{
+    ml_ai::{DEFAULT_MODEL_PATH, MLAI},
+    model::ModelFile,
+    quantization::{measure_drift, Precision},
+    GameState,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::env;
+use std::time::Instant;
+
+const DEFAULT_POSITIONS: usize = 1000;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!(
+            "Usage: {} [model] [output.bin] [f32|f16|int8] [positions]",
+            args[0]
+        );
+        println!();
+        println!("Writes the model in binary form at the given precision (default int8)");
+        println!("and prints value/policy drift against the f32 model for every precision.");
+        return Ok(());
+    }
+
+    let model_path = args.get(1).map_or(DEFAULT_MODEL_PATH, String::as_str);
+    let output_path = args
+        .get(2)
+        .cloned()
+        .unwrap_or_else(|| format!("{}.bin", model_path.trim_end_matches(".json")));
+    let precision: Precision = args.get(3).map_or(Ok(Precision::Int8), |s| s.parse())?;
+    let positions = args
+        .get(4)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_POSITIONS);
+
+    let model = ModelFile::load(model_path)?;
+    let reference = MLAI::from_model_file(&model)?;
+    let states = random_positions(positions, 42);
+    let json_size = model.to_json().len();
+    println!("📦 {} ({} bytes as JSON)", model_path, json_size);
+    println!("🎲 Comparing on {} positions", states.len());
+    println!();
+    println!(
+        "{:<6} {:>10} {:>8} {:>12} {:>12} {:>12} {:>12} {:>10}",
+        "format",
+        "bytes",
+        "ratio",
+        "value mean",
+        "value max",
+        "policy mean",
+        "policy max",
+        "top move"
+    );
+
+    for candidate_precision in [Precision::F32, Precision::F16, Precision::Int8] {
+        let bytes = model.to_binary(candidate_precision);
+        let start = Instant::now();
+        let candidate = MLAI::from_binary(&bytes)?;
+        let load_time = start.elapsed();
+        let drift = measure_drift(&reference, &candidate, &states);
+        println!(
+            "{:<6} {:>10} {:>7.1}x {:>12.6} {:>12.6} {:>12.6} {:>12.6} {:>9.1}%  (loaded in {:?})",
+            candidate_precision.as_str(),
+            bytes.len(),
+            json_size as f64 / bytes.len() as f64,
+            drift.mean_value_error,
+            drift.max_value_error,
+            drift.mean_policy_error,
+            drift.max_policy_error,
+            drift.top_move_agreement * 100.0,
+            load_time
+        );
+    }
+
+    model.save_binary(&output_path, precision)?;
+    println!();
+    println!("💾 Wrote {} model to {}", precision, output_path);
+    Ok(())
+}
+
+// Positions from seeded random games, stopping each at a random depth so
+// openings and middlegames are both represented
+fn random_positions(count: usize, seed: u64) -> Vec<GameState> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut states = Vec::with_capacity(count);
+    while states.len() < count {
+        let mut state = GameState::new();
+        let depth = rng.gen_range(0..30);
+        for _ in 0..depth {
+            let moves = state.get_valid_moves();
+            match moves.choose(&mut rng) {
+                Some(&col) if !state.is_game_over() => state.make_move(col).unwrap(),
+                _ => break,
+            }
+        }
+        if !state.is_game_over() {
+            states.push(state);
+        }
+    }
+    states
+}
@@ -2,21 +2,35 @@ use connect_four_ai_core::training::{
     evaluate_ai_performance, train_genetic_algorithm, TrainingConfig,
 };
 use connect_four_ai_core::{
-    ml_ai::MLAI,
-    self_play::{SelfPlayConfig, SelfPlayTrainer},
+    dataset::{
+        Dataset, DatasetMetadata, SampleMetadata, ShardWriter, TrainingSample,
+        DEFAULT_SAMPLES_PER_SHARD,
+    },
+    features::FeatureSet,
+    mcts::RolloutPolicy,
+    ml_ai::{DEFAULT_HIDDEN_SIZES, DEFAULT_MODEL_PATH, MLAI},
+    model::ModelFile,
+    neural_network::TrainNetworkConfig,
+    optimizer::OptimizerConfig,
+    pipeline::{GatingConfig, Pipeline, PipelineConfig},
+    replay_buffer::ReplayBuffer,
+    self_play::{Adjudication, SelfPlayCheckpoint, SelfPlayConfig, SelfPlayTrainer},
+    supervised::{train_model, SelfPlayDataset},
     GameState,
 };
 use serde_json;
 use std::env;
 use std::fs;
+use std::path::Path;
 use std::time::Instant;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let resume = take_option(&mut args, "--resume")?;
 
     if args.len() < 2 {
         println!(
-            "Usage: {} <train|evaluate|generate_data|self_play> [config_file]",
+            "Usage: {} <train|evaluate|generate_data|self_play|train_network|pipeline|import_model> [config_file]",
             args[0]
         );
         println!();
@@ -24,11 +38,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("  train         - Run genetic algorithm training");
         println!("  evaluate      - Evaluate AI performance");
         println!("  generate_data - Generate self-play training data");
-        println!("  self_play     - Run advanced self-play with MCTS");
+        println!(
+            "  self_play     - Run advanced self-play with MCTS: [config_file] [--resume <checkpoint>]"
+        );
+        println!("  train_network - Train networks on self-play data: <data_file|shard_dir> [output_file] [config_file]");
+        println!(
+            "  pipeline      - Self-play, train and gate generations: [config_file] [run_dir]"
+        );
+        println!(
+            "  import_model  - Convert a legacy or PyTorch weight file: <input_file> [output_file]"
+        );
         return Ok(());
     }
 
     let command = &args[1];
+    if resume.is_some() && command != "self_play" {
+        return Err(format!("--resume does not apply to {}", command).into());
+    }
 
     match command.as_str() {
         "train" => {
@@ -183,21 +209,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let training_data = generate_self_play_data(num_games, depth)?;
 
             // Save training data
-            if let Some(parent) = std::path::Path::new(output_file).parent() {
-                std::fs::create_dir_all(parent)?;
-            }
-
-            let output_data = serde_json::json!({
-                "metadata": {
-                    "num_games": num_games,
-                    "depth": depth,
-                    "generated_at": chrono::Utc::now().to_rfc3339(),
-                    "version": "1.0"
-                },
-                "training_data": training_data
-            });
-
-            fs::write(output_file, serde_json::to_string_pretty(&output_data)?)?;
+            training_data.save(output_file)?;
 
             let total_time = start_time.elapsed();
             println!("\n=== Data Generation Complete ===");
@@ -223,46 +235,225 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let config_content = fs::read_to_string(config_file)?;
             let config: serde_json::Value = serde_json::from_str(&config_content)?;
 
-            let num_games = config["training_defaults"]["num_games"]
-                .as_u64()
-                .unwrap_or(1000) as usize;
-            let mcts_simulations = config["training_defaults"]
-                .get("mcts_simulations")
-                .and_then(|v| v.as_u64())
-                .unwrap_or(800) as usize;
+            let self_play_config = self_play_config(&config["training_defaults"])?;
+            let num_games = self_play_config.num_games;
             let output_file = config["output_formats"]["unified"]
                 .as_str()
                 .unwrap_or("ml/data/weights/advanced_self_play_data.json");
+            // A shard directory streams games to disk instead of one file
+            let shard_dir = config["output_formats"]["shards"].as_str();
+            let samples_per_shard = samples_per_shard(&config["training_defaults"]);
 
             println!("Number of games: {}", num_games);
-            println!("MCTS simulations: {}", mcts_simulations);
+            println!("MCTS simulations: {}", self_play_config.mcts_simulations);
+            println!("MCTS leaf batch size: {}", self_play_config.leaf_batch_size);
+            println!("MCTS rollout policy: {:?}", self_play_config.rollout_policy);
+            println!("MCTS RAVE schedule: {:?}", self_play_config.rave);
             println!("Output file: {}", output_file);
 
-            // Create self-play configuration
-            let mut self_play_config = SelfPlayConfig::default();
-            self_play_config.num_games = num_games;
-            self_play_config.mcts_simulations = mcts_simulations;
+            // Progress is checkpointed next to the output unless resuming
+            // from a checkpoint elsewhere
+            let checkpoint_path = match (&resume, shard_dir) {
+                (Some(path), _) => Path::new(path).to_path_buf(),
+                (None, Some(dir)) => Path::new(dir).join("checkpoint.json"),
+                (None, None) => Path::new(output_file).with_extension("checkpoint.json"),
+            };
+            println!("Checkpoint: {}", checkpoint_path.display());
 
             // Run advanced self-play training
             println!("\n🎮 Starting advanced self-play training with MCTS...");
             let mut trainer = SelfPlayTrainer::new(self_play_config);
-            let training_data = trainer.generate_training_data();
-
-            // Save training data
-            trainer.save_training_data(&training_data, output_file)?;
+            trainer.checkpoint_to(&checkpoint_path);
+            if resume.is_some() {
+                let checkpoint = SelfPlayCheckpoint::load(&checkpoint_path)?;
+                println!(
+                    "⏩ Resuming from game {} with seed {}",
+                    checkpoint.games_completed, checkpoint.seed
+                );
+                trainer.resume(checkpoint);
+            }
+            let (samples, output) = match shard_dir {
+                Some(dir) => {
+                    let metadata = trainer.dataset_metadata();
+                    let mut writer =
+                        ShardWriter::create(Path::new(dir), &metadata, samples_per_shard)?;
+                    let samples = trainer.generate_to_shards(&mut writer)?;
+                    writer.finish()?;
+                    (samples, dir)
+                }
+                None => {
+                    let training_data = trainer.generate_training_data()?;
+                    training_data.save(output_file)?;
+                    (training_data.len(), output_file)
+                }
+            };
+            println!("💾 Training data saved to: {}", output);
 
             let total_time = start_time.elapsed();
             println!("\n=== Advanced Self-Play Complete ===");
             println!("Total time: {:.2} seconds", total_time.as_secs_f64());
             println!("Games played: {}", num_games);
-            println!("Training samples: {}", training_data.len());
-            println!("Output file: {}", output_file);
+            println!("Training samples: {}", samples);
+            println!("Output: {}", output);
             println!("===================================");
         }
 
+        "train_network" => {
+            // Supervised training on data written by `self_play`
+            let data_file = args
+                .get(2)
+                .ok_or("train_network needs a self-play data file")?;
+            let default_output = DEFAULT_MODEL_PATH.to_string();
+            let output_file = args.get(3).unwrap_or(&default_output);
+            let default_config = "ml/config/training.json".to_string();
+            let config_file = args.get(4).unwrap_or(&default_config);
+
+            let config: serde_json::Value = match fs::read_to_string(config_file) {
+                Ok(content) => serde_json::from_str(&content)?,
+                Err(_) => {
+                    println!("⚠️  No config at {}, using defaults", config_file);
+                    serde_json::Value::Null
+                }
+            };
+            let defaults = &config["training_defaults"];
+            let train_config = train_network_config(defaults)?;
+            let replay_shards = replay_shards(defaults);
+            let hidden_sizes = hidden_sizes(&config)?;
+
+            println!("=== Connect Four Network Training ===");
+            println!("Data file: {}", data_file);
+            println!("Output file: {}", output_file);
+            println!("Hidden sizes: {:?}", hidden_sizes);
+            println!("Epochs: {}", train_config.epochs);
+            println!("Batch size: {}", train_config.batch_size);
+            println!("Validation split: {}", train_config.validation_split);
+            println!("Learning rate schedule: {:?}", train_config.schedule);
+            println!("=====================================");
+
+            let start_time = Instant::now();
+            let dataset = if Path::new(data_file.as_str()).is_dir() {
+                // Train on the newest shards only
+                let buffer = ReplayBuffer::open(Path::new(data_file.as_str()), replay_shards)?;
+                println!("🔁 Replay buffer holds {} shards", buffer.shards().count());
+                SelfPlayDataset::from_dataset(&buffer.to_dataset()?)?
+            } else {
+                SelfPlayDataset::load(data_file)?
+            };
+            if dataset.is_empty() {
+                return Err(format!("{} has no training samples", data_file).into());
+            }
+            println!(
+                "📊 Loaded {} samples ({})",
+                dataset.len(),
+                dataset.feature_set
+            );
+
+            let model = train_model(&dataset, &hidden_sizes, &train_config);
+            if let Some(parent) = Path::new(output_file.as_str()).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            model.save(output_file)?;
+
+            println!("\n=== Network Training Complete ===");
+            println!(
+                "Total time: {:.2} seconds",
+                start_time.elapsed().as_secs_f64()
+            );
+            println!("Epochs: {:?}", model.metadata.epochs);
+            println!("Validation loss: {:?}", model.metadata.final_loss);
+            println!("Model saved to: {}", output_file);
+            println!("=================================");
+        }
+
+        "import_model" => {
+            // Legacy flat arrays and PyTorch state_dict exports to a model file
+            let input_file = args.get(2).ok_or("import_model needs a weight file")?;
+            let default_output = DEFAULT_MODEL_PATH.to_string();
+            let output_file = args.get(3).unwrap_or(&default_output);
+
+            let model = ModelFile::load(input_file)?;
+            for (role, network) in [
+                ("Value", &model.value_network),
+                ("Policy", &model.policy_network),
+            ] {
+                println!(
+                    "{} network: {:?} ({} weights)",
+                    role,
+                    network.architecture,
+                    network.weights.len()
+                );
+            }
+            model.save(output_file)?;
+            println!("💾 Model saved to: {}", output_file);
+        }
+
+        "pipeline" => {
+            // Self-play, training and gating in a loop
+            let default_config = "ml/config/training.json".to_string();
+            let config_file = args.get(2).unwrap_or(&default_config);
+            let default_dir = "ml/data/pipeline".to_string();
+            let run_dir = args.get(3).unwrap_or(&default_dir);
+
+            let config: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(config_file)?)?;
+            let defaults = &config["training_defaults"];
+            let self_play = self_play_config(defaults)?;
+            let gating = GatingConfig {
+                games: defaults["gating_games"].as_u64().unwrap_or(40) as usize,
+                mcts_simulations: defaults["gating_simulations"]
+                    .as_u64()
+                    .map_or(self_play.mcts_simulations, |v| v as usize),
+                exploration_constant: self_play.exploration_constant,
+                margin: defaults["gating_margin"].as_f64().unwrap_or(0.05) as f32,
+                ..GatingConfig::default()
+            };
+            let pipeline_config = PipelineConfig {
+                generations: defaults["generations"].as_u64().unwrap_or(10) as usize,
+                self_play,
+                training: train_network_config(defaults)?,
+                samples_per_shard: samples_per_shard(defaults),
+                replay_shards: replay_shards(defaults),
+                gating,
+            };
+
+            println!("=== Connect Four Training Pipeline ===");
+            println!("Config file: {}", config_file);
+            println!("Run directory: {}", run_dir);
+            println!("Generations: {}", pipeline_config.generations);
+            println!(
+                "Games per generation: {}",
+                pipeline_config.self_play.num_games
+            );
+            println!(
+                "Gating: {} games, promote at {:.1}%",
+                pipeline_config.gating.games,
+                (0.5 + pipeline_config.gating.margin) * 100.0
+            );
+            println!("======================================");
+
+            let start_time = Instant::now();
+            // Candidates are fine-tuned from the best model, so the first
+            // one sets the architecture for the whole run
+            let initial =
+                MLAI::with_hidden_sizes(FeatureSet::RelativeFeatures, &hidden_sizes(&config)?);
+            let mut pipeline = Pipeline::open(pipeline_config, Path::new(run_dir), initial)?;
+            pipeline.run()?;
+
+            println!("\n=== Pipeline Complete ===");
+            println!(
+                "Total time: {:.2} seconds",
+                start_time.elapsed().as_secs_f64()
+            );
+            println!("Best generation: {}", pipeline.best_generation());
+            println!("Best model: {}/best.json", run_dir);
+            println!("=========================");
+        }
+
         _ => {
             println!("Unknown command: {}", command);
-            println!("Available commands: train, evaluate, generate_data, self_play");
+            println!(
+                "Available commands: train, evaluate, generate_data, self_play, train_network, pipeline, import_model"
+            );
         }
     }
 
@@ -271,32 +462,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn generate_self_play_data(
     num_games: usize,
-    _depth: usize,
-) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+    depth: usize,
+) -> Result<Dataset, Box<dyn std::error::Error>> {
     let mut training_data = Vec::new();
-    let mut ai = MLAI::new();
-
-    // Try to load existing weights if available
-    if let Ok(weights) = fs::read_to_string("ml/data/weights/ml_ai_weights.json") {
-        if let Ok(weights_data) = serde_json::from_str::<serde_json::Value>(&weights) {
-            if let (Some(value_weights), Some(policy_weights)) = (
-                weights_data["value_network"]["weights"].as_array(),
-                weights_data["policy_network"]["weights"].as_array(),
-            ) {
-                let value_weights: Vec<f32> = value_weights
-                    .iter()
-                    .filter_map(|w| w.as_f64().map(|x| x as f32))
-                    .collect();
-                let policy_weights: Vec<f32> = policy_weights
-                    .iter()
-                    .filter_map(|w| w.as_f64().map(|x| x as f32))
-                    .collect();
-
-                ai.load_weights(&value_weights, &policy_weights);
+    // Try to load existing weights
+    let mut ai = if Path::new(DEFAULT_MODEL_PATH).exists() {
+        match MLAI::load_model(DEFAULT_MODEL_PATH) {
+            Ok(ai) => {
                 println!("✅ Loaded existing weights for self-play");
+                ai
+            }
+            Err(e) => {
+                println!("⚠️  Ignoring saved model: {}", e);
+                MLAI::new()
             }
         }
-    }
+    } else {
+        MLAI::new()
+    };
 
     for game_idx in 0..num_games {
         if game_idx % 100 == 0 {
@@ -308,12 +491,11 @@ fn generate_self_play_data(
         let mut game_features = Vec::new();
         let mut game_values = Vec::new();
         let mut game_policies = Vec::new();
+        let mut game_movers = Vec::new();
 
         // Play the game
         while !game_state.is_game_over() {
-            let features =
-                connect_four_ai_core::features::GameFeatures::from_game_state(&game_state);
-            let features_array = features.to_array();
+            let features_array = ai.feature_set().encode(&game_state);
 
             // Get AI evaluation
             let response = ai.get_best_move(&game_state);
@@ -331,6 +513,7 @@ fn generate_self_play_data(
             game_features.push(features_array.to_vec());
             game_values.push(response.evaluation);
             game_policies.push(policy_target);
+            game_movers.push(game_state.current_player);
             game_moves.push(response.r#move);
 
             // Make the move
@@ -344,38 +527,147 @@ fn generate_self_play_data(
         }
 
         // Determine game result and create final targets
-        let game_result = if game_state.get_winner().is_some() {
-            if game_state.get_winner().unwrap() == connect_four_ai_core::Player::Player1 {
-                1.0 // Win
-            } else {
-                -1.0 // Loss
-            }
-        } else {
-            0.0 // Draw
+        let winner = game_state.get_winner();
+        let game_result: f32 = match winner {
+            Some(connect_four_ai_core::Player::Player1) => 1.0,
+            Some(connect_four_ai_core::Player::Player2) => -1.0,
+            None => 0.0, // Draw
         };
 
         // Create training samples from the game
-        for (i, ((features, value), policy)) in game_features
-            .iter()
-            .zip(game_values.iter())
-            .zip(game_policies.iter())
+        for (i, (((features, value), policy), mover)) in game_features
+            .into_iter()
+            .zip(game_values)
+            .zip(game_policies)
+            .zip(game_movers)
             .enumerate()
         {
             // Adjust value based on game result and move number
             let move_discount = 0.95_f32.powi(i as i32);
-            let adjusted_value = game_result * move_discount;
-
-            training_data.push(serde_json::json!({
-                "features": features,
-                "value_target": adjusted_value,
-                "policy_target": policy,
-                "game_idx": game_idx,
-                "move_idx": i,
-                "game_result": game_result,
-                "original_value": value
-            }));
+            let adjusted_value = ai.feature_set().value_target(winner, mover) * move_discount;
+
+            training_data.push(TrainingSample {
+                features,
+                policy_target: policy,
+                value_target: adjusted_value,
+                game_id: game_idx,
+                ply: i,
+                metadata: SampleMetadata {
+                    player: Some(mover),
+                    game_result: Some(game_result),
+                    original_value: Some(value),
+                },
+            });
+        }
+    }
+
+    let mut metadata = DatasetMetadata::new(ai.feature_set(), num_games);
+    metadata.version = Some("1.0".to_string());
+    metadata.extra.insert("depth".to_string(), depth.into());
+    Ok(Dataset {
+        metadata,
+        samples: training_data,
+    })
+}
+
+// MCTS self-play settings from `training_defaults`
+fn self_play_config(
+    defaults: &serde_json::Value,
+) -> Result<SelfPlayConfig, Box<dyn std::error::Error>> {
+    let rollout_policy = match defaults["mcts_rollout"].as_str() {
+        Some(name) => name.parse()?,
+        None => RolloutPolicy::ValueOnly,
+    };
+    let rave = match defaults["mcts_rave"].as_str() {
+        Some(name) => Some(name.parse()?),
+        None => None,
+    };
+    // Adjudication is on once either threshold is configured
+    let resign_threshold = defaults["resign_threshold"].as_f64().map(|t| t as f32);
+    let draw_threshold = defaults["draw_threshold"].as_f64().map(|t| t as f32);
+    let adjudication = (resign_threshold.is_some() || draw_threshold.is_some()).then(|| {
+        let fallback = Adjudication::default();
+        Adjudication {
+            resign_threshold,
+            draw_threshold,
+            draw_after: defaults["draw_after"]
+                .as_u64()
+                .map_or(fallback.draw_after, |n| n as usize),
+            audit_fraction: defaults["adjudication_audit_fraction"]
+                .as_f64()
+                .map_or(fallback.audit_fraction, |f| f as f32),
         }
+    });
+    Ok(SelfPlayConfig {
+        num_games: defaults["num_games"].as_u64().unwrap_or(1000) as usize,
+        mcts_simulations: defaults["mcts_simulations"].as_u64().unwrap_or(800) as usize,
+        leaf_batch_size: defaults["mcts_leaf_batch_size"].as_u64().unwrap_or(16) as usize,
+        rollout_policy,
+        rave,
+        temperature: defaults["temperature"].as_f64().unwrap_or(1.0) as f32,
+        temperature_moves: defaults["temperature_moves"].as_u64().unwrap_or(8) as usize,
+        adjudication,
+        save_every: defaults["checkpoint_every"].as_u64().unwrap_or(100) as usize,
+        seed: defaults["seed"].as_u64(),
+        ..SelfPlayConfig::default()
+    })
+}
+
+// Removes `flag` and the value after it from `args`, wherever they appear
+fn take_option(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    let Some(idx) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    if idx + 1 >= args.len() {
+        return Err(format!("{} needs a value", flag));
+    }
+    let value = args.remove(idx + 1);
+    args.remove(idx);
+    Ok(Some(value))
+}
+
+// Network training settings from `training_defaults`
+fn train_network_config(
+    defaults: &serde_json::Value,
+) -> Result<TrainNetworkConfig, Box<dyn std::error::Error>> {
+    let mut train_config = TrainNetworkConfig::default();
+    if let Some(epochs) = defaults["epochs"].as_u64() {
+        train_config.epochs = epochs as usize;
     }
+    if let Some(batch_size) = defaults["batch_size"].as_u64() {
+        train_config.batch_size = batch_size as usize;
+    }
+    if let Some(split) = defaults["validation_split"].as_f64() {
+        train_config.validation_split = split as f32;
+    }
+    if let Some(patience) = defaults["early_stopping_patience"].as_u64() {
+        train_config.early_stopping_patience = patience as usize;
+    }
+    if let Some(learning_rate) = defaults["learning_rate"].as_f64() {
+        train_config.optimizer = OptimizerConfig::adam(learning_rate as f32);
+    }
+    if let Some(schedule) = defaults.get("learning_rate_schedule") {
+        train_config.schedule = serde_json::from_value(schedule.clone())?;
+    }
+    if let Some(seed) = defaults["seed"].as_u64() {
+        train_config.seed = seed;
+    }
+    Ok(train_config)
+}
+
+fn hidden_sizes(config: &serde_json::Value) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    Ok(match config["network_architecture"].get("hidden_sizes") {
+        Some(sizes) => serde_json::from_value(sizes.clone())?,
+        None => DEFAULT_HIDDEN_SIZES.to_vec(),
+    })
+}
+
+fn samples_per_shard(defaults: &serde_json::Value) -> usize {
+    defaults["samples_per_shard"]
+        .as_u64()
+        .map_or(DEFAULT_SAMPLES_PER_SHARD, |v| v as usize)
+}
 
-    Ok(training_data)
+fn replay_shards(defaults: &serde_json::Value) -> usize {
+    defaults["replay_buffer_shards"].as_u64().unwrap_or(20) as usize
 }
@@ -0,0 +1,465 @@
+//! Training data written by self-play and read by the trainers.
+//!
+//! A file is `{"metadata": {...}, "training_data": [...]}` with one
+//! `TrainingSample` per position played. Sample keys keep the names earlier
+//! self-play files used, so those still load. Long runs write a
+//! `ShardWriter` directory instead, which is read back shard by shard.
+
+use crate::features::FeatureSet;
+use crate::{Player, COLS};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// One encoded position with the targets the networks learn from it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrainingSample {
+    pub features: Vec<f32>,
+    /// Visit distribution (or chosen move) over the columns.
+    pub policy_target: Vec<f32>,
+    /// Game outcome for the side the feature set scores positions for.
+    pub value_target: f32,
+    #[serde(rename = "game_idx")]
+    pub game_id: usize,
+    /// Moves played before this position.
+    #[serde(rename = "move_idx")]
+    pub ply: usize,
+    #[serde(flatten)]
+    pub metadata: SampleMetadata,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleMetadata {
+    /// Side to move.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<Player>,
+    /// Final result from Player1's view: 1 win, -1 loss, 0 draw.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_result: Option<f32>,
+    /// The generating AI's own evaluation of the position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_value: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature_set: Option<FeatureSet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature_version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_games: Option<usize>,
+    /// RFC 3339 in files this crate writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Generator settings, such as MCTS simulations or search depth.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl DatasetMetadata {
+    /// Metadata for samples encoded with `feature_set` now.
+    pub fn new(feature_set: FeatureSet, num_games: usize) -> Self {
+        DatasetMetadata {
+            feature_set: Some(feature_set),
+            feature_version: Some(feature_set.version()),
+            num_games: Some(num_games),
+            generated_at: Some(chrono::Utc::now().to_rfc3339()),
+            ..DatasetMetadata::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dataset {
+    #[serde(default)]
+    pub metadata: DatasetMetadata,
+    #[serde(rename = "training_data")]
+    pub samples: Vec<TrainingSample>,
+}
+
+impl Dataset {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json =
+            fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        Self::from_json(&json).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid training data: {}", e))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(path, self.to_json()).map_err(|e| format!("failed to write {}: {}", path, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The feature set the samples were encoded with. Files from before it
+    /// was recorded are matched to one by feature count; files recorded
+    /// with another version of their set are rejected.
+    pub fn feature_set(&self) -> Result<FeatureSet, String> {
+        let feature_set = match self.metadata.feature_set {
+            Some(set) => set,
+            None => {
+                let size = self.samples.first().map_or(0, |s| s.features.len());
+                FeatureSet::ALL
+                    .into_iter()
+                    .find(|set| set.size() == size)
+                    .ok_or(format!("no feature set has {} features", size))?
+            }
+        };
+
+        if let Some(version) = self.metadata.feature_version {
+            if version != feature_set.version() {
+                return Err(format!(
+                    "data was encoded with {} v{}, this build provides v{}",
+                    feature_set,
+                    version,
+                    feature_set.version()
+                ));
+            }
+        }
+        Ok(feature_set)
+    }
+
+    /// Checks every sample's shape against the dataset's feature set, which
+    /// it returns.
+    pub fn validate(&self) -> Result<FeatureSet, String> {
+        let feature_set = self.feature_set()?;
+        for (idx, sample) in self.samples.iter().enumerate() {
+            if sample.features.len() != feature_set.size() {
+                return Err(format!(
+                    "sample {} has {} features, {} expects {}",
+                    idx,
+                    sample.features.len(),
+                    feature_set,
+                    feature_set.size()
+                ));
+            }
+            if sample.policy_target.len() != COLS {
+                return Err(format!(
+                    "sample {} has {} policy_target values, expected {}",
+                    idx,
+                    sample.policy_target.len(),
+                    COLS
+                ));
+            }
+        }
+        Ok(feature_set)
+    }
+}
+
+/// Samples per shard when the config doesn't say.
+pub const DEFAULT_SAMPLES_PER_SHARD: usize = 10_000;
+
+const METADATA_FILE: &str = "metadata.json";
+const SHARD_PREFIX: &str = "shard-";
+const SHARD_EXTENSION: &str = "jsonl";
+const PARTIAL_EXTENSION: &str = "jsonl.partial";
+
+fn shard_index(path: &Path) -> Option<usize> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_prefix(SHARD_PREFIX)?;
+    stem.strip_suffix(&format!(".{}", SHARD_EXTENSION))?
+        .parse()
+        .ok()
+}
+
+/// Completed shards in `dir`, oldest first.
+pub fn shard_paths(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+    let mut shards: Vec<(usize, PathBuf)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| shard_index(&path).map(|idx| (idx, path)))
+        .collect();
+    shards.sort();
+    Ok(shards.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Streams the samples of one shard, one JSON line each.
+pub fn read_shard(
+    path: &Path,
+) -> Result<impl Iterator<Item = Result<TrainingSample, String>>, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    let name = path.display().to_string();
+    Ok(BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(move |(idx, line)| {
+            let line = line.map_err(|e| format!("{}: {}", name, e))?;
+            serde_json::from_str(&line).map_err(|e| format!("{} line {}: {}", name, idx + 1, e))
+        }))
+}
+
+/// Append-only self-play output: a directory holding `metadata.json` and
+/// numbered JSONL shards of at most `samples_per_shard` samples.
+///
+/// A shard is written under a `.partial` name and renamed once full or
+/// finished, so readers only ever see complete shards. Reopening a
+/// directory carries on after its last shard.
+pub struct ShardWriter {
+    dir: PathBuf,
+    samples_per_shard: usize,
+    next_index: usize,
+    current: Option<(BufWriter<File>, usize)>,
+}
+
+impl ShardWriter {
+    /// Opens `dir` for appending, creating it with `metadata` if needed.
+    /// Existing data must have been encoded with the same feature set.
+    pub fn create(
+        dir: &Path,
+        metadata: &DatasetMetadata,
+        samples_per_shard: usize,
+    ) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        let metadata_path = dir.join(METADATA_FILE);
+        if metadata_path.exists() {
+            let existing = ShardedDataset::open(dir)?.metadata;
+            if (existing.feature_set, existing.feature_version)
+                != (metadata.feature_set, metadata.feature_version)
+            {
+                return Err(format!(
+                    "{} holds {:?} v{:?} data, not {:?} v{:?}",
+                    dir.display(),
+                    existing.feature_set,
+                    existing.feature_version,
+                    metadata.feature_set,
+                    metadata.feature_version
+                ));
+            }
+        } else {
+            let json = serde_json::to_string_pretty(metadata).unwrap();
+            fs::write(&metadata_path, json)
+                .map_err(|e| format!("failed to write {}: {}", metadata_path.display(), e))?;
+        }
+
+        let next_index = shard_paths(dir)?
+            .last()
+            .and_then(|path| shard_index(path))
+            .map_or(0, |idx| idx + 1);
+        Ok(ShardWriter {
+            dir: dir.to_path_buf(),
+            samples_per_shard: samples_per_shard.max(1),
+            next_index,
+            current: None,
+        })
+    }
+
+    fn shard_path(&self, extension: &str) -> PathBuf {
+        self.dir.join(format!(
+            "{}{:06}.{}",
+            SHARD_PREFIX, self.next_index, extension
+        ))
+    }
+
+    pub fn write(&mut self, sample: &TrainingSample) -> Result<(), String> {
+        if self.current.is_none() {
+            let path = self.shard_path(PARTIAL_EXTENSION);
+            let file = File::create(&path)
+                .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+            self.current = Some((BufWriter::new(file), 0));
+        }
+        let (writer, count) = self.current.as_mut().unwrap();
+        serde_json::to_writer(&mut *writer, sample).map_err(|e| e.to_string())?;
+        writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        *count += 1;
+        if *count >= self.samples_per_shard {
+            self.close_shard()?;
+        }
+        Ok(())
+    }
+
+    pub fn write_all(&mut self, samples: &[TrainingSample]) -> Result<(), String> {
+        samples.iter().try_for_each(|sample| self.write(sample))
+    }
+
+    fn close_shard(&mut self) -> Result<(), String> {
+        if let Some((writer, _)) = self.current.take() {
+            writer
+                .into_inner()
+                .map_err(|e| e.to_string())?
+                .sync_all()
+                .map_err(|e| e.to_string())?;
+            let partial = self.shard_path(PARTIAL_EXTENSION);
+            let complete = self.shard_path(SHARD_EXTENSION);
+            fs::rename(&partial, &complete)
+                .map_err(|e| format!("failed to rename {}: {}", partial.display(), e))?;
+            self.next_index += 1;
+        }
+        Ok(())
+    }
+
+    /// Completes the shard in progress so everything written so far is in
+    /// complete shards. Returns how many complete shards `dir` holds.
+    pub fn flush(&mut self) -> Result<usize, String> {
+        self.close_shard()?;
+        Ok(self.next_index)
+    }
+
+    /// Deletes shards numbered `shards` and above, so writing carries on
+    /// from there. Used to drop data written after a checkpoint.
+    pub fn rewind(&mut self, shards: usize) -> Result<(), String> {
+        if self.current.is_some() {
+            return Err("cannot rewind with a shard in progress".to_string());
+        }
+        for path in shard_paths(&self.dir)? {
+            if shard_index(&path).is_some_and(|idx| idx >= shards) {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("failed to remove {}: {}", path.display(), e))?;
+            }
+        }
+        self.next_index = shards;
+        Ok(())
+    }
+
+    /// Completes the shard in progress, if any.
+    pub fn finish(mut self) -> Result<(), String> {
+        self.close_shard()
+    }
+}
+
+/// A shard directory read back without loading it all at once.
+#[derive(Clone, Debug)]
+pub struct ShardedDataset {
+    pub metadata: DatasetMetadata,
+    pub shards: Vec<PathBuf>,
+}
+
+impl ShardedDataset {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let metadata_path = dir.join(METADATA_FILE);
+        let json = fs::read_to_string(&metadata_path)
+            .map_err(|e| format!("failed to read {}: {}", metadata_path.display(), e))?;
+        let metadata = serde_json::from_str(&json)
+            .map_err(|e| format!("{}: invalid metadata: {}", metadata_path.display(), e))?;
+        Ok(ShardedDataset {
+            metadata,
+            shards: shard_paths(dir)?,
+        })
+    }
+
+    /// Every sample, shard by shard.
+    pub fn samples(&self) -> impl Iterator<Item = Result<TrainingSample, String>> + '_ {
+        self.shards.iter().flat_map(|path| match read_shard(path) {
+            Ok(samples) => Box::new(samples) as Box<dyn Iterator<Item = _>>,
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(ply: usize) -> TrainingSample {
+        TrainingSample {
+            features: vec![0.5; FeatureSet::RelativeFeatures.size()],
+            policy_target: vec![1.0 / COLS as f32; COLS],
+            value_target: -0.9,
+            game_id: 4,
+            ply,
+            metadata: SampleMetadata {
+                player: Some(Player::Player2),
+                game_result: Some(1.0),
+                original_value: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_dataset_round_trips_in_the_self_play_layout() {
+        let mut metadata = DatasetMetadata::new(FeatureSet::RelativeFeatures, 1);
+        metadata.extra.insert("mcts_simulations".into(), 50.into());
+        let dataset = Dataset {
+            metadata,
+            samples: vec![sample(0), sample(1)],
+        };
+        assert_eq!(dataset.validate(), Ok(FeatureSet::RelativeFeatures));
+
+        let json: Value = serde_json::from_str(&dataset.to_json()).unwrap();
+        assert_eq!(json["metadata"]["feature_set"], "relative_features");
+        assert_eq!(json["metadata"]["mcts_simulations"], 50);
+        let second = &json["training_data"][1];
+        assert_eq!(second["game_idx"], 4);
+        assert_eq!(second["move_idx"], 1);
+        assert_eq!(second["player"], "player2");
+        assert!(second.get("original_value").is_none());
+
+        assert_eq!(Dataset::from_json(&json.to_string()).unwrap(), dataset);
+    }
+
+    #[test]
+    fn test_malformed_datasets_are_rejected() {
+        let mut short = Dataset {
+            metadata: DatasetMetadata::new(FeatureSet::RelativeFeatures, 1),
+            samples: vec![sample(0), sample(1)],
+        };
+        short.samples[1].policy_target.pop();
+        assert!(short.validate().unwrap_err().contains("sample 1"));
+
+        let mut unlabelled = short.clone();
+        unlabelled.metadata = DatasetMetadata::default();
+        assert_eq!(unlabelled.feature_set(), Ok(FeatureSet::RelativeFeatures));
+
+        let error = Dataset::from_json(r#"{"training_data": [{"features": []}]}"#).unwrap_err();
+        assert!(error.contains("policy_target"), "{}", error);
+    }
+
+    #[test]
+    fn test_shards_are_only_visible_once_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = DatasetMetadata::new(FeatureSet::RelativeFeatures, 1);
+        let mut writer = ShardWriter::create(dir.path(), &metadata, 2).unwrap();
+        writer
+            .write_all(&[sample(0), sample(1), sample(2)])
+            .unwrap();
+        assert_eq!(shard_paths(dir.path()).unwrap().len(), 1);
+        writer.finish().unwrap();
+
+        let sharded = ShardedDataset::open(dir.path()).unwrap();
+        assert_eq!(sharded.metadata, metadata);
+        let plies: Vec<usize> = sharded.samples().map(|s| s.unwrap().ply).collect();
+        assert_eq!(plies, vec![0, 1, 2]);
+
+        let planes = DatasetMetadata::new(FeatureSet::BoardPlanes, 1);
+        assert!(ShardWriter::create(dir.path(), &planes, 2).is_err());
+
+        fs::write(&sharded.shards[1], "{\"features\": []}\n").unwrap();
+        let error = read_shard(&sharded.shards[1])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(error.contains("line 1"), "{}", error);
+
+        let mut reopened = ShardWriter::create(dir.path(), &metadata, 2).unwrap();
+        reopened.rewind(1).unwrap();
+        assert_eq!(shard_paths(dir.path()).unwrap().len(), 1);
+        reopened.write(&sample(3)).unwrap();
+        assert_eq!(reopened.flush(), Ok(2));
+        assert_eq!(shard_paths(dir.path()).unwrap().len(), 2);
+    }
+}
@@ -1,111 +1,199 @@
 use super::{Cell, GameState, Player, COLS, ROWS};
-use ndarray::Array1;
+use ndarray::{Array1, Array2, ArrayView1};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 pub const SIZE: usize = 100;
 
-#[derive(Clone, Debug)]
-pub struct GameFeatures {
-    pub features: [f32; 100],
-}
-
-impl GameFeatures {
-    pub fn from_game_state(state: &GameState) -> Self {
-        let mut features = [0.0; SIZE];
-        let mut idx = 0;
+/// Planes per cell in `BoardPlanes`.
+pub const PLANES: usize = 3;
+pub const PLANES_SIZE: usize = ROWS * COLS * PLANES;
+
+/// Slots of the handcrafted encoding that carry information: the board
+/// cells and 23 scores.
+pub const HANDCRAFTED_SIZE: usize = ROWS * COLS + 23;
+
+/// Turns a position into a network input. `name` identifies the encoding
+/// and `version` is bumped whenever its layout or meaning changes; model
+/// files record both so weights are never fed inputs they were not trained
+/// on.
+pub trait FeatureExtractor: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn version(&self) -> u32;
+    fn size(&self) -> usize;
+
+    /// Writes the features of `state` into `out`, which has `size()` slots.
+    fn extract(&self, state: &GameState, out: &mut [f32]);
+
+    fn encode(&self, state: &GameState) -> Array1<f32> {
+        let mut features = Array1::zeros(self.size());
+        self.extract(state, features.as_slice_mut().unwrap());
+        features
+    }
 
-        // Board occupancy (42 features - 6 rows × 7 columns)
-        for col in 0..COLS {
-            for row in 0..ROWS {
-                features[idx] = match state.board[col][row] {
-                    Cell::Empty => 0.0,
-                    Cell::Player1 => 1.0,
-                    Cell::Player2 => -1.0,
-                };
-                idx += 1;
-            }
+    /// Feature matrix for a batch of positions, one row per state.
+    fn encode_batch(&self, states: &[GameState]) -> Array2<f32> {
+        let mut batch = Array2::zeros((states.len(), self.size()));
+        for (mut row, state) in batch.rows_mut().into_iter().zip(states) {
+            self.extract(state, row.as_slice_mut().unwrap());
         }
+        batch
+    }
+}
 
-        // Strategic features
-        features[idx] = Self::center_control_score(state, Player::Player1) as f32;
-        idx += 1;
-
-        features[idx] = Self::center_control_score(state, Player::Player2) as f32;
-        idx += 1;
-
-        features[idx] = Self::pieces_count(state, Player::Player1) as f32;
-        idx += 1;
-
-        features[idx] = Self::pieces_count(state, Player::Player2) as f32;
-        idx += 1;
-
-        features[idx] = Self::threat_score(state, Player::Player1) as f32;
-        idx += 1;
-
-        features[idx] = Self::threat_score(state, Player::Player2) as f32;
-        idx += 1;
+/// `GameFeatures`: handcrafted features from Player1's point of view,
+/// clamped to [-10, 10] and padded to `SIZE`.
+pub struct HandcraftedExtractor;
 
-        features[idx] = Self::mobility_score(state, Player::Player1) as f32;
-        idx += 1;
+impl FeatureExtractor for HandcraftedExtractor {
+    fn name(&self) -> &'static str {
+        "game_features"
+    }
 
-        features[idx] = Self::mobility_score(state, Player::Player2) as f32;
-        idx += 1;
+    fn version(&self) -> u32 {
+        1
+    }
 
-        features[idx] = Self::vertical_control_score(state, Player::Player1) as f32;
-        idx += 1;
+    fn size(&self) -> usize {
+        SIZE
+    }
 
-        features[idx] = Self::vertical_control_score(state, Player::Player2) as f32;
-        idx += 1;
+    fn extract(&self, state: &GameState, out: &mut [f32]) {
+        handcrafted(state, Player::Player1, &mut out[..HANDCRAFTED_SIZE]);
+        for feature in &mut out[..HANDCRAFTED_SIZE] {
+            *feature = feature.clamp(-10.0, 10.0);
+        }
+        out[HANDCRAFTED_SIZE..].fill(0.0);
+    }
+}
 
-        features[idx] = Self::horizontal_control_score(state, Player::Player1) as f32;
-        idx += 1;
+/// `BoardPlanes`: the raw board as planes for convolutional networks.
+/// Version 2 networks score positions for the side to move.
+pub struct BoardPlaneExtractor;
 
-        features[idx] = Self::horizontal_control_score(state, Player::Player2) as f32;
-        idx += 1;
+impl FeatureExtractor for BoardPlaneExtractor {
+    fn name(&self) -> &'static str {
+        "board_planes"
+    }
 
-        features[idx] = Self::diagonal_control_score(state, Player::Player1) as f32;
-        idx += 1;
+    fn version(&self) -> u32 {
+        2
+    }
 
-        features[idx] = Self::diagonal_control_score(state, Player::Player2) as f32;
-        idx += 1;
+    fn size(&self) -> usize {
+        PLANES_SIZE
+    }
 
-        features[idx] = Self::blocking_score(state, Player::Player1) as f32;
-        idx += 1;
+    fn extract(&self, state: &GameState, out: &mut [f32]) {
+        board_planes(state, out);
+    }
+}
 
-        features[idx] = Self::blocking_score(state, Player::Player2) as f32;
-        idx += 1;
+/// The handcrafted features from the side to move's point of view, without
+/// padding: the same position with the colours swapped encodes the same.
+/// Version 2 leaves the scores unclamped for a `FeatureNormalizer` to scale.
+pub struct RelativeExtractor;
 
-        features[idx] = Self::height_advantage_score(state, Player::Player1) as f32;
-        idx += 1;
+impl FeatureExtractor for RelativeExtractor {
+    fn name(&self) -> &'static str {
+        "relative_features"
+    }
 
-        features[idx] = Self::height_advantage_score(state, Player::Player2) as f32;
-        idx += 1;
+    fn version(&self) -> u32 {
+        2
+    }
 
-        features[idx] = Self::material_balance_score(state) as f32;
-        idx += 1;
+    fn size(&self) -> usize {
+        HANDCRAFTED_SIZE
+    }
 
-        features[idx] = Self::positional_advantage_score(state, Player::Player1);
-        idx += 1;
+    fn extract(&self, state: &GameState, out: &mut [f32]) {
+        handcrafted(state, state.current_player, out);
+    }
+}
 
-        features[idx] = Self::positional_advantage_score(state, Player::Player2);
-        idx += 1;
+// The board, one slot per cell column by column from the top row, holding
+// 1.0 for `first`'s discs and -1.0 for its opponent's, then `first`'s score
+// and its opponent's for center control, pieces, threats, mobility,
+// vertical, horizontal and diagonal control, blocking and height, the
+// opponent's lead in pieces, and both sides' positional advantage and
+// endgame evaluation.
+fn handcrafted(state: &GameState, first: Player, out: &mut [f32]) {
+    let second = first.opponent();
+    let own = Cell::from_player(first);
+    for col in 0..COLS {
+        for row in 0..ROWS {
+            out[col * ROWS + row] = match state.board[col][row] {
+                Cell::Empty => 0.0,
+                cell if cell == own => 1.0,
+                _ => -1.0,
+            };
+        }
+    }
 
-        features[idx] = Self::endgame_evaluation(state, Player::Player1);
-        idx += 1;
+    let paired: [fn(&GameState, Player) -> i32; 9] = [
+        GameFeatures::center_control_score,
+        GameFeatures::pieces_count,
+        GameFeatures::threat_score,
+        GameFeatures::mobility_score,
+        GameFeatures::vertical_control_score,
+        GameFeatures::horizontal_control_score,
+        GameFeatures::diagonal_control_score,
+        GameFeatures::blocking_score,
+        GameFeatures::height_advantage_score,
+    ];
+    let mut scores = Vec::with_capacity(HANDCRAFTED_SIZE - ROWS * COLS);
+    for score in paired {
+        scores.push(score(state, first) as f32);
+        scores.push(score(state, second) as f32);
+    }
+    scores.push(GameFeatures::material_balance_score(state, first) as f32);
+    for score in [
+        GameFeatures::positional_advantage_score,
+        GameFeatures::endgame_evaluation,
+    ] {
+        scores.push(score(state, first));
+        scores.push(score(state, second));
+    }
 
-        features[idx] = Self::endgame_evaluation(state, Player::Player2);
-        idx += 1;
+    out[ROWS * COLS..].copy_from_slice(&scores);
+}
 
-        // Fill remaining features with zeros
-        while idx < SIZE {
-            features[idx] = 0.0;
-            idx += 1;
+// See `BoardPlanes`
+fn board_planes(state: &GameState, out: &mut [f32]) {
+    let own = Cell::from_player(state.current_player);
+    let opponent = Cell::from_player(state.current_player.opponent());
+    let to_move = if state.current_player == Player::Player1 {
+        1.0
+    } else {
+        0.0
+    };
+
+    for row in 0..ROWS {
+        for col in 0..COLS {
+            let idx = (row * COLS + col) * PLANES;
+            let cell = state.board[col][row];
+            out[idx] = if cell == own { 1.0 } else { 0.0 };
+            out[idx + 1] = if cell == opponent { 1.0 } else { 0.0 };
+            out[idx + 2] = to_move;
         }
+    }
+}
 
-        // Normalize features to ensure they're in reasonable bounds
-        for i in 0..SIZE {
-            features[i] = features[i].max(-10.0).min(10.0);
-        }
+/// The handcrafted encoding from Player1's point of view: Player1's discs
+/// are 1.0 and Player2's -1.0, and each per-player score is Player1's then
+/// Player2's. Only the first `HANDCRAFTED_SIZE` slots are used; the zero
+/// padding up to `SIZE` stays so version 1 weights keep loading.
+#[derive(Clone, Debug)]
+pub struct GameFeatures {
+    pub features: [f32; 100],
+}
 
+impl GameFeatures {
+    pub fn from_game_state(state: &GameState) -> Self {
+        let mut features = [0.0; SIZE];
+        HandcraftedExtractor.extract(state, &mut features);
         GameFeatures { features }
     }
 
@@ -113,6 +201,15 @@ impl GameFeatures {
         Array1::from_vec(self.features.to_vec())
     }
 
+    /// Feature matrix for a batch of positions, one row per state.
+    pub fn batch_array(states: &[GameState]) -> Array2<f32> {
+        let mut batch = Array2::zeros((states.len(), SIZE));
+        for (mut row, state) in batch.rows_mut().into_iter().zip(states) {
+            row.assign(&ArrayView1::from(&Self::from_game_state(state).features));
+        }
+        batch
+    }
+
     fn pieces_count(state: &GameState, player: Player) -> i32 {
         let mut count = 0;
         for col in 0..COLS {
@@ -316,10 +413,8 @@ impl GameFeatures {
         score
     }
 
-    fn material_balance_score(state: &GameState) -> i32 {
-        let p1_pieces = Self::pieces_count(state, Player::Player1);
-        let p2_pieces = Self::pieces_count(state, Player::Player2);
-        p2_pieces - p1_pieces
+    fn material_balance_score(state: &GameState, first: Player) -> i32 {
+        Self::pieces_count(state, first.opponent()) - Self::pieces_count(state, first)
     }
 
     fn positional_advantage_score(state: &GameState, player: Player) -> f32 {
@@ -347,6 +442,120 @@ impl GameFeatures {
     }
 }
 
+/// The board as stacked planes for convolutional networks: the side to
+/// move's discs, the opponent's discs, and a constant plane that is 1 when
+/// Player1 is to move. Laid out cell by cell (row 0 first, then by column)
+/// with the planes innermost, as `ConvNetwork` expects.
+#[derive(Clone, Debug)]
+pub struct BoardPlanes {
+    pub planes: [f32; PLANES_SIZE],
+}
+
+impl BoardPlanes {
+    pub fn from_game_state(state: &GameState) -> Self {
+        let mut planes = [0.0; PLANES_SIZE];
+        board_planes(state, &mut planes);
+        BoardPlanes { planes }
+    }
+
+    pub fn to_array(&self) -> Array1<f32> {
+        Array1::from_vec(self.planes.to_vec())
+    }
+
+    /// Planes for a batch of positions, one row per state.
+    pub fn batch_array(states: &[GameState]) -> Array2<f32> {
+        let mut batch = Array2::zeros((states.len(), PLANES_SIZE));
+        for (mut row, state) in batch.rows_mut().into_iter().zip(states) {
+            row.assign(&ArrayView1::from(&Self::from_game_state(state).planes));
+        }
+        batch
+    }
+}
+
+/// The encodings a model can be trained on, by name. Model files and
+/// self-play data store the name; `extractor` gives the implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureSet {
+    GameFeatures,
+    BoardPlanes,
+    RelativeFeatures,
+}
+
+impl FeatureSet {
+    pub const ALL: [FeatureSet; 3] = [
+        FeatureSet::GameFeatures,
+        FeatureSet::BoardPlanes,
+        FeatureSet::RelativeFeatures,
+    ];
+
+    pub fn extractor(self) -> &'static dyn FeatureExtractor {
+        match self {
+            FeatureSet::GameFeatures => &HandcraftedExtractor,
+            FeatureSet::BoardPlanes => &BoardPlaneExtractor,
+            FeatureSet::RelativeFeatures => &RelativeExtractor,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.extractor().name()
+    }
+
+    pub fn version(self) -> u32 {
+        self.extractor().version()
+    }
+
+    pub fn size(self) -> usize {
+        self.extractor().size()
+    }
+
+    pub fn encode(self, state: &GameState) -> Array1<f32> {
+        self.extractor().encode(state)
+    }
+
+    pub fn encode_batch(self, states: &[GameState]) -> Array2<f32> {
+        self.extractor().encode_batch(states)
+    }
+
+    /// The player a value network over this encoding scores positions for
+    /// when `mover` is to move. `GameFeatures` does not show whose turn it
+    /// is, so its networks score everything for Player1; the other encodings
+    /// score for the side to move.
+    pub fn value_perspective(self, mover: Player) -> Player {
+        match self {
+            FeatureSet::GameFeatures => Player::Player1,
+            FeatureSet::BoardPlanes | FeatureSet::RelativeFeatures => mover,
+        }
+    }
+
+    /// Value target for a position with `mover` to move in a game `winner`
+    /// won, `None` being a draw.
+    pub fn value_target(self, winner: Option<Player>, mover: Player) -> f32 {
+        match winner {
+            Some(winner) if winner == self.value_perspective(mover) => 1.0,
+            Some(_) => -1.0,
+            None => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FeatureSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FeatureSet::ALL
+            .into_iter()
+            .find(|set| set.name() == s)
+            .ok_or_else(|| format!("unknown feature set {:?}", s))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -445,4 +654,105 @@ mod tests {
             assert!(!feature.is_infinite(), "Feature {} is infinite", i);
         }
     }
+
+    #[test]
+    fn test_batch_array_rows_match_single_features() {
+        let mut first = GameState::new();
+        first.make_move(3).unwrap();
+        let mut second = first.clone();
+        second.make_move(0).unwrap();
+        let states = [first, second];
+
+        let batch = GameFeatures::batch_array(&states);
+        assert_eq!(batch.shape(), &[2, SIZE]);
+        for (row, state) in batch.rows().into_iter().zip(&states) {
+            assert_eq!(row.to_vec(), GameFeatures::from_game_state(state).features.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_board_planes_are_relative_to_side_to_move() {
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        state.make_move(3).unwrap();
+        let bottom = ROWS - 1;
+        let idx = (bottom * COLS + 3) * PLANES;
+
+        // Player2 to move: the disc in column 3 belongs to the opponent
+        let planes = BoardPlanes::from_game_state(&state).planes;
+        assert_eq!(&planes[idx..idx + PLANES], &[0.0, 1.0, 0.0]);
+        assert_eq!(planes.iter().filter(|&&p| p != 0.0).count(), 1);
+
+        state.make_move(0).unwrap();
+        let planes = BoardPlanes::from_game_state(&state).planes;
+        assert_eq!(&planes[idx..idx + PLANES], &[1.0, 0.0, 1.0]);
+        assert_eq!(planes[(bottom * COLS) * PLANES + 1], 1.0);
+
+        let batch = BoardPlanes::batch_array(&[state.clone()]);
+        assert_eq!(batch.row(0).to_vec(), planes.to_vec());
+    }
+
+    #[test]
+    fn test_feature_sets_are_named_by_their_extractor() {
+        let state = GameState::new();
+        for set in FeatureSet::ALL {
+            assert_eq!(serde_json::to_value(set).unwrap(), set.name());
+            assert_eq!(set.name().parse::<FeatureSet>(), Ok(set));
+            assert_eq!(set.encode(&state).len(), set.size());
+            assert_eq!(set.encode_batch(&[state.clone()]).ncols(), set.size());
+        }
+        assert!("raw_pixels".parse::<FeatureSet>().is_err());
+    }
+
+    #[test]
+    fn test_relative_features_do_not_depend_on_colour() {
+        let play = |first| {
+            let mut state = GameState::new();
+            state.current_player = first;
+            for col in [3, 3, 4, 2, 4] {
+                state.make_move(col).unwrap();
+            }
+            state
+        };
+        let (red, yellow) = (play(Player::Player1), play(Player::Player2));
+
+        let relative = FeatureSet::RelativeFeatures;
+        assert_eq!(relative.encode(&red), relative.encode(&yellow));
+        assert_ne!(
+            GameFeatures::from_game_state(&red).to_array(),
+            GameFeatures::from_game_state(&yellow).to_array()
+        );
+
+        // The handcrafted slots of GameFeatures are the relative encoding,
+        // clamped, whenever Player1 is to move; the rest is padding
+        let state = yellow;
+        assert_eq!(state.current_player, Player::Player1);
+        let absolute = GameFeatures::from_game_state(&state).features;
+        let raw = relative.encode(&state);
+        assert!(raw.iter().any(|f| f.abs() > 10.0));
+        assert_eq!(
+            absolute[..HANDCRAFTED_SIZE].to_vec(),
+            raw.mapv(|f| f.clamp(-10.0, 10.0)).to_vec()
+        );
+        assert!(absolute[HANDCRAFTED_SIZE..].iter().all(|&f| f == 0.0));
+    }
+
+    #[test]
+    fn test_value_targets_follow_the_value_perspective() {
+        use Player::{Player1, Player2};
+        let game_features = FeatureSet::GameFeatures;
+        assert_eq!(game_features.value_target(Some(Player1), Player2), 1.0);
+        assert_eq!(
+            FeatureSet::RelativeFeatures.value_target(Some(Player1), Player2),
+            -1.0
+        );
+        assert_eq!(
+            FeatureSet::BoardPlanes.value_target(Some(Player2), Player2),
+            1.0
+        );
+        assert_eq!(
+            FeatureSet::RelativeFeatures.value_target(None, Player1),
+            0.0
+        );
+    }
 }
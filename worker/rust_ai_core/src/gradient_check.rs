@@ -0,0 +1,456 @@
+//! Finite-difference verification of backpropagation.
+//!
+//! `check_gradients` nudges each trainable weight up and down, measures the
+//! change in loss, and compares that slope with the analytic gradient from
+//! the network's backward pass. `check_descent` takes one small optimizer
+//! step and confirms the loss falls. New layer types, output heads and
+//! optimizers should pass both.
+//!
+//! ReLU and masking make the loss only piecewise smooth: pick weights and
+//! inputs that keep units away from zero pre-activations, or a step may
+//! cross a kink and report a spurious mismatch.
+
+use crate::neural_network::{ConvNetwork, Network, NeuralNetwork};
+use crate::optimizer::{Gradients, Optimizer, OptimizerConfig};
+use ndarray::Array2;
+
+/// A network whose trainable weights can be read, replaced and
+/// differentiated. Weights are in the order `Gradients` flattens to.
+pub trait GradientCheckable {
+    fn trainable_weights(&self) -> Vec<f32>;
+
+    fn set_trainable_weights(&mut self, weights: &[f32]);
+
+    /// Mean loss and its gradients over the batch, masked when
+    /// `legal_moves` is given.
+    fn loss_and_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients);
+
+    fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer);
+}
+
+impl GradientCheckable for NeuralNetwork {
+    fn trainable_weights(&self) -> Vec<f32> {
+        self.get_weights()
+    }
+
+    fn set_trainable_weights(&mut self, weights: &[f32]) {
+        self.load_weights(weights)
+            .expect("trainable weights match the network");
+    }
+
+    fn loss_and_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients) {
+        match legal_moves {
+            Some(legal_moves) => self.compute_masked_gradients(inputs, targets, legal_moves),
+            None => self.compute_gradients(inputs, targets),
+        }
+    }
+
+    fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+        NeuralNetwork::apply_gradients(self, gradients, optimizer);
+    }
+}
+
+// Batch-norm running statistics follow the trainable weights in
+// `get_weights`; they are carried through unchanged
+impl GradientCheckable for ConvNetwork {
+    fn trainable_weights(&self) -> Vec<f32> {
+        let mut weights = self.get_weights();
+        weights.truncate(self.trainable_weight_count());
+        weights
+    }
+
+    fn set_trainable_weights(&mut self, weights: &[f32]) {
+        let mut all = self.get_weights();
+        all[..weights.len()].copy_from_slice(weights);
+        self.load_weights(&all);
+    }
+
+    fn loss_and_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients) {
+        match legal_moves {
+            Some(legal_moves) => self.compute_masked_gradients(inputs, targets, legal_moves),
+            None => self.compute_gradients(inputs, targets),
+        }
+    }
+
+    fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+        ConvNetwork::apply_gradients(self, gradients, optimizer);
+    }
+}
+
+impl GradientCheckable for Network {
+    fn trainable_weights(&self) -> Vec<f32> {
+        match self {
+            Network::Dense(network) => network.trainable_weights(),
+            Network::Conv(network) => network.trainable_weights(),
+        }
+    }
+
+    fn set_trainable_weights(&mut self, weights: &[f32]) {
+        match self {
+            Network::Dense(network) => network.set_trainable_weights(weights),
+            Network::Conv(network) => network.set_trainable_weights(weights),
+        }
+    }
+
+    fn loss_and_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients) {
+        match self {
+            Network::Dense(network) => network.loss_and_gradients(inputs, targets, legal_moves),
+            Network::Conv(network) => network.loss_and_gradients(inputs, targets, legal_moves),
+        }
+    }
+
+    fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+        match self {
+            Network::Dense(network) => {
+                GradientCheckable::apply_gradients(network, gradients, optimizer)
+            }
+            Network::Conv(network) => {
+                GradientCheckable::apply_gradients(network.as_mut(), gradients, optimizer)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientCheckOptions {
+    /// Step for the central difference `(L(w + e) - L(w - e)) / 2e`.
+    pub epsilon: f32,
+    /// Allowed `|numeric - analytic|` relative to `1 + |numeric|`.
+    pub tolerance: f32,
+    /// Check only this many weights, evenly spread, for large networks.
+    pub max_weights: Option<usize>,
+}
+
+impl Default for GradientCheckOptions {
+    fn default() -> Self {
+        GradientCheckOptions {
+            epsilon: 1e-2,
+            tolerance: 1e-2,
+            max_weights: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradientMismatch {
+    pub index: usize,
+    pub analytic: f32,
+    pub numeric: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GradientCheckReport {
+    pub checked: usize,
+    /// Largest `|numeric - analytic| / (1 + |numeric|)` seen.
+    pub max_error: f32,
+    pub mismatches: Vec<GradientMismatch>,
+}
+
+impl GradientCheckReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Compares the network's analytic gradients with central differences of
+/// its loss. The network's weights are restored afterwards.
+pub fn check_gradients<N: GradientCheckable>(
+    network: &mut N,
+    inputs: &Array2<f32>,
+    targets: &Array2<f32>,
+    legal_moves: Option<&Array2<bool>>,
+    options: &GradientCheckOptions,
+) -> GradientCheckReport {
+    let weights = network.trainable_weights();
+    let (_, gradients) = network.loss_and_gradients(inputs, targets, legal_moves);
+    let analytic: Vec<f32> = gradients
+        .layers
+        .iter()
+        .flat_map(|(w, b)| w.iter().chain(b.iter()).copied().collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        analytic.len(),
+        weights.len(),
+        "gradients do not cover the trainable weights"
+    );
+
+    let stride = options
+        .max_weights
+        .map_or(1, |max| weights.len().div_ceil(max.max(1)));
+    let mut report = GradientCheckReport::default();
+    let mut shifted = weights.clone();
+    for index in (0..weights.len()).step_by(stride) {
+        shifted[index] = weights[index] + options.epsilon;
+        network.set_trainable_weights(&shifted);
+        let (loss_up, _) = network.loss_and_gradients(inputs, targets, legal_moves);
+        shifted[index] = weights[index] - options.epsilon;
+        network.set_trainable_weights(&shifted);
+        let (loss_down, _) = network.loss_and_gradients(inputs, targets, legal_moves);
+        shifted[index] = weights[index];
+
+        let numeric = (loss_up - loss_down) / (2.0 * options.epsilon);
+        let error = (numeric - analytic[index]).abs() / (1.0 + numeric.abs());
+        report.checked += 1;
+        report.max_error = report.max_error.max(error);
+        if error > options.tolerance {
+            report.mismatches.push(GradientMismatch {
+                index,
+                analytic: analytic[index],
+                numeric,
+            });
+        }
+    }
+
+    network.set_trainable_weights(&weights);
+    report
+}
+
+/// Loss before and after one step of `optimizer` from the current weights;
+/// with a small enough learning rate the second should be lower. The
+/// network's weights are restored afterwards.
+pub fn check_descent<N: GradientCheckable>(
+    network: &mut N,
+    inputs: &Array2<f32>,
+    targets: &Array2<f32>,
+    legal_moves: Option<&Array2<bool>>,
+    optimizer: OptimizerConfig,
+) -> (f32, f32) {
+    let weights = network.trainable_weights();
+    let (before, gradients) = network.loss_and_gradients(inputs, targets, legal_moves);
+    network.apply_gradients(&gradients, &mut Optimizer::new(optimizer));
+    let (after, _) = network.loss_and_gradients(inputs, targets, legal_moves);
+    network.set_trainable_weights(&weights);
+    (before, after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neural_network::{Architecture, ConvNetworkConfig, NetworkConfig, OutputActivation};
+
+    fn dense(output_size: usize, output_activation: OutputActivation) -> NeuralNetwork {
+        let config = NetworkConfig {
+            input_size: 3,
+            hidden_sizes: vec![4, 3],
+            output_size,
+            output_activation,
+        };
+        let mut network = NeuralNetwork::new(config.clone());
+        let weights: Vec<f32> = (0..config.total_weights())
+            // Offset so no hidden unit sits within epsilon of the ReLU kink
+            .map(|i| ((i * 7 % 11) as f32 - 5.3) * 0.1)
+            .collect();
+        network.load_weights(&weights).unwrap();
+        network
+    }
+
+    fn inputs() -> Array2<f32> {
+        Array2::from_shape_vec(
+            (3, 3),
+            vec![0.5, -1.0, 0.3, 1.5, 0.25, -0.4, -0.7, 0.9, 1.1],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dense_heads_pass_gradient_check() {
+        let heads = [
+            (OutputActivation::Tanh, 1, vec![-0.5, 0.3, 0.9]),
+            (
+                OutputActivation::Linear,
+                2,
+                vec![-2.0, 1.5, 0.0, 0.4, 3.0, -1.0],
+            ),
+            (
+                OutputActivation::Softmax,
+                4,
+                vec![
+                    0.0, 1.0, 0.0, 0.0, 0.2, 0.3, 0.5, 0.0, 0.25, 0.25, 0.25, 0.25,
+                ],
+            ),
+        ];
+        for (output_activation, outputs, targets) in heads {
+            let mut network = dense(outputs, output_activation);
+            let targets = Array2::from_shape_vec((3, outputs), targets).unwrap();
+            let report = check_gradients(
+                &mut network,
+                &inputs(),
+                &targets,
+                None,
+                &GradientCheckOptions::default(),
+            );
+            assert_eq!(report.checked, network.get_weights().len());
+            assert!(report.passed(), "{:?}: {:?}", output_activation, report);
+        }
+    }
+
+    #[test]
+    fn test_masked_softmax_passes_gradient_check() {
+        let mut network = dense(4, OutputActivation::Softmax);
+        // Target mass on a masked move is constant and must not break the check
+        let targets = Array2::from_shape_vec(
+            (3, 4),
+            vec![0.0, 0.6, 0.0, 0.4, 0.5, 0.5, 0.0, 0.0, 0.1, 0.2, 0.3, 0.4],
+        )
+        .unwrap();
+        let legal = Array2::from_shape_vec(
+            (3, 4),
+            vec![
+                true, true, false, true, true, false, true, true, false, false, false, false,
+            ],
+        )
+        .unwrap();
+        let report = check_gradients(
+            &mut network,
+            &inputs(),
+            &targets,
+            Some(&legal),
+            &GradientCheckOptions::default(),
+        );
+        assert!(report.passed(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_softmax_loss_is_exact_for_confident_mistakes() {
+        // Logits far enough apart that the target move's probability
+        // underflows; a clamped log would flatten the loss here
+        let mut network = NeuralNetwork::new(NetworkConfig {
+            input_size: 1,
+            hidden_sizes: vec![1],
+            output_size: 2,
+            output_activation: OutputActivation::Softmax,
+        });
+        network
+            .load_weights(&[1.0, 0.0, 40.0, -40.0, 0.0, 0.0])
+            .unwrap();
+        let inputs = Array2::from_elem((1, 1), 1.0);
+        let targets = Array2::from_shape_vec((1, 2), vec![0.0, 1.0]).unwrap();
+
+        assert!((network.loss(&inputs, &targets) - 80.0).abs() < 1e-3);
+        let report = check_gradients(
+            &mut network,
+            &inputs,
+            &targets,
+            None,
+            &GradientCheckOptions::default(),
+        );
+        assert!(report.passed(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_conv_network_passes_gradient_check() {
+        let config = ConvNetworkConfig {
+            input_channels: 2,
+            rows: 3,
+            cols: 4,
+            filters: 2,
+            kernel_size: 3,
+            residual_blocks: 1,
+            batch_norm: true,
+            head_channels: 1,
+            head_hidden_size: 3,
+            output_size: 4,
+            output_activation: OutputActivation::Softmax,
+        };
+        let mut network = Network::new(&Architecture::Conv(config));
+        let weights: Vec<f32> = (0..network.trainable_weights().len())
+            .map(|i| ((i * 7 % 13) as f32 - 5.7) * 0.08)
+            .collect();
+        network.set_trainable_weights(&weights);
+
+        let inputs =
+            Array2::from_shape_fn((3, 24), |(b, i)| ((b * 5 + i * 7) % 4) as f32 * 0.5 - 0.6);
+        let targets = Array2::from_shape_fn((3, 4), |(b, j)| if b == j { 1.0 } else { 0.0 });
+        // A batch-norm scale shifts a whole channel, so smaller steps keep
+        // cells off their ReLU kinks
+        let options = GradientCheckOptions {
+            epsilon: 3e-3,
+            tolerance: 2e-2,
+            max_weights: Some(60),
+        };
+        let report = check_gradients(&mut network, &inputs, &targets, None, &options);
+        assert!(report.checked <= 60 && report.checked > 30);
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(network.trainable_weights(), weights);
+    }
+
+    #[test]
+    fn test_every_optimizer_descends() {
+        let targets = Array2::from_shape_vec((3, 1), vec![-0.5, 0.3, 0.9]).unwrap();
+        for optimizer in [
+            OptimizerConfig::sgd(0.01, 0.9),
+            OptimizerConfig::adam(0.001),
+            OptimizerConfig::adamw(0.001, 0.01),
+        ] {
+            let mut network = dense(1, OutputActivation::Tanh);
+            let weights = network.get_weights();
+            let (before, after) = check_descent(&mut network, &inputs(), &targets, None, optimizer);
+            assert!(after < before, "{:?}: {} -> {}", optimizer, before, after);
+            assert_eq!(network.get_weights(), weights);
+        }
+    }
+
+    // A network whose backward pass gets one weight's gradient wrong
+    struct Broken(NeuralNetwork);
+
+    impl GradientCheckable for Broken {
+        fn trainable_weights(&self) -> Vec<f32> {
+            self.0.trainable_weights()
+        }
+
+        fn set_trainable_weights(&mut self, weights: &[f32]) {
+            self.0.set_trainable_weights(weights);
+        }
+
+        fn loss_and_gradients(
+            &self,
+            inputs: &Array2<f32>,
+            targets: &Array2<f32>,
+            legal_moves: Option<&Array2<bool>>,
+        ) -> (f32, Gradients) {
+            let (loss, mut gradients) = self.0.loss_and_gradients(inputs, targets, legal_moves);
+            gradients.layers[0].1[2] += 0.5;
+            (loss, gradients)
+        }
+
+        fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+            GradientCheckable::apply_gradients(&mut self.0, gradients, optimizer);
+        }
+    }
+
+    #[test]
+    fn test_gradient_check_catches_wrong_gradients() {
+        let mut network = Broken(dense(1, OutputActivation::Tanh));
+        let targets = Array2::from_shape_vec((3, 1), vec![-0.5, 0.3, 0.9]).unwrap();
+        let report = check_gradients(
+            &mut network,
+            &inputs(),
+            &targets,
+            None,
+            &GradientCheckOptions::default(),
+        );
+        // First layer biases follow its 3 x 4 weights
+        assert_eq!(report.mismatches.len(), 1, "{:?}", report);
+        assert_eq!(report.mismatches[0].index, 14);
+    }
+}
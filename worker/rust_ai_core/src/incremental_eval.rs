@@ -0,0 +1,340 @@
+//! Incrementally maintained heuristic evaluation for minimax search.
+//!
+//! `GameState::evaluate` rescans all 42 cells for every feature, several times
+//! over for threats and mobility. `IncrementalEval` keeps the per-cell terms as
+//! running totals and caches the per-column and per-row features, so a move
+//! only recomputes the windows it can actually influence.
+
+use crate::genetic_params::GeneticParams;
+use crate::{Cell, GameState, Player, COLS, ROWS};
+
+/// Raw per-player feature totals, indexed by `Player as usize`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct FeatureTotals {
+    pub center_control: [i32; 2],
+    pub pieces: [i32; 2],
+    pub threat: [i32; 2],
+    pub mobility: [i32; 2],
+    pub vertical: [i32; 2],
+    pub horizontal: [i32; 2],
+    pub defensive: [i32; 2],
+}
+
+impl FeatureTotals {
+    /// Combines the features with the genetic weights, Player1 positive.
+    pub(crate) fn weighted(&self, params: &GeneticParams) -> i32 {
+        let terms = [
+            (self.center_control, params.center_control_weight),
+            (self.threat, params.threat_weight),
+            (self.pieces, params.piece_count_weight),
+            (self.mobility, params.mobility_weight),
+            (self.vertical, params.vertical_control_weight),
+            (self.horizontal, params.horizontal_control_weight),
+            (self.defensive, params.defensive_weight),
+        ];
+
+        terms
+            .iter()
+            .map(|(values, weight)| {
+                let weight = *weight as i32;
+                values[0] * weight - values[1] * weight
+            })
+            .sum()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IncrementalEval {
+    position: i32,
+    center_control: [i32; 2],
+    pieces: [i32; 2],
+    vertical: [[i32; 2]; COLS],
+    horizontal: [[i32; 2]; ROWS],
+    threat: [[i32; 2]; COLS],
+    defensive: [[i32; 2]; COLS],
+    winner: Option<Player>,
+}
+
+impl IncrementalEval {
+    pub fn new(state: &GameState) -> Self {
+        let mut eval = IncrementalEval {
+            position: 0,
+            center_control: [0; 2],
+            pieces: [0; 2],
+            vertical: [[0; 2]; COLS],
+            horizontal: [[0; 2]; ROWS],
+            threat: [[0; 2]; COLS],
+            defensive: [[0; 2]; COLS],
+            winner: state.get_winner(),
+        };
+
+        for col in 0..COLS {
+            for row in 0..ROWS {
+                if let Some(player) = state.board[col][row].to_player() {
+                    eval.add_piece(state, col, row, player);
+                }
+            }
+            eval.refresh_column(state, col);
+        }
+        for row in 0..ROWS {
+            eval.refresh_row(state, row);
+        }
+
+        eval
+    }
+
+    /// Updates the cached terms for the piece just dropped in `col`.
+    /// `state` must be the position after the move.
+    pub fn apply_move(&mut self, state: &GameState, col: u8) {
+        let col = col as usize;
+        let Some(row) = (0..ROWS).find(|&row| state.board[col][row] != Cell::Empty) else {
+            return;
+        };
+        let Some(player) = state.board[col][row].to_player() else {
+            return;
+        };
+
+        self.add_piece(state, col, row, player);
+        self.refresh_row(state, row);
+        self.refresh_column(state, col);
+
+        if self.winner.is_none() && state.check_win_at(col, row, player) {
+            self.winner = Some(player);
+        }
+
+        // Another column's threat and defensive scores only change if their
+        // scans reach the new piece
+        for other in (0..COLS).filter(|&other| other != col) {
+            if state.board[other][0] != Cell::Empty {
+                continue;
+            }
+            let other_row = GameState::lowest_empty_row_in(&state.board, other);
+            for scanned in [Player::Player1, Player::Player2] {
+                if scan_reaches(&state.board, (other, other_row), (col, row), scanned) {
+                    let defender = scanned.opponent();
+                    self.threat[other][scanned as usize] =
+                        state.column_threat_score(&state.board, other, scanned);
+                    self.defensive[other][defender as usize] =
+                        state.column_defensive_score(&state.board, other, defender);
+                }
+            }
+        }
+    }
+
+    /// Whether the position is won or the board is full.
+    pub fn is_game_over(&self) -> bool {
+        self.winner.is_some() || self.is_full()
+    }
+
+    fn is_full(&self) -> bool {
+        self.pieces[0] + self.pieces[1] == (ROWS * COLS) as i32
+    }
+
+    /// Same result as `state.evaluate()` for the position this was built for.
+    pub fn evaluate(&self, state: &GameState) -> i32 {
+        if let Some(winner) = self.winner {
+            return match winner {
+                Player::Player1 => 10000,
+                Player::Player2 => -10000,
+            };
+        }
+
+        if self.is_full() {
+            return 0;
+        }
+
+        self.position + self.feature_totals(state).weighted(&state.genetic_params)
+    }
+
+    pub(crate) fn feature_totals(&self, state: &GameState) -> FeatureTotals {
+        let sum_columns = |values: &[[i32; 2]]| {
+            values
+                .iter()
+                .fold([0; 2], |acc, v| [acc[0] + v[0], acc[1] + v[1]])
+        };
+
+        FeatureTotals {
+            center_control: self.center_control,
+            pieces: self.pieces,
+            threat: sum_columns(&self.threat),
+            mobility: self.mobility_scores(state),
+            vertical: sum_columns(&self.vertical),
+            horizontal: sum_columns(&self.horizontal),
+            defensive: sum_columns(&self.defensive),
+        }
+    }
+
+    fn add_piece(&mut self, state: &GameState, col: usize, row: usize, player: Player) {
+        let idx = player as usize;
+        let value = state.cell_position_value(col, row);
+        match player {
+            Player::Player1 => self.position += value,
+            Player::Player2 => self.position -= value,
+        }
+        if (2..=4).contains(&col) {
+            self.center_control[idx] += (ROWS - row) as i32;
+        }
+        self.pieces[idx] += 1;
+    }
+
+    fn refresh_column(&mut self, state: &GameState, col: usize) {
+        for player in [Player::Player1, Player::Player2] {
+            let idx = player as usize;
+            self.vertical[col][idx] = GameState::column_run_score(&state.board, col, player);
+            self.threat[col][idx] = state.column_threat_score(&state.board, col, player);
+            self.defensive[col][idx] = state.column_defensive_score(&state.board, col, player);
+        }
+    }
+
+    fn refresh_row(&mut self, state: &GameState, row: usize) {
+        for player in [Player::Player1, Player::Player2] {
+            self.horizontal[row][player as usize] =
+                GameState::row_run_score(&state.board, row, player);
+        }
+    }
+
+    // Mobility replays every legal move of the side to move and rescores the
+    // threats. Besides the dropped-in column, only columns whose threat scans
+    // reach the hypothetical piece need recomputing.
+    fn mobility_scores(&self, state: &GameState) -> [i32; 2] {
+        if self.pieces[0] + self.pieces[1] == 0 {
+            return [0; 2];
+        }
+
+        let drop_rows: [Option<usize>; COLS] = std::array::from_fn(|col| {
+            (state.board[col][0] == Cell::Empty)
+                .then(|| GameState::lowest_empty_row_in(&state.board, col))
+        });
+        let base_threat = self
+            .threat
+            .iter()
+            .fold([0; 2], |acc, t| [acc[0] + t[0], acc[1] + t[1]]);
+        let mut mobility = [0; 2];
+
+        for (col, row) in drop_rows
+            .iter()
+            .enumerate()
+            .filter_map(|(col, row)| row.map(|row| (col, row)))
+        {
+            let mut test_board = state.board;
+            test_board[col][row] = Cell::from_player(state.current_player);
+
+            for player in [Player::Player1, Player::Player2] {
+                let idx = player as usize;
+                let mut threat = base_threat[idx] - self.threat[col][idx]
+                    + state.column_threat_score(&test_board, col, player);
+                for (other, other_row) in drop_rows
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(other, row)| row.map(|row| (other, row)))
+                {
+                    if other != col
+                        && scan_reaches(&state.board, (other, other_row), (col, row), player)
+                    {
+                        threat -= self.threat[other][idx];
+                        threat += state.column_threat_score(&test_board, other, player);
+                    }
+                }
+                mobility[idx] += threat / 10; // Normalize
+            }
+        }
+
+        mobility
+    }
+}
+
+// Whether scoring `player`'s drop at `from` can look at the cell `to`. Threat
+// and win scans run along rows and diagonals (columns stay in their own
+// column) and stop at the first cell that isn't the player's.
+fn scan_reaches(
+    board: &[[Cell; ROWS]; COLS],
+    from: (usize, usize),
+    to: (usize, usize),
+    player: Player,
+) -> bool {
+    let dcol = to.0 as i32 - from.0 as i32;
+    let drow = to.1 as i32 - from.1 as i32;
+    if dcol == 0 || (drow != 0 && drow.abs() != dcol.abs()) {
+        return false;
+    }
+    let cell = Cell::from_player(player);
+    (1..dcol.abs()).all(|step| {
+        let col = from.0 as i32 + step * dcol.signum();
+        let row = from.1 as i32 + step * drow.signum();
+        board[col as usize][row as usize] == cell
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn assert_matches_full_recompute(state: &GameState, eval: &IncrementalEval) {
+        assert_eq!(
+            eval.evaluate(state),
+            state.evaluate(),
+            "incremental evaluation diverged on board {:?}",
+            state.board
+        );
+        assert_eq!(
+            eval.evaluate(state),
+            IncrementalEval::new(state).evaluate(state)
+        );
+    }
+
+    #[test]
+    fn test_empty_board_matches_full_evaluation() {
+        let state = GameState::new();
+        let eval = IncrementalEval::new(&state);
+        assert_matches_full_recompute(&state, &eval);
+    }
+
+    #[test]
+    fn test_random_games_match_full_evaluation() {
+        let mut rng = rand::thread_rng();
+
+        for game in 0..200 {
+            let mut state = if game % 2 == 0 {
+                GameState::new()
+            } else {
+                GameState::with_genetic_params(GeneticParams::random())
+            };
+            let mut eval = IncrementalEval::new(&state);
+
+            while !state.is_game_over() {
+                let moves = state.get_valid_moves();
+                let col = moves[rng.gen_range(0..moves.len())];
+                state.make_move(col).unwrap();
+                eval.apply_move(&state, col);
+                assert_matches_full_recompute(&state, &eval);
+            }
+        }
+    }
+
+    #[test]
+    fn test_feature_totals_match_game_state_helpers() {
+        let mut state = GameState::new();
+        for col in [3, 3, 2, 4, 4, 5, 1, 0] {
+            state.make_move(col).unwrap();
+        }
+
+        let totals = IncrementalEval::new(&state).feature_totals(&state);
+        for player in [Player::Player1, Player::Player2] {
+            let idx = player as usize;
+            assert_eq!(
+                totals.center_control[idx],
+                state.center_control_score(player)
+            );
+            assert_eq!(totals.pieces[idx], state.pieces_count(player));
+            assert_eq!(totals.threat[idx], state.threat_score(player));
+            assert_eq!(totals.mobility[idx], state.mobility_score(player));
+            assert_eq!(totals.vertical[idx], state.vertical_control_score(player));
+            assert_eq!(
+                totals.horizontal[idx],
+                state.horizontal_control_score(player)
+            );
+            assert_eq!(totals.defensive[idx], state.defensive_score(player));
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use crate::genetic_params::GeneticParams;
+use crate::incremental_eval::{FeatureTotals, IncrementalEval};
+use crate::search_tree::{SearchTreeNode, TreeExportOptions, TreeRecorder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::hash::{DefaultHasher, Hash, Hasher};
@@ -6,12 +8,23 @@ use std::hash::{DefaultHasher, Hash, Hasher};
 #[cfg(feature = "wasm")]
 pub mod wasm_api;
 
+pub mod dataset;
 pub mod features;
 pub mod genetic_params;
+pub mod gradient_check;
+pub mod incremental_eval;
 pub mod mcts;
 pub mod ml_ai;
+pub mod model;
 pub mod neural_network;
+pub mod normalization;
+pub mod optimizer;
+pub mod pipeline;
+pub mod quantization;
+pub mod replay_buffer;
+pub mod search_tree;
 pub mod self_play;
+pub mod supervised;
 pub mod training;
 
 pub const ROWS: usize = 6;
@@ -182,9 +195,23 @@ impl GameState {
         Ok(())
     }
 
+    // Takes back the top piece of `col`, for searches that explore moves in
+    // place rather than on copies
+    pub(crate) fn undo_move(&mut self, col: u8) {
+        let col = col as usize;
+        if let Some(row) = (0..ROWS).find(|&row| self.board[col][row] != Cell::Empty) {
+            self.board[col][row] = Cell::Empty;
+            self.current_player = self.current_player.opponent();
+        }
+    }
+
     fn get_lowest_empty_row(&self, col: usize) -> usize {
+        Self::lowest_empty_row_in(&self.board, col)
+    }
+
+    fn lowest_empty_row_in(board: &[[Cell; ROWS]; COLS], col: usize) -> usize {
         for row in (0..ROWS).rev() {
-            if self.board[col][row] == Cell::Empty {
+            if board[col][row] == Cell::Empty {
                 return row;
             }
         }
@@ -278,86 +305,65 @@ impl GameState {
 
         // Position evaluation using genetic parameters
         for col in 0..COLS {
-            let column_value = match col {
-                3 => self.genetic_params.center_column_value, // Center column
-                2 | 4 => self.genetic_params.adjacent_center_value, // Adjacent to center
-                1 | 5 => self.genetic_params.outer_column_value, // Further from center
-                0 | 6 => self.genetic_params.edge_column_value, // Edge columns
-                _ => self.genetic_params.edge_column_value,
-            };
-
             for row in 0..ROWS {
                 match self.board[col][row] {
-                    Cell::Player1 => {
-                        score += (column_value as f64
-                            * (ROWS - row) as f64
-                            * self.genetic_params.row_height_weight)
-                            as i32;
-                    }
-                    Cell::Player2 => {
-                        score -= (column_value as f64
-                            * (ROWS - row) as f64
-                            * self.genetic_params.row_height_weight)
-                            as i32;
-                    }
+                    Cell::Player1 => score += self.cell_position_value(col, row),
+                    Cell::Player2 => score -= self.cell_position_value(col, row),
                     Cell::Empty => {}
                 }
             }
         }
 
-        // Center control bonus using genetic parameters
-        let center_control_p1 = self.center_control_score(Player::Player1);
-        let center_control_p2 = self.center_control_score(Player::Player2);
-        let center_weight = self.genetic_params.center_control_weight as i32;
-        score += center_control_p1 * center_weight;
-        score -= center_control_p2 * center_weight;
-
-        // Threat detection using genetic parameters
-        let threat_p1 = self.threat_score(Player::Player1);
-        let threat_p2 = self.threat_score(Player::Player2);
-        let threat_weight = self.genetic_params.threat_weight as i32;
-        score += threat_p1 * threat_weight;
-        score -= threat_p2 * threat_weight;
-
-        // Piece count evaluation using genetic parameters
-        let piece_count_p1 = self.pieces_count(Player::Player1);
-        let piece_count_p2 = self.pieces_count(Player::Player2);
-        let piece_weight = self.genetic_params.piece_count_weight as i32;
-        score += piece_count_p1 * piece_weight;
-        score -= piece_count_p2 * piece_weight;
-
-        // Mobility evaluation using genetic parameters
-        let mobility_p1 = self.mobility_score(Player::Player1);
-        let mobility_p2 = self.mobility_score(Player::Player2);
-        let mobility_weight = self.genetic_params.mobility_weight as i32;
-        score += mobility_p1 * mobility_weight;
-        score -= mobility_p2 * mobility_weight;
-
-        // Vertical control evaluation using genetic parameters
-        let vertical_p1 = self.vertical_control_score(Player::Player1);
-        let vertical_p2 = self.vertical_control_score(Player::Player2);
-        let vertical_weight = self.genetic_params.vertical_control_weight as i32;
-        score += vertical_p1 * vertical_weight;
-        score -= vertical_p2 * vertical_weight;
-
-        // Horizontal control evaluation using genetic parameters
-        let horizontal_p1 = self.horizontal_control_score(Player::Player1);
-        let horizontal_p2 = self.horizontal_control_score(Player::Player2);
-        let horizontal_weight = self.genetic_params.horizontal_control_weight as i32;
-        score += horizontal_p1 * horizontal_weight;
-        score -= horizontal_p2 * horizontal_weight;
-
-        // Defensive evaluation using genetic parameters
-        let defensive_p1 = self.defensive_score(Player::Player1);
-        let defensive_p2 = self.defensive_score(Player::Player2);
-        let defensive_weight = self.genetic_params.defensive_weight as i32;
-        score += defensive_p1 * defensive_weight;
-        score -= defensive_p2 * defensive_weight;
+        // Feature weights (center control, threats, piece count, mobility,
+        // vertical/horizontal control, defence) using genetic parameters
+        let totals = FeatureTotals {
+            center_control: [
+                self.center_control_score(Player::Player1),
+                self.center_control_score(Player::Player2),
+            ],
+            pieces: [
+                self.pieces_count(Player::Player1),
+                self.pieces_count(Player::Player2),
+            ],
+            threat: [
+                self.threat_score(Player::Player1),
+                self.threat_score(Player::Player2),
+            ],
+            mobility: [
+                self.mobility_score(Player::Player1),
+                self.mobility_score(Player::Player2),
+            ],
+            vertical: [
+                self.vertical_control_score(Player::Player1),
+                self.vertical_control_score(Player::Player2),
+            ],
+            horizontal: [
+                self.horizontal_control_score(Player::Player1),
+                self.horizontal_control_score(Player::Player2),
+            ],
+            defensive: [
+                self.defensive_score(Player::Player1),
+                self.defensive_score(Player::Player2),
+            ],
+        };
+        score += totals.weighted(&self.genetic_params);
 
         // Evaluation is always from Player1's perspective (positive = Player1 advantage)
         score
     }
 
+    fn cell_position_value(&self, col: usize, row: usize) -> i32 {
+        let column_value = match col {
+            3 => self.genetic_params.center_column_value, // Center column
+            2 | 4 => self.genetic_params.adjacent_center_value, // Adjacent to center
+            1 | 5 => self.genetic_params.outer_column_value, // Further from center
+            0 | 6 => self.genetic_params.edge_column_value, // Edge columns
+            _ => self.genetic_params.edge_column_value,
+        };
+
+        (column_value as f64 * (ROWS - row) as f64 * self.genetic_params.row_height_weight) as i32
+    }
+
     pub fn position_score(&self, player: Player) -> i32 {
         let mut score = 0;
 
@@ -413,26 +419,54 @@ impl GameState {
 
         // Check for immediate winning threats in valid moves only
         for col in 0..COLS {
-            if self.can_place_in_column(col) {
-                let row = self.get_lowest_empty_row(col);
-                if row < ROWS {
-                    // Test if placing a piece here would create a win
-                    let mut test_board = self.board;
-                    test_board[col][row] = Cell::from_player(player);
-
-                    // Check if this creates a win
-                    if self.check_win_at_test(&test_board, col, row, player) {
-                        score += 10000; // Immediate win threat - much higher priority
+            score += self.column_threat_score(&self.board, col, player);
+        }
+
+        score
+    }
+
+    fn column_threat_score(&self, board: &[[Cell; ROWS]; COLS], col: usize, player: Player) -> i32 {
+        if board[col][0] != Cell::Empty {
+            return 0;
+        }
+
+        let row = Self::lowest_empty_row_in(board, col);
+        let piece = Cell::from_player(player);
+
+        // Scores the drop as `count_threats_at` would after placing it, walking
+        // each line once; the line through a win would count 4 or more
+        let mut threats = 0;
+        for (dcol, drow) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+            let mut consecutive = 1;
+            let mut blocked = 0;
+            for sign in [1, -1] {
+                let mut c = col as i32 + sign * dcol;
+                let mut r = row as i32 + sign * drow;
+                while c >= 0 && c < COLS as i32 && r >= 0 && r < ROWS as i32 {
+                    let cell = board[c as usize][r as usize];
+                    if cell == piece {
+                        consecutive += 1;
+                        c += sign * dcol;
+                        r += sign * drow;
                     } else {
-                        // Check for 3-in-a-row threats
-                        let threat_value = self.count_threats_at(&test_board, col, row, player);
-                        score += threat_value;
+                        if cell != Cell::Empty {
+                            blocked += 1;
+                        }
+                        break;
                     }
                 }
             }
+
+            threats += match (consecutive, blocked) {
+                (4.., _) => return 10000, // Immediate win threat - much higher priority
+                (3, 0) => 100,
+                (3, _) | (2, 0) => 10,
+                (2, _) | (1, 0) => 1,
+                _ => 0,
+            };
         }
 
-        score
+        threats
     }
 
     fn count_threats_at(
@@ -578,68 +612,87 @@ impl GameState {
     }
 
     pub fn vertical_control_score(&self, player: Player) -> i32 {
+        (0..COLS)
+            .map(|col| Self::column_run_score(&self.board, col, player))
+            .sum()
+    }
+
+    fn column_run_score(board: &[[Cell; ROWS]; COLS], col: usize, player: Player) -> i32 {
         let mut score = 0;
-        for col in 0..COLS {
-            let mut consecutive = 0;
-            for row in 0..ROWS {
-                if self.board[col][row] == Cell::from_player(player) {
-                    consecutive += 1;
-                } else {
-                    consecutive = 0;
-                }
-                score += consecutive;
+        let mut consecutive = 0;
+        for &cell in &board[col] {
+            if cell == Cell::from_player(player) {
+                consecutive += 1;
+            } else {
+                consecutive = 0;
             }
+            score += consecutive;
         }
         score
     }
 
     pub fn horizontal_control_score(&self, player: Player) -> i32 {
+        (0..ROWS)
+            .map(|row| Self::row_run_score(&self.board, row, player))
+            .sum()
+    }
+
+    fn row_run_score(board: &[[Cell; ROWS]; COLS], row: usize, player: Player) -> i32 {
         let mut score = 0;
-        for row in 0..ROWS {
-            let mut consecutive = 0;
-            for col in 0..COLS {
-                if self.board[col][row] == Cell::from_player(player) {
-                    consecutive += 1;
-                } else {
-                    consecutive = 0;
-                }
-                score += consecutive;
+        let mut consecutive = 0;
+        for column in board {
+            if column[row] == Cell::from_player(player) {
+                consecutive += 1;
+            } else {
+                consecutive = 0;
             }
+            score += consecutive;
         }
         score
     }
 
     pub fn defensive_score(&self, player: Player) -> i32 {
-        let opponent = player.opponent();
         let mut defensive_score = 0;
 
         // Check each column for defensive opportunities
         for col in 0..COLS {
-            if self.can_place_in_column(col) {
-                let row = self.get_lowest_empty_row(col);
+            defensive_score += self.column_defensive_score(&self.board, col, player);
+        }
 
-                // Test if placing a piece here would block an opponent threat
-                let mut test_board = self.board;
-                test_board[col][row] = Cell::from_player(player);
+        defensive_score
+    }
 
-                // Check if this blocks an opponent's winning move
-                if self.check_win_at_test(&test_board, col, row, opponent) {
-                    defensive_score += 5000; // High value for blocking opponent win
-                } else {
-                    // Check if this blocks opponent's 3-in-a-row threat
-                    let opponent_threat_before =
-                        self.count_threats_at(&self.board, col, row, opponent);
-                    let opponent_threat_after =
-                        self.count_threats_at(&test_board, col, row, opponent);
-
-                    if opponent_threat_after < opponent_threat_before {
-                        defensive_score += (opponent_threat_before - opponent_threat_after) * 100;
-                    }
-                }
-            }
+    fn column_defensive_score(
+        &self,
+        board: &[[Cell; ROWS]; COLS],
+        col: usize,
+        player: Player,
+    ) -> i32 {
+        if board[col][0] != Cell::Empty {
+            return 0;
         }
 
-        defensive_score
+        let opponent = player.opponent();
+        let row = Self::lowest_empty_row_in(board, col);
+
+        // Test if placing a piece here would block an opponent threat
+        let mut test_board = *board;
+        test_board[col][row] = Cell::from_player(player);
+
+        // Check if this blocks an opponent's winning move
+        if self.check_win_at_test(&test_board, col, row, opponent) {
+            5000 // High value for blocking opponent win
+        } else {
+            // Check if this blocks opponent's 3-in-a-row threat
+            let opponent_threat_before = self.count_threats_at(board, col, row, opponent);
+            let opponent_threat_after = self.count_threats_at(&test_board, col, row, opponent);
+
+            if opponent_threat_after < opponent_threat_before {
+                (opponent_threat_before - opponent_threat_after) * 100
+            } else {
+                0
+            }
+        }
     }
 }
 
@@ -653,6 +706,7 @@ pub struct AI {
     transposition_table: HashMap<u64, TranspositionEntry>,
     pub nodes_evaluated: u32,
     pub transposition_hits: u32,
+    tree_recorder: Option<TreeRecorder>,
 }
 
 pub struct HeuristicAI {
@@ -665,9 +719,21 @@ impl AI {
             transposition_table: HashMap::new(),
             nodes_evaluated: 0,
             transposition_hits: 0,
+            tree_recorder: None,
         }
     }
 
+    /// Records the tree explored by each following `get_best_move` call,
+    /// down to `options.max_depth` plies.
+    pub fn record_search_tree(&mut self, options: TreeExportOptions) {
+        self.tree_recorder = Some(TreeRecorder::new(options));
+    }
+
+    /// The tree recorded by the last `get_best_move`, if recording is on.
+    pub fn take_search_tree(&mut self) -> Option<SearchTreeNode> {
+        self.tree_recorder.as_mut()?.take_root()
+    }
+
     pub fn get_transposition_table_size(&self) -> usize {
         self.transposition_table.len()
     }
@@ -683,6 +749,9 @@ impl AI {
     ) -> (Option<u8>, Vec<MoveEvaluation>) {
         self.nodes_evaluated = 0;
         self.transposition_hits = 0;
+        if let Some(recorder) = self.tree_recorder.as_mut() {
+            recorder.start(state);
+        }
 
         let valid_moves = state.get_valid_moves();
 
@@ -761,10 +830,20 @@ impl AI {
             f32::INFINITY
         };
 
+        let root_eval = IncrementalEval::new(state);
+
         for &col in &valid_moves {
             let mut next_state = state.clone();
             if next_state.make_move(col).is_ok() {
-                let score = self.minimax(&next_state, depth - 1, f32::NEG_INFINITY, f32::INFINITY);
+                let mut next_eval = root_eval.clone();
+                next_eval.apply_move(&next_state, col);
+                let score = self.minimax(
+                    &mut next_state,
+                    &next_eval,
+                    depth - 1,
+                    f32::NEG_INFINITY,
+                    f32::INFINITY,
+                );
 
                 move_evaluations.push(MoveEvaluation {
                     column: col,
@@ -813,10 +892,56 @@ impl AI {
             );
         }
 
+        if let Some(recorder) = self.tree_recorder.as_mut() {
+            recorder.finish(best_move.map(|_| best_score));
+        }
+
         (best_move, move_evaluations)
     }
 
-    fn minimax(&mut self, state: &GameState, depth: u8, alpha: f32, beta: f32) -> f32 {
+    fn minimax(
+        &mut self,
+        state: &mut GameState,
+        eval: &IncrementalEval,
+        depth: u8,
+        alpha: f32,
+        beta: f32,
+    ) -> f32 {
+        if self.tree_recorder.is_none() {
+            return self.minimax_node(state, eval, depth, alpha, beta);
+        }
+
+        if let Some(recorder) = self.tree_recorder.as_mut() {
+            recorder.enter(state, alpha, beta);
+        }
+        let hits_before = self.transposition_hits;
+        let nodes_before = self.nodes_evaluated;
+
+        let score = self.minimax_node(state, eval, depth, alpha, beta);
+
+        // Only a node that expanded counts towards nodes_evaluated, and one
+        // answered from the table did nothing but bump the hit counter
+        let expanded = self.nodes_evaluated > nodes_before;
+        let cache_hit = !expanded && self.transposition_hits == hits_before + 1;
+        let move_count = if expanded {
+            state.get_valid_moves().len()
+        } else {
+            0
+        };
+        if let Some(recorder) = self.tree_recorder.as_mut() {
+            recorder.exit(score, cache_hit, move_count);
+        }
+        score
+    }
+
+    fn minimax_node(
+        &mut self,
+        state: &mut GameState,
+        eval: &IncrementalEval,
+        depth: u8,
+        alpha: f32,
+        beta: f32,
+    ) -> f32 {
         let state_hash = state.hash();
 
         if let Some(entry) = self.transposition_table.get(&state_hash) {
@@ -827,13 +952,13 @@ impl AI {
         }
 
         if depth == 0 {
-            let eval = state.evaluate() as f32;
+            let score = eval.evaluate(state) as f32;
             // The evaluation is always from Player1's perspective
             // We need to adjust it based on the current player
             let adjusted_eval = if state.current_player == Player::Player1 {
-                eval
+                score
             } else {
-                -eval
+                -score
             };
             self.transposition_table.insert(
                 state_hash,
@@ -846,14 +971,14 @@ impl AI {
             return adjusted_eval;
         }
 
-        if state.is_game_over() {
-            let eval = state.evaluate() as f32;
+        if eval.is_game_over() {
+            let score = eval.evaluate(state) as f32;
             // The evaluation is always from Player1's perspective
             // We need to adjust it based on the current player
             let adjusted_eval = if state.current_player == Player::Player1 {
-                eval
+                score
             } else {
-                -eval
+                -score
             };
             self.transposition_table.insert(
                 state_hash,
@@ -883,10 +1008,13 @@ impl AI {
         let mut alpha = alpha;
         let mut beta = beta;
 
+        // Children are explored in place and taken back before pruning
         for &col in &valid_moves {
-            let mut next_state = state.clone();
-            if next_state.make_move(col).is_ok() {
-                let score = self.minimax(&next_state, depth - 1, alpha, beta);
+            if state.make_move(col).is_ok() {
+                let mut next_eval = eval.clone();
+                next_eval.apply_move(state, col);
+                let score = self.minimax(state, &next_eval, depth - 1, alpha, beta);
+                state.undo_move(col);
 
                 if is_maximizing {
                     best_score = best_score.max(score);
@@ -1197,4 +1325,43 @@ mod tests {
                                       // The AI might find a different winning sequence, so just check it's a valid move
         assert!(state.get_valid_moves().contains(&best_move.unwrap()));
     }
+
+    #[test]
+    fn test_ai_records_search_tree() {
+        let mut ai = AI::new();
+        ai.record_search_tree(TreeExportOptions {
+            max_depth: 2,
+            min_visits: 0,
+        });
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        state.make_move(3).unwrap();
+
+        let (best_move, evaluations) = ai.get_best_move(&state, 4);
+        let tree = ai.take_search_tree().unwrap();
+
+        assert!(tree.mv.is_none());
+        assert_eq!(tree.children.len(), evaluations.len());
+        assert!(tree.children.iter().any(|child| child.mv == best_move));
+        for child in &tree.children {
+            let evaluation = evaluations
+                .iter()
+                .find(|e| Some(e.column) == child.mv)
+                .unwrap();
+            assert_eq!(child.score, Some(evaluation.score));
+            assert_eq!(child.alpha, Some(f32::NEG_INFINITY));
+            for grandchild in &child.children {
+                assert_eq!(grandchild.depth, 2);
+                assert!(grandchild.children.is_empty());
+            }
+        }
+        assert!(ai.take_search_tree().is_none());
+    }
+
+    #[test]
+    fn test_ai_without_recording_has_no_tree() {
+        let mut ai = AI::new();
+        ai.get_best_move(&GameState::new(), 2);
+        assert!(ai.take_search_tree().is_none());
+    }
 }
@@ -1,4 +1,6 @@
-use connect_four_ai_core::{GameState, AI};
+use connect_four_ai_core::mcts::{RaveSchedule, RolloutPolicy, SearchLimits, MCTS};
+use connect_four_ai_core::search_tree::{SearchTreeNode, TreeExportOptions};
+use connect_four_ai_core::{GameState, Player, AI, COLS};
 use std::env;
 use std::fs;
 use std::process;
@@ -7,18 +9,65 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
         eprintln!(
-            "Usage: {} <get_move|evaluate> <input_file> [--depth N]",
+            "Usage: {} <get_move|evaluate|mcts> <input_file> [--depth N] [--simulations N] [--threads N] [--time-ms N] [--rollout POLICY] [--rave SCHEDULE] [--tree-json FILE] [--tree-dot FILE] [--tree-depth N] [--tree-min-visits N]",
             args[0]
         );
         process::exit(1);
     }
 
     let mut depth = 3u8;
+    let mut simulations = 10_000usize;
+    let mut threads = 0usize;
+    let mut time_ms = None;
+    let mut rollout_policy = RolloutPolicy::default();
+    let mut rave: Option<RaveSchedule> = None;
+    let mut tree_json = None;
+    let mut tree_dot = None;
+    let mut tree_options = TreeExportOptions::default();
     let mut i = 3;
     while i < args.len() {
         if args[i] == "--depth" && i + 1 < args.len() {
             depth = args[i + 1].parse().unwrap_or(3);
             i += 2;
+        } else if args[i] == "--simulations" && i + 1 < args.len() {
+            simulations = args[i + 1].parse().unwrap_or(10_000);
+            i += 2;
+        } else if args[i] == "--threads" && i + 1 < args.len() {
+            threads = args[i + 1].parse().unwrap_or(0);
+            i += 2;
+        } else if args[i] == "--time-ms" && i + 1 < args.len() {
+            time_ms = args[i + 1].parse().ok();
+            i += 2;
+        } else if args[i] == "--rollout" && i + 1 < args.len() {
+            rollout_policy = match args[i + 1].parse() {
+                Ok(policy) => policy,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+            i += 2;
+        } else if args[i] == "--rave" && i + 1 < args.len() {
+            rave = match args[i + 1].parse() {
+                Ok(schedule) => Some(schedule),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+            i += 2;
+        } else if args[i] == "--tree-json" && i + 1 < args.len() {
+            tree_json = Some(args[i + 1].clone());
+            i += 2;
+        } else if args[i] == "--tree-dot" && i + 1 < args.len() {
+            tree_dot = Some(args[i + 1].clone());
+            i += 2;
+        } else if args[i] == "--tree-depth" && i + 1 < args.len() {
+            tree_options.max_depth = args[i + 1].parse().unwrap_or(3);
+            i += 2;
+        } else if args[i] == "--tree-min-visits" && i + 1 < args.len() {
+            tree_options.min_visits = args[i + 1].parse().unwrap_or(1);
+            i += 2;
         } else {
             i += 1;
         }
@@ -46,7 +95,13 @@ fn main() {
     match command.as_str() {
         "get_move" => {
             let mut ai = AI::new();
+            if tree_json.is_some() || tree_dot.is_some() {
+                ai.record_search_tree(tree_options);
+            }
             let (best_move, move_evaluations) = ai.get_best_move(&game_state, depth);
+            if let Some(tree) = ai.take_search_tree() {
+                write_tree(&tree, tree_json.as_deref(), tree_dot.as_deref());
+            }
             let evaluation = game_state.evaluate();
             let response = serde_json::json!({
                 "move": best_move,
@@ -55,6 +110,40 @@ fn main() {
             });
             println!("{}", serde_json::to_string(&response).unwrap());
         }
+        "mcts" => {
+            let limits = SearchLimits {
+                time_limit: time_ms.map(std::time::Duration::from_millis),
+                ..SearchLimits::default()
+            };
+            let mut mcts = MCTS::with_limits(1.0, simulations, limits);
+            mcts.rollout_policy = rollout_policy;
+            mcts.rave = rave;
+            // Static evaluation scored for the side to move
+            let value_fn = |state: &GameState| {
+                let value = (state.evaluate() as f32 / 10000.0).clamp(-1.0, 1.0);
+                match state.current_player {
+                    Player::Player1 => value,
+                    Player::Player2 => -value,
+                }
+            };
+            let policy_fn = |_state: &GameState| vec![1.0 / COLS as f32; COLS];
+            let result = mcts.search_parallel(game_state, &value_fn, &policy_fn, threads);
+            if let Some(tree) = mcts.export_tree(&tree_options) {
+                write_tree(&tree, tree_json.as_deref(), tree_dot.as_deref());
+            }
+            let response = serde_json::json!({
+                "move": result.best_move,
+                "moveProbabilities": result.move_probabilities,
+                "visitCounts": result.visit_counts,
+                "moveValues": result.move_values,
+                "rootValue": result.root_value,
+                "provenValue": result.proven_value,
+                "simulations": result.simulations,
+                "elapsedMs": result.elapsed.as_millis() as u64,
+                "stopReason": format!("{:?}", result.stop_reason)
+            });
+            println!("{}", serde_json::to_string(&response).unwrap());
+        }
         "evaluate" => {
             let evaluation = game_state.evaluate();
             println!("{}", evaluation);
@@ -65,3 +154,15 @@ fn main() {
         }
     }
 }
+
+fn write_tree(tree: &SearchTreeNode, json_file: Option<&str>, dot_file: Option<&str>) {
+    let outputs = [(json_file, tree.to_json()), (dot_file, tree.to_dot())];
+    for (file, contents) in outputs {
+        if let Some(file) = file {
+            if let Err(e) = fs::write(file, contents) {
+                eprintln!("Failed to write search tree to {}: {}", file, e);
+                process::exit(1);
+            }
+        }
+    }
+}
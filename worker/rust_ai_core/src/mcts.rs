@@ -1,6 +1,29 @@
+use crate::search_tree::{SearchTreeNode, TreeExportOptions};
 use crate::{GameState, Player, COLS};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
 use std::f32;
+use std::str::FromStr;
+use std::time::Duration;
+
+// How many plies `advance_to` will look below the root for a matching position
+const MAX_REROOT_DEPTH: usize = 4;
+
+const MAX_ROLLOUT_DEPTH: usize = 20;
+const DEFAULT_TRUNCATED_DEPTH: usize = 6;
+// Heuristic scores of this size map to roughly ±0.76 after tanh
+const STATIC_EVAL_SCALE: f32 = 1000.0;
+const DEFAULT_RAVE_EQUIVALENCE: f32 = 300.0;
+const DEFAULT_RAVE_BIAS: f32 = 0.1;
+
+// +1 when Player1 is to move, so `value * sign` is the mover's outcome
+fn mover_sign(state: &GameState) -> f32 {
+    match state.current_player {
+        Player::Player1 => 1.0,
+        Player::Player2 => -1.0,
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MCTSNode {
@@ -8,16 +31,30 @@ pub struct MCTSNode {
     pub parent: Option<usize>,
     pub children: Vec<usize>,
     pub visits: u32,
+    /// Sum of backed-up values for the player whose move led here, so a
+    /// parent picks the child with the highest mean.
     pub total_value: f32,
     pub prior_probability: f32,
     pub is_terminal: bool,
     pub valid_moves: Vec<u8>,
+    pub move_from_parent: Option<u8>,
+    /// Simulations currently in flight through this node (parallel search).
+    pub virtual_loss: u32,
+    /// Policy for this position, computed once and reused for every child.
+    pub policy: Option<Vec<f32>>,
+    /// Game-theoretic value (Player1 positive) once the solver has proven it.
+    pub proven_value: Option<f32>,
+    /// All-moves-as-first statistics for the move leading here (RAVE), from
+    /// the same point of view as `total_value`.
+    pub amaf_visits: u32,
+    pub amaf_value: f32,
 }
 
 impl MCTSNode {
     pub fn new(state: GameState, parent: Option<usize>, prior_probability: f32) -> Self {
         let valid_moves = state.get_valid_moves();
         let is_terminal = state.is_game_over();
+        let proven_value = is_terminal.then(|| MCTS::get_terminal_value(&state));
 
         Self {
             state,
@@ -28,18 +65,27 @@ impl MCTSNode {
             prior_probability,
             is_terminal,
             valid_moves,
+            move_from_parent: None,
+            virtual_loss: 0,
+            policy: None,
+            proven_value,
+            amaf_visits: 0,
+            amaf_value: 0.0,
         }
     }
 
     pub fn ucb_score(&self, exploration_constant: f32, parent_visits: u32) -> f32 {
-        if self.visits == 0 {
+        // Each in-flight simulation counts as a pending loss so that parallel
+        // workers spread out over different lines
+        let visits = self.visits + self.virtual_loss;
+        if visits == 0 {
             return f32::INFINITY;
         }
 
-        let exploitation = self.total_value / self.visits as f32;
+        let exploitation = (self.total_value - self.virtual_loss as f32) / visits as f32;
         let exploration =
             exploration_constant * self.prior_probability * (parent_visits as f32).sqrt()
-                / (1.0 + self.visits as f32);
+                / (1.0 + visits as f32);
 
         exploitation + exploration
     }
@@ -49,10 +95,153 @@ impl MCTSNode {
     }
 }
 
+/// Extra stopping conditions for a search, on top of `num_simulations`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    /// Wall-clock budget for a single search.
+    pub time_limit: Option<Duration>,
+    /// Stop once the tree holds this many nodes, to bound memory.
+    pub max_nodes: Option<usize>,
+    /// Stop as soon as no other root move can overtake the most visited one
+    /// with the simulations (or time) that are left.
+    pub early_stop: bool,
+}
+
+/// How `search` values a newly expanded leaf.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RolloutPolicy {
+    /// Uniformly random moves.
+    #[default]
+    Uniform,
+    /// Takes an immediate win, else blocks the opponent's, else plays randomly.
+    WinBlock,
+    /// Like `WinBlock`, but samples the other moves by positional value.
+    HeuristicWeighted,
+    /// `WinBlock` playout stopped after `depth` plies and scored by `evaluate`.
+    Truncated { depth: usize },
+    /// No playout: the leaf is scored by `value_fn` alone.
+    ValueOnly,
+}
+
+impl FromStr for RolloutPolicy {
+    type Err = String;
+
+    /// Accepts `uniform`, `win-block`, `heuristic`, `truncated[:depth]` and `value`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.split_once(':') {
+            Some(("truncated", depth)) => depth
+                .parse()
+                .map(|depth| RolloutPolicy::Truncated { depth })
+                .map_err(|_| format!("Invalid truncated rollout depth: {}", depth)),
+            Some(_) => Err(format!("Unknown rollout policy: {}", name)),
+            None => match name {
+                "uniform" => Ok(RolloutPolicy::Uniform),
+                "win-block" => Ok(RolloutPolicy::WinBlock),
+                "heuristic" => Ok(RolloutPolicy::HeuristicWeighted),
+                "truncated" => Ok(RolloutPolicy::Truncated {
+                    depth: DEFAULT_TRUNCATED_DEPTH,
+                }),
+                "value" => Ok(RolloutPolicy::ValueOnly),
+                _ => Err(format!("Unknown rollout policy: {}", name)),
+            },
+        }
+    }
+}
+
+/// How much weight RAVE gives the AMAF value against a child's own value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaveSchedule {
+    /// beta = sqrt(k / (3n + k)): AMAF and UCT weigh equally after `k` visits.
+    Equivalence { k: f32 },
+    /// Minimum-MSE schedule, for an estimated AMAF `bias`.
+    MinimumMse { bias: f32 },
+}
+
+impl RaveSchedule {
+    /// Weight of the AMAF value after `visits` real and `amaf_visits` AMAF updates.
+    pub fn beta(&self, visits: f32, amaf_visits: f32) -> f32 {
+        if amaf_visits == 0.0 {
+            return 0.0;
+        }
+        match *self {
+            RaveSchedule::Equivalence { k } => (k / (3.0 * visits + k)).sqrt(),
+            RaveSchedule::MinimumMse { bias } => {
+                amaf_visits / (visits + amaf_visits + 4.0 * bias * bias * visits * amaf_visits)
+            }
+        }
+    }
+}
+
+impl FromStr for RaveSchedule {
+    type Err = String;
+
+    /// Accepts `equivalence[:k]` and `mse[:bias]`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let (kind, param) = match name.split_once(':') {
+            Some((kind, param)) => (kind, Some(param)),
+            None => (name, None),
+        };
+        let parse = |default: f32| {
+            param.map_or(Ok(default), |p| {
+                p.parse()
+                    .map_err(|_| format!("Invalid RAVE parameter: {}", p))
+            })
+        };
+        match kind {
+            "equivalence" => Ok(RaveSchedule::Equivalence {
+                k: parse(DEFAULT_RAVE_EQUIVALENCE)?,
+            }),
+            "mse" => Ok(RaveSchedule::MinimumMse {
+                bias: parse(DEFAULT_RAVE_BIAS)?,
+            }),
+            _ => Err(format!("Unknown RAVE schedule: {}", name)),
+        }
+    }
+}
+
+/// Value for the side to move and move priors for one leaf position.
+pub type LeafEvaluation = (f32, Vec<f32>);
+
+// Move priors for a position
+type PolicyFn<'a> = &'a dyn Fn(&GameState) -> Vec<f32>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Simulations,
+    TimeLimit,
+    NodeLimit,
+    BestMoveDecided,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: u8,
+    /// Root visit distribution, indexed by column.
+    pub move_probabilities: Vec<f32>,
+    pub visit_counts: Vec<u32>,
+    /// Mean backed-up value of each root move for the side to move at the
+    /// root, `None` if unvisited.
+    pub move_values: Vec<Option<f32>>,
+    /// Mean backed-up value of the root for its side to move.
+    pub root_value: f32,
+    pub simulations: usize,
+    /// Set when the solver has proven the root position, again for the side
+    /// to move.
+    pub proven_value: Option<f32>,
+    pub elapsed: Duration,
+    pub stop_reason: StopReason,
+}
+
 pub struct MCTS {
     pub nodes: Vec<MCTSNode>,
     pub exploration_constant: f32,
     pub num_simulations: usize,
+    pub limits: SearchLimits,
+    pub rollout_policy: RolloutPolicy,
+    /// Blend AMAF values into selection (RAVE); `None` is plain UCT.
+    pub rave: Option<RaveSchedule>,
+    root: Option<usize>,
+    rng: StdRng,
 }
 
 impl MCTS {
@@ -61,7 +250,164 @@ impl MCTS {
             nodes: Vec::new(),
             exploration_constant,
             num_simulations,
+            limits: SearchLimits::default(),
+            rollout_policy: RolloutPolicy::default(),
+            rave: None,
+            root: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn with_limits(
+        exploration_constant: f32,
+        num_simulations: usize,
+        limits: SearchLimits,
+    ) -> Self {
+        Self {
+            limits,
+            ..Self::new(exploration_constant, num_simulations)
+        }
+    }
+
+    /// Makes rollouts repeatable: searches after this draw their random
+    /// moves from `seed` rather than from entropy.
+    pub fn seed_rollouts(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn root(&self) -> Option<usize> {
+        self.root
+    }
+
+    /// Snapshot of the tree below the current root, for visualization.
+    pub fn export_tree(&self, options: &TreeExportOptions) -> Option<SearchTreeNode> {
+        self.root.map(|root| self.export_node(root, 0, options))
+    }
+
+    fn export_node(&self, idx: usize, depth: usize, options: &TreeExportOptions) -> SearchTreeNode {
+        let node = &self.nodes[idx];
+        let mut exported =
+            SearchTreeNode::new(node.move_from_parent, node.state.current_player, depth);
+        exported.visits = Some(node.visits);
+        exported.q_value = (node.visits > 0).then(|| node.total_value / node.visits as f32);
+        exported.prior = Some(node.prior_probability);
+        exported.proven_value = node.proven_value;
+        if self.rave.is_some() && depth > 0 {
+            exported.amaf_visits = Some(node.amaf_visits);
+            exported.amaf_q_value =
+                (node.amaf_visits > 0).then(|| node.amaf_value / node.amaf_visits as f32);
+        }
+        if depth > 0 {
+            exported.ucb = node
+                .parent
+                .map(|parent| self.selection_score(idx, self.nodes[parent].visits));
+        }
+
+        if depth < options.max_depth {
+            exported.children = node
+                .children
+                .iter()
+                .filter(|&&child| self.nodes[child].visits >= options.min_visits)
+                .map(|&child| self.export_node(child, depth + 1, options))
+                .collect();
+        }
+        exported
+    }
+
+    /// Drops the whole tree so the next search starts from scratch.
+    pub fn reset(&mut self) {
+        self.nodes.clear();
+        self.root = None;
+    }
+
+    /// Moves the root down the tree along `moves` (e.g. our move followed by
+    /// the opponent's reply), keeping the statistics of the subtree that was
+    /// actually reached and discarding everything else. Returns `false` and
+    /// resets the tree if the line leaves the explored part of the tree.
+    pub fn advance_root(&mut self, moves: &[u8]) -> bool {
+        let Some(mut current) = self.root else {
+            return false;
+        };
+
+        for &mv in moves {
+            match self.child_for_move(current, mv) {
+                Some(child) => current = child,
+                None => {
+                    self.reset();
+                    return false;
+                }
+            }
+        }
+
+        self.reroot(current);
+        true
+    }
+
+    /// Like `advance_root`, but finds the moves itself by looking for `state`
+    /// a few plies below the current root. Useful when only the new position
+    /// is known, as with the wasm API.
+    pub fn advance_to(&mut self, state: &GameState) -> bool {
+        let Some(root_idx) = self.root else {
+            return false;
+        };
+
+        let mut queue = VecDeque::from([(root_idx, 0)]);
+        while let Some((idx, depth)) = queue.pop_front() {
+            let node_state = &self.nodes[idx].state;
+            if node_state.board == state.board && node_state.current_player == state.current_player
+            {
+                self.reroot(idx);
+                return true;
+            }
+            if depth < MAX_REROOT_DEPTH {
+                queue.extend(self.nodes[idx].children.iter().map(|&c| (c, depth + 1)));
+            }
+        }
+
+        self.reset();
+        false
+    }
+
+    fn child_for_move(&self, node_idx: usize, mv: u8) -> Option<usize> {
+        self.nodes[node_idx]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].move_from_parent == Some(mv))
+    }
+
+    // Makes `new_root` the root and compacts the arena so that only its
+    // subtree remains, renumbering parent/child links accordingly.
+    fn reroot(&mut self, new_root: usize) {
+        let mut order = vec![new_root];
+        let mut i = 0;
+        while i < order.len() {
+            order.extend(self.nodes[order[i]].children.iter().copied());
+            i += 1;
+        }
+
+        let mut remap = vec![None; self.nodes.len()];
+        for (new_idx, &old_idx) in order.iter().enumerate() {
+            remap[old_idx] = Some(new_idx);
         }
+
+        let mut old_nodes: Vec<Option<MCTSNode>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.nodes = order
+            .iter()
+            .map(|&old_idx| {
+                let mut node = old_nodes[old_idx].take().expect("node visited twice");
+                node.parent = node.parent.and_then(|p| remap[p]);
+                node.children = node.children.iter().filter_map(|&c| remap[c]).collect();
+                node
+            })
+            .collect();
+
+        self.nodes[0].parent = None;
+        self.nodes[0].move_from_parent = None;
+        self.root = Some(0);
     }
 
     pub fn search(
@@ -70,60 +416,372 @@ impl MCTS {
         value_fn: &dyn Fn(&GameState) -> f32,
         policy_fn: &dyn Fn(&GameState) -> Vec<f32>,
     ) -> (u8, Vec<f32>) {
-        // Create root node
-        let root_idx = self.add_node(root_state, None, 1.0);
+        let result = self.search_with_stats(root_state, value_fn, policy_fn);
+        (result.best_move, result.move_probabilities)
+    }
+
+    /// Runs simulations until `num_simulations` or one of `limits` is hit,
+    /// and reports the root statistics alongside the chosen move.
+    /// `value_fn` scores a position for its side to move.
+    pub fn search_with_stats(
+        &mut self,
+        root_state: GameState,
+        value_fn: &dyn Fn(&GameState) -> f32,
+        policy_fn: &dyn Fn(&GameState) -> Vec<f32>,
+    ) -> SearchResult {
+        let start_ms = now_ms();
+        let root_idx = self.prepare_root(root_state);
+
+        // Run simulations until a budget runs out
+        let mut simulations = 0;
+        let stop_reason = loop {
+            if let Some(reason) = self.check_limits(root_idx, simulations, start_ms) {
+                break reason;
+            }
 
-        // Run simulations
-        for _ in 0..self.num_simulations {
             self.simulate(root_idx, value_fn, policy_fn);
+            simulations += 1;
+        };
+
+        self.collect_result(root_idx, simulations, start_ms, stop_reason)
+    }
+
+    /// Tree-parallel search: `num_threads` workers share one tree, each
+    /// descending under the lock with virtual loss on its path and running
+    /// `value_fn` and `policy_fn` on the leaf outside it. The policy is kept
+    /// on the leaf for its later expansion. `0` uses every core.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn search_parallel(
+        &mut self,
+        root_state: GameState,
+        value_fn: &(dyn Fn(&GameState) -> f32 + Sync),
+        policy_fn: &(dyn Fn(&GameState) -> Vec<f32> + Sync),
+        num_threads: usize,
+    ) -> SearchResult {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Mutex;
+
+        let num_threads = if num_threads == 0 {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        } else {
+            num_threads
+        };
+
+        let start_ms = now_ms();
+        let root_idx = self.prepare_root(root_state);
+        if self.nodes[root_idx].policy.is_none() && !self.nodes[root_idx].is_terminal {
+            let policy = policy_fn(&self.nodes[root_idx].state);
+            self.nodes[root_idx].policy = Some(policy);
+        }
+        // Priors for a leaf another worker is still evaluating
+        let uniform_policy = |_state: &GameState| vec![1.0 / COLS as f32; COLS];
+        let rollout_policy = self.rollout_policy;
+        let started = AtomicUsize::new(0);
+        let stop_reason = Mutex::new(None);
+        let tree = Mutex::new(&mut *self);
+
+        std::thread::scope(|scope| {
+            for _ in 0..num_threads {
+                scope.spawn(|| loop {
+                    let (leaf_idx, leaf_state, known_value, rollout_seed) = {
+                        let mut tree = tree.lock().unwrap();
+                        let mut reason = stop_reason.lock().unwrap();
+                        if reason.is_none() {
+                            *reason = tree.check_limits(
+                                root_idx,
+                                started.load(Ordering::SeqCst),
+                                start_ms,
+                            );
+                        }
+                        if reason.is_some() {
+                            break;
+                        }
+                        started.fetch_add(1, Ordering::SeqCst);
+
+                        let (leaf_idx, known_value) =
+                            tree.descend(root_idx, Some(&uniform_policy), true);
+                        let leaf_state = known_value
+                            .is_none()
+                            .then(|| tree.nodes[leaf_idx].state.clone());
+                        // Each rollout gets its own stream, drawn from the tree's
+                        let rollout_seed = tree.rng.gen();
+                        (leaf_idx, leaf_state, known_value, rollout_seed)
+                    };
+
+                    let mut played = Vec::new();
+                    let evaluation = leaf_state.map(|state| {
+                        let mut rng = StdRng::seed_from_u64(rollout_seed);
+                        let value = Self::evaluate_leaf(
+                            &state,
+                            rollout_policy,
+                            value_fn,
+                            &mut played,
+                            &mut rng,
+                        );
+                        (value, policy_fn(&state))
+                    });
+
+                    let mut tree = tree.lock().unwrap();
+                    let value = match evaluation {
+                        Some((value, policy)) => {
+                            tree.nodes[leaf_idx].policy = Some(policy);
+                            value
+                        }
+                        None => known_value.unwrap(),
+                    };
+                    tree.backup(leaf_idx, value, &played);
+                    tree.release_virtual_loss(leaf_idx);
+                });
+            }
+        });
+
+        let simulations = started.into_inner();
+        let stop_reason = stop_reason
+            .into_inner()
+            .unwrap()
+            .unwrap_or(StopReason::Simulations);
+        self.collect_result(root_idx, simulations, start_ms, stop_reason)
+    }
+
+    /// Batched search: descends up to `batch_size` times with virtual loss,
+    /// then hands all pending leaves to `evaluate_batch` at once. It returns
+    /// a value for the side to move and a policy per state, and the policy
+    /// is cached on the leaf for its later expansion. A descent that reaches
+    /// a leaf already in the batch sends the batch off early, so no node is
+    /// expanded before its policy is known.
+    pub fn search_batched(
+        &mut self,
+        root_state: GameState,
+        evaluate_batch: &dyn Fn(&[GameState]) -> Vec<LeafEvaluation>,
+        batch_size: usize,
+    ) -> SearchResult {
+        let start_ms = now_ms();
+        let root_idx = self.prepare_root(root_state);
+        let batch_size = batch_size.max(1);
+
+        if self.nodes[root_idx].policy.is_none() && !self.nodes[root_idx].is_terminal {
+            let root_state = [self.nodes[root_idx].state.clone()];
+            if let Some((_, policy)) = evaluate_batch(&root_state).pop() {
+                self.nodes[root_idx].policy = Some(policy);
+            }
         }
 
-        // Get move probabilities
+        let mut simulations = 0;
+        let mut pending = Vec::with_capacity(batch_size);
+        let stop_reason = loop {
+            let mut stop = None;
+            while pending.len() < batch_size {
+                stop = self.check_limits(root_idx, simulations, start_ms);
+                if stop.is_some() {
+                    break;
+                }
+
+                // Leaves are only expanded once their policy is in, so
+                // reaching one that is still pending evaluates the batch early
+                let (leaf_idx, known_value) = self.descend(root_idx, None, true);
+                match known_value {
+                    Some(value) => {
+                        self.backup(leaf_idx, value, &[]);
+                        self.release_virtual_loss(leaf_idx);
+                    }
+                    None if pending.contains(&leaf_idx) => {
+                        self.release_virtual_loss(leaf_idx);
+                        break;
+                    }
+                    None => pending.push(leaf_idx),
+                }
+                simulations += 1;
+            }
+
+            if !pending.is_empty() {
+                let states: Vec<GameState> = pending
+                    .iter()
+                    .map(|&idx| self.nodes[idx].state.clone())
+                    .collect();
+                let mut evaluations = evaluate_batch(&states).into_iter();
+                for leaf_idx in pending.drain(..) {
+                    if let Some((value, policy)) = evaluations.next() {
+                        let sign = mover_sign(&self.nodes[leaf_idx].state);
+                        self.nodes[leaf_idx].policy = Some(policy);
+                        self.backup(leaf_idx, value * sign, &[]);
+                    }
+                    self.release_virtual_loss(leaf_idx);
+                }
+            }
+
+            if let Some(reason) = stop {
+                break reason;
+            }
+        };
+
+        self.collect_result(root_idx, simulations, start_ms, stop_reason)
+    }
+
+    // Reuse the existing tree when it is rooted at this position, otherwise
+    // start a fresh one
+    fn prepare_root(&mut self, root_state: GameState) -> usize {
+        let root_idx = match self.root {
+            Some(idx)
+                if self.nodes[idx].state.board == root_state.board
+                    && self.nodes[idx].state.current_player == root_state.current_player =>
+            {
+                idx
+            }
+            _ => {
+                self.nodes.clear();
+                self.add_node(root_state, None, 1.0)
+            }
+        };
+        self.root = Some(root_idx);
+        root_idx
+    }
+
+    fn check_limits(
+        &self,
+        root_idx: usize,
+        simulations: usize,
+        start_ms: f64,
+    ) -> Option<StopReason> {
+        if simulations >= self.num_simulations {
+            return Some(StopReason::Simulations);
+        }
+
+        let elapsed_ms = now_ms() - start_ms;
+        let time_left_ms = self
+            .limits
+            .time_limit
+            .map(|limit| limit.as_secs_f64() * 1000.0 - elapsed_ms);
+        if time_left_ms.is_some_and(|left| left <= 0.0) {
+            return Some(StopReason::TimeLimit);
+        }
+
+        if self
+            .limits
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes.len() >= max_nodes)
+        {
+            return Some(StopReason::NodeLimit);
+        }
+
+        if self.limits.early_stop && simulations > 0 {
+            // Estimate how many more simulations we can still afford
+            let mut remaining = (self.num_simulations - simulations) as f64;
+            if let Some(left) = time_left_ms {
+                let rate = simulations as f64 / elapsed_ms.max(1e-3);
+                remaining = remaining.min(left * rate);
+            }
+            if self.best_move_decided(root_idx, remaining) {
+                return Some(StopReason::BestMoveDecided);
+            }
+        }
+
+        None
+    }
+
+    fn collect_result(
+        &self,
+        root_idx: usize,
+        simulations: usize,
+        start_ms: f64,
+        stop_reason: StopReason,
+    ) -> SearchResult {
+        // Get move probabilities and values
         let root_node = &self.nodes[root_idx];
-        let mut move_probs = vec![0.0; COLS as usize];
+        let mut visit_counts = vec![0; COLS];
+        let mut move_values = vec![None; COLS];
         let mut total_visits = 0;
 
         for &child_idx in &root_node.children {
             let child = &self.nodes[child_idx];
-            let move_idx = self.get_move_from_parent(root_idx, child_idx);
-            move_probs[move_idx as usize] = child.visits as f32;
+            let move_idx = self.get_move_from_parent(root_idx, child_idx) as usize;
+            visit_counts[move_idx] = child.visits;
+            if child.visits > 0 {
+                move_values[move_idx] = Some(child.total_value / child.visits as f32);
+            }
             total_visits += child.visits;
         }
 
-        if total_visits > 0 {
-            for prob in &mut move_probs {
-                *prob /= total_visits as f32;
-            }
-        }
+        let move_probabilities = visit_counts
+            .iter()
+            .map(|&visits| {
+                if total_visits > 0 {
+                    visits as f32 / total_visits as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect();
 
-        // Select best move
+        // Play a proven win if there is one, never a proven loss unless every
+        // move loses, and otherwise the most visited move
         let best_move = if root_node.valid_moves.is_empty() {
             // No valid moves available
             0
         } else {
+            let sign = mover_sign(&root_node.state);
             root_node
                 .valid_moves
                 .iter()
-                .max_by(|&&a, &&b| {
-                    let a_visits = self.nodes[root_node
-                        .children
-                        .iter()
-                        .position(|&c| self.get_move_from_parent(root_idx, c) == a)
-                        .unwrap_or(0)]
-                    .visits;
-                    let b_visits = self.nodes[root_node
-                        .children
-                        .iter()
-                        .position(|&c| self.get_move_from_parent(root_idx, c) == b)
-                        .unwrap_or(0)]
-                    .visits;
-                    a_visits.cmp(&b_visits)
+                .max_by_key(|&&mv| {
+                    let proven = self
+                        .child_for_move(root_idx, mv)
+                        .and_then(|child| self.nodes[child].proven_value);
+                    let rank = match proven {
+                        Some(value) if value * sign > 0.0 => 2,
+                        Some(value) if value * sign < 0.0 => 0,
+                        _ => 1,
+                    };
+                    (rank, visit_counts[mv as usize])
                 })
                 .copied()
                 .unwrap_or(root_node.valid_moves[0]) // Fallback to first valid move
         };
 
-        (best_move, move_probs)
+        // The root's total is kept for the player who moved into it
+        let root_value = if root_node.visits > 0 {
+            -root_node.total_value / root_node.visits as f32
+        } else {
+            0.0
+        };
+        let sign = mover_sign(&root_node.state);
+
+        SearchResult {
+            best_move,
+            move_probabilities,
+            visit_counts,
+            move_values,
+            root_value,
+            simulations,
+            proven_value: root_node.proven_value.map(|value| value * sign),
+            elapsed: Duration::from_secs_f64((now_ms() - start_ms).max(0.0) / 1000.0),
+            stop_reason,
+        }
+    }
+
+    // The most visited root move is settled once the runner-up could not
+    // catch up even if it received every remaining simulation.
+    fn best_move_decided(&self, root_idx: usize, remaining_simulations: f64) -> bool {
+        if self.nodes[root_idx].proven_value.is_some() {
+            return true;
+        }
+
+        let mut visits: Vec<u32> = self.nodes[root_idx]
+            .children
+            .iter()
+            .map(|&child| self.nodes[child].visits)
+            .collect();
+        if visits.len() < self.nodes[root_idx].valid_moves.len() {
+            // An unexpanded move still counts as a contender with no visits
+            visits.push(0);
+        }
+        if visits.len() < 2 {
+            return !visits.is_empty();
+        }
+
+        visits.sort_unstable_by(|a, b| b.cmp(a));
+        (visits[1] as f64 + remaining_simulations) < visits[0] as f64
     }
 
     fn simulate(
@@ -132,72 +790,128 @@ impl MCTS {
         value_fn: &dyn Fn(&GameState) -> f32,
         policy_fn: &dyn Fn(&GameState) -> Vec<f32>,
     ) -> f32 {
-        self.simulate_with_depth(node_idx, value_fn, policy_fn, 0)
+        let (leaf_idx, known_value) = self.descend(node_idx, Some(policy_fn), false);
+        let mut played = Vec::new();
+        let value = known_value.unwrap_or_else(|| {
+            let state = &self.nodes[leaf_idx].state;
+            Self::evaluate_leaf(
+                state,
+                self.rollout_policy,
+                value_fn,
+                &mut played,
+                &mut self.rng,
+            )
+        });
+        self.backup(leaf_idx, value, &played);
+        value
     }
 
-    fn simulate_with_depth(
+    // Walks down from `node_idx` by UCB until it reaches a terminal node or
+    // expands a new child. Returns the leaf and its value when already known
+    // (terminal or too deep); otherwise the leaf still needs evaluating.
+    // Without `policy_fn`, a node still waiting for its policy is returned
+    // as it is instead of being expanded.
+    fn descend(
         &mut self,
         node_idx: usize,
-        value_fn: &dyn Fn(&GameState) -> f32,
-        policy_fn: &dyn Fn(&GameState) -> Vec<f32>,
-        depth: usize,
-    ) -> f32 {
+        policy_fn: Option<PolicyFn>,
+        virtual_loss: bool,
+    ) -> (usize, Option<f32>) {
         const MAX_SIMULATION_DEPTH: usize = 100;
 
-        if depth > MAX_SIMULATION_DEPTH {
-            // Return a neutral value if we've gone too deep
-            return 0.0;
-        }
+        let mut node_idx = node_idx;
+        let mut depth = 0;
+
+        loop {
+            if virtual_loss {
+                self.nodes[node_idx].virtual_loss += 1;
+            }
+
+            if depth > MAX_SIMULATION_DEPTH {
+                // Return a neutral value if we've gone too deep
+                return (node_idx, Some(0.0));
+            }
 
-        {
             let node = &self.nodes[node_idx];
+            if let Some(value) = node.proven_value {
+                return (node_idx, Some(value));
+            }
             if node.is_terminal {
-                return self.get_terminal_value(&node.state);
+                return (node_idx, Some(Self::get_terminal_value(&node.state)));
             }
-        }
 
-        {
-            let node = &self.nodes[node_idx];
             if !node.is_fully_expanded() {
+                if node.policy.is_none() {
+                    let Some(policy_fn) = policy_fn else {
+                        return (node_idx, None);
+                    };
+                    let policy = policy_fn(&node.state);
+                    self.nodes[node_idx].policy = Some(policy);
+                }
                 // Expand node
-                let new_child_idx = self.expand_node(node_idx, policy_fn);
-                let value = self.rollout(&self.nodes[new_child_idx].state);
-                self.backpropagate(new_child_idx, value);
-                return value;
+                let new_child_idx = self.expand_node(node_idx);
+                if virtual_loss && new_child_idx != node_idx {
+                    self.nodes[new_child_idx].virtual_loss += 1;
+                }
+                // A terminal child already knows its value
+                return (new_child_idx, self.nodes[new_child_idx].proven_value);
             }
-        }
 
-        // Select child using UCB
-        let parent_visits = self.nodes[node_idx].visits;
-        let children = self.nodes[node_idx].children.clone();
+            // Select child using UCB, skipping moves already proven to lose
+            let parent_visits = node.visits + node.virtual_loss;
+            let sign = mover_sign(&node.state);
+            let best_child_idx = node
+                .children
+                .iter()
+                .filter(|&&child| {
+                    self.nodes[child]
+                        .proven_value
+                        .is_none_or(|value| value * sign >= 0.0)
+                })
+                .max_by(|&&a, &&b| {
+                    let a_score = self.selection_score(a, parent_visits);
+                    let b_score = self.selection_score(b, parent_visits);
+                    a_score
+                        .partial_cmp(&b_score)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .copied()
+                .unwrap_or(node_idx);
 
-        let best_child_idx = children
-            .iter()
-            .max_by(|&&a, &&b| {
-                let a_score = self.nodes[a].ucb_score(self.exploration_constant, parent_visits);
-                let b_score = self.nodes[b].ucb_score(self.exploration_constant, parent_visits);
-                a_score
-                    .partial_cmp(&b_score)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            })
-            .copied()
-            .unwrap_or(node_idx);
+            if best_child_idx == node_idx {
+                // Every move is proven to lose, so this node is lost too
+                let value = -sign;
+                self.nodes[node_idx].proven_value = Some(value);
+                return (node_idx, Some(value));
+            }
+            node_idx = best_child_idx;
+            depth += 1;
+        }
+    }
 
-        let value = self.simulate_with_depth(best_child_idx, value_fn, policy_fn, depth + 1);
-        self.backpropagate(node_idx, value);
-        value
+    fn evaluate_leaf(
+        state: &GameState,
+        rollout_policy: RolloutPolicy,
+        value_fn: &dyn Fn(&GameState) -> f32,
+        played: &mut Vec<(Player, u8)>,
+        rng: &mut impl Rng,
+    ) -> f32 {
+        match rollout_policy {
+            RolloutPolicy::ValueOnly => value_fn(state) * mover_sign(state),
+            _ => Self::rollout(state, rollout_policy, played, rng),
+        }
     }
 
-    fn expand_node(
-        &mut self,
-        node_idx: usize,
-        policy_fn: &dyn Fn(&GameState) -> Vec<f32>,
-    ) -> usize {
-        let policy = {
-            let node = &self.nodes[node_idx];
-            policy_fn(&node.state)
-        };
+    fn release_virtual_loss(&mut self, node_idx: usize) {
+        let mut current_idx = Some(node_idx);
+        while let Some(idx) = current_idx {
+            let node = &mut self.nodes[idx];
+            node.virtual_loss = node.virtual_loss.saturating_sub(1);
+            current_idx = node.parent;
+        }
+    }
 
+    fn expand_node(&mut self, node_idx: usize) -> usize {
         // Find unexpanded move
         let expanded_moves: Vec<u8> = {
             let node = &self.nodes[node_idx];
@@ -219,8 +933,13 @@ impl MCTS {
         // Create new state
         let mut new_state = self.nodes[node_idx].state.clone();
         if new_state.make_move(unexpanded_move).is_ok() {
-            let prior_prob = policy.get(unexpanded_move as usize).copied().unwrap_or(0.0);
+            let prior_prob = self.nodes[node_idx]
+                .policy
+                .as_ref()
+                .and_then(|policy| policy.get(unexpanded_move as usize).copied())
+                .unwrap_or(0.0);
             let child_idx = self.add_node(new_state, Some(node_idx), prior_prob);
+            self.nodes[child_idx].move_from_parent = Some(unexpanded_move);
             self.nodes[node_idx].children.push(child_idx);
             child_idx
         } else {
@@ -228,26 +947,163 @@ impl MCTS {
         }
     }
 
-    fn rollout(&self, state: &GameState) -> f32 {
+    fn rollout(
+        state: &GameState,
+        rollout_policy: RolloutPolicy,
+        played: &mut Vec<(Player, u8)>,
+        rng: &mut impl Rng,
+    ) -> f32 {
+        let max_depth = match rollout_policy {
+            RolloutPolicy::Truncated { depth } => depth,
+            _ => MAX_ROLLOUT_DEPTH,
+        };
         let mut current_state = state.clone();
         let mut depth = 0;
-        const MAX_ROLLOUT_DEPTH: usize = 20;
 
-        while !current_state.is_game_over() && depth < MAX_ROLLOUT_DEPTH {
+        while !current_state.is_game_over() && depth < max_depth {
             let valid_moves = current_state.get_valid_moves();
             if valid_moves.is_empty() {
                 break;
             }
 
-            // Random move selection for rollout
-            let random_move = valid_moves[rand::thread_rng().gen_range(0..valid_moves.len())];
-            if current_state.make_move(random_move).is_err() {
+            let tactical_move = match rollout_policy {
+                RolloutPolicy::Uniform => None,
+                _ => Self::tactical_move(&current_state, &valid_moves),
+            };
+            let next_move = tactical_move.unwrap_or_else(|| match rollout_policy {
+                RolloutPolicy::HeuristicWeighted => {
+                    Self::weighted_move(&current_state, &valid_moves, rng)
+                }
+                _ => valid_moves[rng.gen_range(0..valid_moves.len())],
+            });
+            let mover = current_state.current_player;
+            if current_state.make_move(next_move).is_err() {
                 break;
             }
+            played.push((mover, next_move));
             depth += 1;
         }
 
-        self.get_terminal_value(&current_state)
+        match rollout_policy {
+            RolloutPolicy::Truncated { .. } if !current_state.is_game_over() => {
+                (current_state.evaluate() as f32 / STATIC_EVAL_SCALE).tanh()
+            }
+            _ => Self::get_terminal_value(&current_state),
+        }
+    }
+
+    // A move that wins on the spot, or else one that stops the opponent from
+    // winning on their next turn
+    fn tactical_move(state: &GameState, valid_moves: &[u8]) -> Option<u8> {
+        let mover = state.current_player;
+        [mover, mover.opponent()].into_iter().find_map(|player| {
+            valid_moves.iter().copied().find(|&mv| {
+                let col = mv as usize;
+                let row = GameState::lowest_empty_row_in(&state.board, col);
+                state.check_win_at_test(&state.board, col, row, player)
+            })
+        })
+    }
+
+    // Samples a move with probability proportional to its cell's position value
+    fn weighted_move(state: &GameState, valid_moves: &[u8], rng: &mut impl Rng) -> u8 {
+        let weights: Vec<i32> = valid_moves
+            .iter()
+            .map(|&mv| {
+                let col = mv as usize;
+                let row = GameState::lowest_empty_row_in(&state.board, col);
+                state.cell_position_value(col, row).max(0) + 1
+            })
+            .collect();
+
+        let mut pick = rng.gen_range(0..weights.iter().sum::<i32>());
+        for (&mv, &weight) in valid_moves.iter().zip(&weights) {
+            if pick < weight {
+                return mv;
+            }
+            pick -= weight;
+        }
+        valid_moves[valid_moves.len() - 1]
+    }
+
+    // Backpropagates a simulation result (Player1 positive, like terminal and
+    // proven values), plus the AMAF updates when RAVE is on. `played` holds
+    // the rollout moves made below the leaf.
+    fn backup(&mut self, leaf_idx: usize, value: f32, played: &[(Player, u8)]) {
+        self.backpropagate(leaf_idx, value);
+        if self.rave.is_some() {
+            self.update_amaf(leaf_idx, value, played);
+        }
+    }
+
+    // Every child whose column its parent's mover played anywhere later in
+    // the simulation gets the result as if that move had been played first
+    fn update_amaf(&mut self, leaf_idx: usize, value: f32, played: &[(Player, u8)]) {
+        let mut seen = [[false; COLS]; 2];
+        for &(player, mv) in played {
+            seen[player as usize][mv as usize] = true;
+        }
+
+        let mut current_idx = leaf_idx;
+        loop {
+            let node = &self.nodes[current_idx];
+            let mover = node.state.current_player as usize;
+            let sign = mover_sign(&node.state);
+            let credited: Vec<usize> = node
+                .children
+                .iter()
+                .copied()
+                .filter(|&child| {
+                    self.nodes[child]
+                        .move_from_parent
+                        .is_some_and(|mv| seen[mover][mv as usize])
+                })
+                .collect();
+            for child in credited {
+                self.nodes[child].amaf_visits += 1;
+                self.nodes[child].amaf_value += value * sign;
+            }
+
+            let node = &self.nodes[current_idx];
+            match (node.parent, node.move_from_parent) {
+                (Some(parent), Some(mv)) => {
+                    let parent_mover = self.nodes[parent].state.current_player as usize;
+                    seen[parent_mover][mv as usize] = true;
+                    current_idx = parent;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // UCB score of a child, with its AMAF value blended in when RAVE is on
+    fn selection_score(&self, child_idx: usize, parent_visits: u32) -> f32 {
+        let node = &self.nodes[child_idx];
+        let Some(schedule) = self.rave else {
+            return node.ucb_score(self.exploration_constant, parent_visits);
+        };
+
+        let visits = node.visits + node.virtual_loss;
+        if visits == 0 && node.amaf_visits == 0 {
+            return f32::INFINITY;
+        }
+
+        let beta = schedule.beta(visits as f32, node.amaf_visits as f32);
+        let value = if visits > 0 {
+            (node.total_value - node.virtual_loss as f32) / visits as f32
+        } else {
+            0.0
+        };
+        let amaf = if node.amaf_visits > 0 {
+            node.amaf_value / node.amaf_visits as f32
+        } else {
+            0.0
+        };
+        let exploration =
+            self.exploration_constant * node.prior_probability * (parent_visits as f32).sqrt()
+                / (1.0 + visits as f32);
+
+        (1.0 - beta) * value + beta * amaf + exploration
     }
 
     fn backpropagate(&mut self, node_idx: usize, value: f32) {
@@ -256,7 +1112,9 @@ impl MCTS {
         while current_idx < self.nodes.len() {
             let parent_idx = self.nodes[current_idx].parent;
             self.nodes[current_idx].visits += 1;
-            self.nodes[current_idx].total_value += value;
+            // Credit the player who moved here: the opponent of this mover
+            let sign = mover_sign(&self.nodes[current_idx].state);
+            self.nodes[current_idx].total_value -= value * sign;
 
             if let Some(parent) = parent_idx {
                 current_idx = parent;
@@ -265,9 +1123,52 @@ impl MCTS {
                 break;
             }
         }
+
+        if self.nodes[node_idx].proven_value.is_some() {
+            self.propagate_proof(node_idx);
+        }
+    }
+
+    // MCTS-Solver: a parent is won as soon as one child wins for the side to
+    // move there, and decided once every move has a proven value
+    fn propagate_proof(&mut self, node_idx: usize) {
+        let mut current_idx = self.nodes[node_idx].parent;
+
+        while let Some(idx) = current_idx {
+            if self.nodes[idx].proven_value.is_some() {
+                break;
+            }
+
+            let node = &self.nodes[idx];
+            let sign = mover_sign(&node.state);
+            let mut all_proven = node.is_fully_expanded();
+            let mut best: Option<f32> = None;
+            for &child in &node.children {
+                match self.nodes[child].proven_value {
+                    Some(value) => {
+                        if best.is_none_or(|best| value * sign > best * sign) {
+                            best = Some(value);
+                        }
+                    }
+                    None => all_proven = false,
+                }
+            }
+
+            let proven = match best {
+                Some(value) if value * sign > 0.0 => Some(value),
+                Some(value) if all_proven => Some(value),
+                _ => None,
+            };
+            if proven.is_none() {
+                break;
+            }
+
+            self.nodes[idx].proven_value = proven;
+            current_idx = self.nodes[idx].parent;
+        }
     }
 
-    fn get_terminal_value(&self, state: &GameState) -> f32 {
+    fn get_terminal_value(state: &GameState) -> f32 {
         if let Some(winner) = state.get_winner() {
             match winner {
                 Player::Player1 => 1.0,
@@ -290,6 +1191,10 @@ impl MCTS {
     }
 
     fn get_move_from_parent(&self, parent_idx: usize, child_idx: usize) -> u8 {
+        if let Some(mv) = self.nodes[child_idx].move_from_parent {
+            return mv;
+        }
+
         let parent_state = &self.nodes[parent_idx].state;
         let child_state = &self.nodes[child_idx].state;
 
@@ -304,6 +1209,20 @@ impl MCTS {
     }
 }
 
+// Milliseconds from an arbitrary origin; `Instant` is unavailable in the browser
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+fn now_ms() -> f64 {
+    lazy_static::lazy_static! {
+        static ref ORIGIN: std::time::Instant = std::time::Instant::now();
+    }
+    ORIGIN.elapsed().as_secs_f64() * 1000.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -350,4 +1269,660 @@ mod tests {
         assert_eq!(move_probs.len(), COLS as usize);
         assert!((move_probs.iter().sum::<f32>() - 1.0).abs() < 0.001);
     }
+
+    #[test]
+    fn test_advance_root_keeps_subtree() {
+        let state = GameState::new();
+        let mut mcts = MCTS::new(1.0, 200);
+
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let (best_move, _) = mcts.search(state.clone(), &value_fn, &policy_fn);
+        let root = mcts.root().unwrap();
+        let child = mcts.child_for_move(root, best_move).unwrap();
+        let child_visits = mcts.nodes[child].visits;
+        let tree_size = mcts.nodes.len();
+
+        assert!(mcts.advance_root(&[best_move]));
+        assert_eq!(mcts.root(), Some(0));
+        assert_eq!(mcts.nodes[0].visits, child_visits);
+        assert_eq!(mcts.nodes[0].parent, None);
+        assert!(mcts.nodes.len() < tree_size);
+
+        // Every remaining link must point inside the compacted arena
+        for (idx, node) in mcts.nodes.iter().enumerate() {
+            for &c in &node.children {
+                assert_eq!(mcts.nodes[c].parent, Some(idx));
+            }
+        }
+
+        // Searching the new root continues from the reused statistics
+        let mut next_state = state;
+        next_state.make_move(best_move).unwrap();
+        mcts.search(next_state, &value_fn, &policy_fn);
+        assert_eq!(mcts.nodes[0].visits, child_visits + 200);
+    }
+
+    #[test]
+    fn test_advance_root_unknown_line_resets() {
+        let mut mcts = MCTS::new(1.0, 10);
+        assert!(!mcts.advance_root(&[3]));
+
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+        mcts.search(GameState::new(), &value_fn, &policy_fn);
+
+        // Ten simulations cannot reach four plies deep along a fixed line
+        assert!(!mcts.advance_root(&[3, 3, 3, 3]));
+        assert!(mcts.nodes.is_empty());
+        assert_eq!(mcts.root(), None);
+    }
+
+    #[test]
+    fn test_advance_to_finds_position_after_reply() {
+        let state = GameState::new();
+        let mut mcts = MCTS::new(1.0, 300);
+
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+        let (best_move, _) = mcts.search(state.clone(), &value_fn, &policy_fn);
+
+        let root = mcts.root().unwrap();
+        let our_child = mcts.child_for_move(root, best_move).unwrap();
+        let reply_node = mcts.nodes[our_child].children[0];
+        let reply = mcts.nodes[reply_node].move_from_parent.unwrap();
+
+        let mut next_state = state;
+        next_state.make_move(best_move).unwrap();
+        next_state.make_move(reply).unwrap();
+
+        assert!(mcts.advance_to(&next_state));
+        assert_eq!(mcts.nodes[0].state.board, next_state.board);
+        assert!(mcts.nodes[0].visits > 0);
+    }
+
+    #[test]
+    fn test_search_with_stats_reports_root_statistics() {
+        let mut mcts = MCTS::new(1.0, 300);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let result = mcts.search_with_stats(GameState::new(), &value_fn, &policy_fn);
+
+        assert_eq!(result.stop_reason, StopReason::Simulations);
+        assert_eq!(result.simulations, 300);
+        assert_eq!(result.visit_counts.iter().sum::<u32>(), 300);
+        assert_eq!(mcts.nodes[0].visits, 300);
+        assert!((result.move_probabilities.iter().sum::<f32>() - 1.0).abs() < 0.001);
+        assert!(result.move_values.iter().all(|v| v.is_some()));
+        assert!((-1.0..=1.0).contains(&result.root_value));
+        assert_eq!(
+            result.visit_counts[result.best_move as usize],
+            *result.visit_counts.iter().max().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_search_stops_at_time_limit() {
+        let limits = SearchLimits {
+            time_limit: Some(Duration::from_millis(20)),
+            ..SearchLimits::default()
+        };
+        let mut mcts = MCTS::with_limits(1.0, usize::MAX, limits);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let result = mcts.search_with_stats(GameState::new(), &value_fn, &policy_fn);
+
+        assert_eq!(result.stop_reason, StopReason::TimeLimit);
+        assert!(result.simulations > 0);
+        assert!(result.elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_search_stops_at_node_limit() {
+        let limits = SearchLimits {
+            max_nodes: Some(50),
+            ..SearchLimits::default()
+        };
+        let mut mcts = MCTS::with_limits(1.0, 10_000, limits);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let result = mcts.search_with_stats(GameState::new(), &value_fn, &policy_fn);
+
+        assert_eq!(result.stop_reason, StopReason::NodeLimit);
+        assert_eq!(mcts.nodes.len(), 50);
+    }
+
+    #[test]
+    fn test_search_stops_early_when_move_is_decided() {
+        let limits = SearchLimits {
+            early_stop: true,
+            ..SearchLimits::default()
+        };
+        let mut mcts = MCTS::with_limits(1.0, 2_000, limits);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        // Player1 to move with three in a row: column 3 wins immediately
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        assert_eq!(result.best_move, 3);
+        assert_eq!(result.stop_reason, StopReason::BestMoveDecided);
+        assert!(result.simulations < 2_000);
+    }
+
+    #[test]
+    fn test_parallel_search_shares_one_tree() {
+        let mut mcts = MCTS::new(1.0, 400);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let result = mcts.search_parallel(GameState::new(), &value_fn, &policy_fn, 4);
+
+        assert_eq!(result.simulations, 400);
+        assert_eq!(result.stop_reason, StopReason::Simulations);
+        assert_eq!(result.visit_counts.iter().sum::<u32>(), 400);
+        let root = mcts.root().unwrap();
+        assert_eq!(mcts.nodes[root].visits, 400);
+        assert!(mcts.nodes.iter().all(|node| node.virtual_loss == 0));
+    }
+
+    #[test]
+    fn test_parallel_search_expands_with_the_leaf_policy() {
+        let mut mcts = MCTS::new(1.0, 200);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![0.1, 0.1, 0.1, 0.4, 0.1, 0.1, 0.1];
+
+        mcts.search_parallel(GameState::new(), &value_fn, &policy_fn, 1);
+
+        let root = mcts.root().unwrap();
+        let centre = mcts.child_for_move(root, 3).unwrap();
+        assert_eq!(mcts.nodes[centre].prior_probability, 0.4);
+        let reply = mcts.child_for_move(centre, 3).unwrap();
+        assert_eq!(mcts.nodes[reply].prior_probability, 0.4);
+    }
+
+    #[test]
+    fn test_parallel_search_finds_winning_move() {
+        let mut mcts = MCTS::new(1.0, 800);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_parallel(state, &value_fn, &policy_fn, 0);
+
+        assert_eq!(result.best_move, 3);
+    }
+
+    #[test]
+    fn test_virtual_loss_lowers_ucb_score() {
+        let mut node = MCTSNode::new(GameState::new(), None, 0.5);
+        node.visits = 4;
+        node.total_value = 2.0;
+        let before = node.ucb_score(1.0, 10);
+
+        node.virtual_loss = 2;
+        assert!(node.ucb_score(1.0, 10) < before);
+    }
+
+    #[test]
+    fn test_batched_search_evaluates_leaves_in_batches() {
+        use std::cell::RefCell;
+
+        let mut mcts = MCTS::new(1.0, 300);
+        let batch_sizes = RefCell::new(Vec::new());
+        let evaluate_batch = |states: &[GameState]| {
+            batch_sizes.borrow_mut().push(states.len());
+            states
+                .iter()
+                .map(|_| (0.0, vec![1.0 / 7.0; 7]))
+                .collect::<Vec<_>>()
+        };
+
+        let result = mcts.search_batched(GameState::new(), &evaluate_batch, 8);
+
+        assert_eq!(result.simulations, 300);
+        let root = mcts.root().unwrap();
+        assert_eq!(mcts.nodes[root].visits, 300);
+        assert!(mcts.nodes.iter().all(|node| node.virtual_loss == 0));
+        let batch_sizes = batch_sizes.into_inner();
+        assert!(batch_sizes.iter().all(|&size| size <= 8));
+        assert!(batch_sizes.iter().filter(|&&size| size == 8).count() > 10);
+    }
+
+    #[test]
+    fn test_batched_search_expands_leaves_with_their_own_priors() {
+        let mut mcts = MCTS::new(1.0, 400);
+        let policy = vec![0.05, 0.05, 0.1, 0.5, 0.1, 0.15, 0.05];
+        let evaluate_batch = |states: &[GameState]| {
+            states
+                .iter()
+                .map(|_| (0.0, policy.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let result = mcts.search_batched(GameState::new(), &evaluate_batch, 16);
+        assert_eq!(result.simulations, 400);
+
+        // Leaves descended to again while pending aren't given stand-in
+        // priors: every child's comes from its parent's evaluated policy
+        for node in mcts.nodes.iter().filter(|node| node.parent.is_some()) {
+            let col = node.move_from_parent.unwrap() as usize;
+            assert_eq!(node.prior_probability, policy[col]);
+        }
+    }
+
+    #[test]
+    fn test_batched_search_finds_winning_move() {
+        let mut mcts = MCTS::new(1.0, 800);
+        let evaluate_batch = |states: &[GameState]| {
+            states
+                .iter()
+                .map(|_| (0.0, vec![1.0 / 7.0; 7]))
+                .collect::<Vec<_>>()
+        };
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_batched(state, &evaluate_batch, 16);
+
+        assert_eq!(result.best_move, 3);
+    }
+
+    #[test]
+    fn test_solver_proves_immediate_win() {
+        let mut mcts = MCTS::new(1.0, 500);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        assert_eq!(result.best_move, 3);
+        assert_eq!(result.proven_value, Some(1.0));
+        let root = mcts.root().unwrap();
+        let winning_child = mcts.child_for_move(root, 3).unwrap();
+        assert_eq!(mcts.nodes[winning_child].proven_value, Some(1.0));
+    }
+
+    #[test]
+    fn test_solver_proves_loss_when_every_move_loses() {
+        let mut mcts = MCTS::new(1.0, 2_000);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        // Player2 has an open three on the bottom row: both ends win
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [6, 1, 6, 2, 5, 3] {
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        assert_eq!(result.proven_value, Some(-1.0));
+        let root = mcts.root().unwrap();
+        assert!(mcts.nodes[root]
+            .children
+            .iter()
+            .all(|&child| mcts.nodes[child].proven_value == Some(-1.0)));
+    }
+
+    #[test]
+    fn test_solver_proves_node_once_every_child_is_lost() {
+        let mut mcts = MCTS::new(1.0, 7);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        // Player2 has an open three on the bottom row, so every reply loses
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [6, 1, 6, 2, 5, 3] {
+            state.make_move(col).unwrap();
+        }
+        mcts.search_with_stats(state.clone(), &value_fn, &policy_fn);
+        let root = mcts.root().unwrap();
+        assert!(mcts.nodes[root].is_fully_expanded());
+        assert!(mcts.nodes[root]
+            .children
+            .iter()
+            .all(|&child| mcts.nodes[child].proven_value.is_none()));
+
+        // Searching the same tree on proves the replies one by one until
+        // the root has none left
+        let mut result = mcts.search_with_stats(state.clone(), &value_fn, &policy_fn);
+        for _ in 0..100 {
+            if result.proven_value.is_some() {
+                break;
+            }
+            result = mcts.search_with_stats(state.clone(), &value_fn, &policy_fn);
+        }
+
+        assert_eq!(result.proven_value, Some(-1.0));
+        assert_eq!(mcts.nodes[root].proven_value, Some(-1.0));
+        assert!(mcts.nodes[root]
+            .children
+            .iter()
+            .all(|&child| mcts.nodes[child].proven_value == Some(-1.0)));
+
+        // A proven root ends every descent at once, so the tree stops growing
+        let nodes = mcts.nodes.len();
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+        assert_eq!(result.proven_value, Some(-1.0));
+        assert_eq!(result.simulations, 7);
+        assert_eq!(mcts.nodes.len(), nodes);
+    }
+
+    #[test]
+    fn test_early_stop_once_root_is_proven() {
+        let limits = SearchLimits {
+            early_stop: true,
+            ..SearchLimits::default()
+        };
+        let mut mcts = MCTS::with_limits(1.0, 100_000, limits);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [6, 1, 6, 2, 5, 3] {
+            state.make_move(col).unwrap();
+        }
+
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        assert_eq!(result.stop_reason, StopReason::BestMoveDecided);
+        assert!(result.simulations < 1_000);
+    }
+
+    #[test]
+    fn test_rollout_policy_from_str() {
+        assert_eq!("uniform".parse(), Ok(RolloutPolicy::Uniform));
+        assert_eq!("win-block".parse(), Ok(RolloutPolicy::WinBlock));
+        assert_eq!("heuristic".parse(), Ok(RolloutPolicy::HeuristicWeighted));
+        assert_eq!(
+            "truncated".parse(),
+            Ok(RolloutPolicy::Truncated {
+                depth: DEFAULT_TRUNCATED_DEPTH
+            })
+        );
+        assert_eq!(
+            "truncated:3".parse(),
+            Ok(RolloutPolicy::Truncated { depth: 3 })
+        );
+        assert_eq!("value".parse(), Ok(RolloutPolicy::ValueOnly));
+        assert!("truncated:x".parse::<RolloutPolicy>().is_err());
+        assert!("greedy".parse::<RolloutPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_tactical_move_prefers_win_then_block() {
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [0, 6, 0, 6, 1, 6] {
+            state.make_move(col).unwrap();
+        }
+        // Player1 to move: no win of its own, must block column 6
+        assert_eq!(
+            MCTS::tactical_move(&state, &state.get_valid_moves()),
+            Some(6)
+        );
+
+        state.make_move(0).unwrap();
+        state.make_move(1).unwrap();
+        // Player1 now has three stacked in column 0 and wins there first
+        state.current_player = Player::Player1;
+        assert_eq!(
+            MCTS::tactical_move(&state, &state.get_valid_moves()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_win_block_rollouts_take_immediate_wins() {
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        for policy in [RolloutPolicy::WinBlock, RolloutPolicy::HeuristicWeighted] {
+            for _ in 0..20 {
+                let mut rng = rand::thread_rng();
+                assert_eq!(
+                    MCTS::rollout(&state, policy, &mut Vec::new(), &mut rng),
+                    1.0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_rollouts_repeat_searches() {
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        let value_fn = |_: &GameState| 0.0;
+        let policy_fn = |_: &GameState| vec![1.0 / COLS as f32; COLS];
+
+        let search = |seed| {
+            let mut mcts = MCTS::new(1.0, 300);
+            mcts.rollout_policy = RolloutPolicy::Uniform;
+            mcts.seed_rollouts(seed);
+            let result = mcts.search_with_stats(state.clone(), &value_fn, &policy_fn);
+            (result.visit_counts, result.root_value)
+        };
+        assert_eq!(search(9), search(9));
+        assert_ne!(search(9), search(10));
+    }
+
+    #[test]
+    fn test_truncated_rollout_uses_static_evaluation() {
+        let mut state = GameState::new();
+        state.make_move(3).unwrap();
+
+        let value = MCTS::rollout(
+            &state,
+            RolloutPolicy::Truncated { depth: 0 },
+            &mut Vec::new(),
+            &mut rand::thread_rng(),
+        );
+        assert_eq!(value, (state.evaluate() as f32 / STATIC_EVAL_SCALE).tanh());
+    }
+
+    #[test]
+    fn test_value_only_policy_scores_leaves_with_value_fn() {
+        let mut mcts = MCTS::new(1.0, 50);
+        mcts.rollout_policy = RolloutPolicy::ValueOnly;
+        // Player1 is half a point up everywhere, scored for the side to move
+        let value_fn = |state: &GameState| match state.current_player {
+            Player::Player1 => 0.5,
+            Player::Player2 => -0.5,
+        };
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        let result = mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        assert!((result.root_value - 0.5).abs() < 1e-6);
+        assert!(result
+            .move_values
+            .iter()
+            .all(|value| value.is_some_and(|value| (value - 0.5).abs() < 1e-6)));
+    }
+
+    #[test]
+    fn test_export_tree_respects_depth_and_visit_threshold() {
+        let mut mcts = MCTS::new(1.0, 300);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+        mcts.search(GameState::new(), &value_fn, &policy_fn);
+
+        let options = TreeExportOptions {
+            max_depth: 2,
+            min_visits: 5,
+        };
+        let tree = mcts.export_tree(&options).unwrap();
+
+        assert_eq!(tree.visits, Some(300));
+        assert!(tree.mv.is_none());
+        assert!(!tree.children.is_empty());
+        for child in &tree.children {
+            assert!(child.mv.is_some());
+            assert!(child.visits.unwrap() >= 5);
+            assert!(child.ucb.is_some());
+            for grandchild in &child.children {
+                assert!(grandchild.children.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_rave_schedules() {
+        let equivalence = RaveSchedule::Equivalence { k: 300.0 };
+        assert_eq!(equivalence.beta(0.0, 10.0), 1.0);
+        assert!((equivalence.beta(300.0, 100.0) - 0.5).abs() < 1e-6);
+        assert_eq!(equivalence.beta(10.0, 0.0), 0.0);
+
+        let mse = RaveSchedule::MinimumMse { bias: 0.1 };
+        assert_eq!(mse.beta(0.0, 10.0), 1.0);
+        assert!(mse.beta(100.0, 100.0) < mse.beta(10.0, 100.0));
+
+        assert_eq!(
+            "equivalence".parse(),
+            Ok(RaveSchedule::Equivalence {
+                k: DEFAULT_RAVE_EQUIVALENCE
+            })
+        );
+        assert_eq!(
+            "mse:0.2".parse(),
+            Ok(RaveSchedule::MinimumMse { bias: 0.2 })
+        );
+        assert!("mse:high".parse::<RaveSchedule>().is_err());
+        assert!("amaf".parse::<RaveSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_rave_collects_amaf_statistics() {
+        let mut mcts = MCTS::new(1.0, 300);
+        mcts.rave = Some(RaveSchedule::Equivalence { k: 300.0 });
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        mcts.search(GameState::new(), &value_fn, &policy_fn);
+
+        let root = mcts.root().unwrap();
+        let children = &mcts.nodes[root].children;
+        let visits: u32 = children.iter().map(|&c| mcts.nodes[c].visits).sum();
+        let amaf_visits: u32 = children.iter().map(|&c| mcts.nodes[c].amaf_visits).sum();
+        // Each simulation credits its own move plus any column replayed later
+        assert!(children
+            .iter()
+            .all(|&c| mcts.nodes[c].amaf_visits >= mcts.nodes[c].visits));
+        assert!(amaf_visits > visits);
+    }
+
+    #[test]
+    fn test_amaf_is_off_without_rave() {
+        let mut mcts = MCTS::new(1.0, 100);
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        mcts.search(GameState::new(), &value_fn, &policy_fn);
+
+        assert!(mcts.nodes.iter().all(|node| node.amaf_visits == 0));
+    }
+
+    #[test]
+    fn test_rave_search_finds_winning_move() {
+        let mut mcts = MCTS::new(1.0, 400);
+        mcts.rave = Some(RaveSchedule::MinimumMse { bias: 0.1 });
+        let value_fn = |_state: &GameState| 0.0;
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in 0..3 {
+            state.make_move(col).unwrap();
+            state.make_move(col).unwrap();
+        }
+
+        let (best_move, _) = mcts.search(state, &value_fn, &policy_fn);
+
+        assert_eq!(best_move, 3);
+    }
+
+    #[test]
+    fn test_search_plays_for_both_colours() {
+        use crate::Cell;
+
+        // Values the centre column for the side to move, so whoever is to
+        // move should take it
+        let value_fn = |state: &GameState| {
+            let own = Cell::from_player(state.current_player);
+            let centre: f32 = state.board[3]
+                .iter()
+                .map(|&cell| match cell {
+                    Cell::Empty => 0.0,
+                    cell if cell == own => 0.5,
+                    _ => -0.5,
+                })
+                .sum();
+            centre.clamp(-1.0, 1.0)
+        };
+        let policy_fn = |_state: &GameState| vec![1.0 / 7.0; 7];
+        let evaluate_batch = |states: &[GameState]| {
+            states
+                .iter()
+                .map(|state| (value_fn(state), policy_fn(state)))
+                .collect::<Vec<_>>()
+        };
+
+        for first in [Player::Player1, Player::Player2] {
+            let mut state = GameState::new();
+            state.current_player = first;
+
+            let mut mcts = MCTS::new(1.0, 400);
+            mcts.rollout_policy = RolloutPolicy::ValueOnly;
+            let result = mcts.search_with_stats(state.clone(), &value_fn, &policy_fn);
+            assert_eq!(result.best_move, 3, "{:?} to move", first);
+            assert!(
+                result.move_values[3].unwrap() > 0.0,
+                "{:?}",
+                result.move_values
+            );
+            assert!(result.root_value > 0.0);
+
+            let result = MCTS::new(1.0, 400).search_batched(state, &evaluate_batch, 8);
+            assert_eq!(result.best_move, 3, "{:?} to move (batched)", first);
+        }
+    }
 }
@@ -1,6 +1,11 @@
-use super::features::GameFeatures;
-use super::neural_network::{NetworkConfig, NeuralNetwork};
+use super::features::FeatureSet;
+use super::mcts::LeafEvaluation;
+use super::model::{ModelFile, NetworkSpec, TrainingMetadata};
+use super::neural_network::{Architecture, Network, NetworkConfig, OutputActivation};
+use super::normalization::FeatureNormalizer;
+use super::quantization::Precision;
 use super::{GameState, COLS};
+use ndarray::{Array1, Array2};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -26,29 +31,132 @@ pub struct MLResponse {
     pub diagnostics: MLDiagnostics,
 }
 
+/// Where the trainers look for a model to continue from.
+pub const DEFAULT_MODEL_PATH: &str = "ml/data/weights/ml_ai_weights.json";
+
+/// Hidden layers of a fresh `MLAI`, matching `ml/config/training.json`.
+pub const DEFAULT_HIDDEN_SIZES: [usize; 4] = [256, 128, 64, 32];
+
+#[derive(Clone)]
 pub struct MLAI {
-    value_network: NeuralNetwork,
-    policy_network: NeuralNetwork,
+    value_network: Network,
+    policy_network: Network,
+    feature_set: FeatureSet,
+    normalizer: Option<FeatureNormalizer>,
 }
 
 impl MLAI {
+    /// Untrained dense networks over `RelativeFeatures`.
     pub fn new() -> Self {
-        // Create networks with appropriate sizes for Connect Four
-        let value_config = NetworkConfig {
-            input_size: 100,
-            hidden_sizes: vec![64, 32],
-            output_size: 1,
-        };
-        let policy_config = NetworkConfig {
-            input_size: 100,
-            hidden_sizes: vec![64, 32],
-            output_size: 7,
+        Self::with_feature_set(FeatureSet::RelativeFeatures)
+    }
+
+    /// Untrained dense networks over `feature_set`.
+    pub fn with_feature_set(feature_set: FeatureSet) -> Self {
+        Self::with_hidden_sizes(feature_set, &DEFAULT_HIDDEN_SIZES)
+    }
+
+    /// Untrained dense networks over `feature_set` with the given hidden
+    /// layers.
+    pub fn with_hidden_sizes(feature_set: FeatureSet, hidden_sizes: &[usize]) -> Self {
+        let dense = |output_size, output_activation| {
+            Architecture::Dense(NetworkConfig {
+                input_size: feature_set.size(),
+                hidden_sizes: hidden_sizes.to_vec(),
+                output_size,
+                output_activation,
+            })
         };
 
         MLAI {
-            value_network: NeuralNetwork::new(value_config),
-            policy_network: NeuralNetwork::new(policy_config),
+            value_network: Network::new(&dense(1, OutputActivation::Tanh)),
+            policy_network: Network::new(&dense(COLS, OutputActivation::Softmax)),
+            feature_set,
+            normalizer: None,
+        }
+    }
+
+    /// Builds the networks the file describes; fails if it does not
+    /// validate.
+    pub fn from_model_file(model: &ModelFile) -> Result<Self, String> {
+        model.validate()?;
+        let mut value_network = Network::new(&model.value_network.architecture);
+        value_network.load_weights(&model.value_network.weights)?;
+        let mut policy_network = Network::new(&model.policy_network.architecture);
+        policy_network.load_weights(&model.policy_network.weights)?;
+
+        Ok(MLAI {
+            value_network,
+            policy_network,
+            feature_set: model.features.set,
+            normalizer: model.normalizer.clone(),
+        })
+    }
+
+    /// Loads a JSON or binary model with f32 inference.
+    pub fn load_model(path: &str) -> Result<Self, String> {
+        Self::from_model_file(&ModelFile::load(path)?)
+    }
+
+    /// Builds from a binary model, running inference at the precision its
+    /// weights were stored at.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, String> {
+        let mut ai = Self::from_model_file(&ModelFile::from_binary(bytes)?)?;
+        ai.quantize(ModelFile::binary_precision(bytes)?);
+        Ok(ai)
+    }
+
+    /// Switches both networks' forward passes to `precision`.
+    pub fn quantize(&mut self, precision: Precision) {
+        self.value_network.quantize(precision);
+        self.policy_network.quantize(precision);
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.value_network.precision()
+    }
+
+    pub fn to_model_file(&self, metadata: TrainingMetadata) -> ModelFile {
+        let spec = |network: &Network| NetworkSpec {
+            architecture: network.architecture(),
+            weights: network.get_weights(),
+        };
+        let model = ModelFile::new(
+            self.feature_set,
+            spec(&self.value_network),
+            spec(&self.policy_network),
+            metadata,
+        );
+        match &self.normalizer {
+            Some(normalizer) => model.with_normalizer(normalizer.clone()),
+            None => model,
+        }
+    }
+
+    pub fn feature_set(&self) -> FeatureSet {
+        self.feature_set
+    }
+
+    pub fn normalizer(&self) -> Option<&FeatureNormalizer> {
+        self.normalizer.as_ref()
+    }
+
+    /// Network input for `state`: its features, scaled by the model's
+    /// normalizer when it has one.
+    pub fn encode(&self, state: &GameState) -> Array1<f32> {
+        let mut features = self.feature_set.encode(state);
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.apply(features.view_mut());
+        }
+        features
+    }
+
+    pub fn encode_batch(&self, states: &[GameState]) -> Array2<f32> {
+        let mut features = self.feature_set.encode_batch(states);
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.apply_batch(&mut features);
         }
+        features
     }
 
     pub fn get_best_move(&mut self, state: &GameState) -> MLResponse {
@@ -83,9 +191,11 @@ impl MLAI {
         }
 
         // Get current position evaluation
-        let features = GameFeatures::from_game_state(state);
-        let value_output = self.value_network.forward(&features.to_array());
-        let policy_outputs = self.policy_network.forward(&features.to_array());
+        let features = self.encode(state);
+        let value_output = self.side_to_move_value(self.value_network.forward(&features)[0], state);
+        let policy_outputs = self
+            .policy_network
+            .forward_masked(&features, &legal_move_mask(state));
 
         let mut move_evaluations = Vec::new();
         let mut best_move = valid_moves[0];
@@ -94,10 +204,10 @@ impl MLAI {
         for &col in &valid_moves {
             let mut next_state = state.clone();
             if next_state.make_move(col).is_ok() {
-                let next_features = GameFeatures::from_game_state(&next_state);
-                let next_value = self.value_network.forward(&next_features.to_array());
+                // The next position is scored for the opponent, who moves there
+                let next_value = -self.evaluate_position(&next_state);
 
-                let mut score = next_value[0] * 0.7 + policy_outputs[col as usize] * 0.3;
+                let mut score = next_value * 0.7 + policy_outputs[col as usize] * 0.3;
 
                 // Use evolved genetic parameters for center control bonuses
                 let center_control_weight = state.genetic_params.center_control_weight as f32;
@@ -162,38 +272,95 @@ impl MLAI {
 
         MLResponse {
             r#move: Some(best_move),
-            evaluation: value_output[0],
+            evaluation: value_output,
             thinking: format!(
                 "ML AI chose column {} with score {:.3}. Value network: {:.3}",
-                best_move, best_score, value_output[0]
+                best_move, best_score, value_output
             ),
             diagnostics: MLDiagnostics {
                 valid_moves,
                 move_evaluations,
-                value_network_output: value_output[0],
+                value_network_output: value_output,
                 policy_network_outputs: policy_outputs.to_vec(),
             },
         }
     }
 
+    /// Value network output for the side to move, from -1 (lost) to 1 (won).
+    pub fn evaluate_position(&self, state: &GameState) -> f32 {
+        let value = self.value_network.forward(&self.encode(state));
+        self.side_to_move_value(value[0], state)
+    }
 
+    /// Move probabilities from the policy network, zero for full columns.
+    pub fn policy(&self, state: &GameState) -> Vec<f32> {
+        self.policy_network
+            .forward_masked(&self.encode(state), &legal_move_mask(state))
+            .to_vec()
+    }
 
-    pub fn evaluate_position(&self, state: &GameState) -> f32 {
-        let features = GameFeatures::from_game_state(state);
-        let value = self.value_network.forward(&features.to_array());
-        value[0]
+    /// Value and policy for every state with one forward pass per network.
+    pub fn evaluate_batch(&self, states: &[GameState]) -> Vec<LeafEvaluation> {
+        if states.is_empty() {
+            return Vec::new();
+        }
+
+        let features = self.encode_batch(states);
+        let values = self.value_network.forward_batch(&features);
+        let legal_moves = Array2::from_shape_fn((states.len(), COLS), |(i, col)| {
+            states[i].can_place_in_column(col)
+        });
+        let policies = self
+            .policy_network
+            .forward_batch_masked(&features, &legal_moves);
+
+        values
+            .rows()
+            .into_iter()
+            .zip(policies.rows())
+            .zip(states)
+            .map(|((value, policy), state)| {
+                (self.side_to_move_value(value[0], state), policy.to_vec())
+            })
+            .collect()
+    }
+
+    // Flips values from networks that score positions for Player1
+    fn side_to_move_value(&self, value: f32, state: &GameState) -> f32 {
+        let mover = state.current_player;
+        if self.feature_set.value_perspective(mover) == mover {
+            value
+        } else {
+            -value
+        }
     }
 
-    pub fn load_weights(&mut self, value_weights: &[f32], policy_weights: &[f32]) {
-        self.value_network.load_weights(value_weights);
-        self.policy_network.load_weights(policy_weights);
+    /// Replaces the weights of the current networks, rejecting vectors that
+    /// do not fit them.
+    pub fn load_weights(
+        &mut self,
+        value_weights: &[f32],
+        policy_weights: &[f32],
+    ) -> Result<(), String> {
+        self.value_network
+            .load_weights(value_weights)
+            .map_err(|e| format!("value network: {}", e))?;
+        self.policy_network
+            .load_weights(policy_weights)
+            .map_err(|e| format!("policy network: {}", e))
     }
 
-    pub fn get_networks(&self) -> (&NeuralNetwork, &NeuralNetwork) {
+    pub fn get_networks(&self) -> (&Network, &Network) {
         (&self.value_network, &self.policy_network)
     }
 }
 
+fn legal_move_mask(state: &GameState) -> Vec<bool> {
+    (0..COLS)
+        .map(|col| state.can_place_in_column(col))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,8 +368,107 @@ mod tests {
     #[test]
     fn test_ml_ai_new() {
         let ai = MLAI::new();
-        assert!(ai.value_network.num_layers() > 0);
-        assert!(ai.policy_network.num_layers() > 0);
+        match ai.value_network.architecture() {
+            Architecture::Dense(config) => {
+                assert_eq!(config.hidden_sizes, DEFAULT_HIDDEN_SIZES.to_vec())
+            }
+            architecture => panic!("unexpected architecture {:?}", architecture),
+        }
+        assert_eq!(ai.feature_set(), FeatureSet::RelativeFeatures);
+    }
+
+    #[test]
+    fn test_ml_ai_round_trips_through_model_file() {
+        let ai = MLAI::new();
+        let model = ai.to_model_file(TrainingMetadata::default());
+        let restored =
+            MLAI::from_model_file(&ModelFile::from_json(&model.to_json()).unwrap()).unwrap();
+
+        let mut state = GameState::new();
+        state.make_move(3).unwrap();
+        assert_eq!(
+            restored.evaluate_position(&state),
+            ai.evaluate_position(&state)
+        );
+        assert_eq!(restored.policy(&state), ai.policy(&state));
+    }
+
+    #[test]
+    fn test_ml_ai_quantized_inference_stays_close_to_f32() {
+        use crate::quantization::measure_drift;
+
+        // Clamped inputs keep random weights' quantisation error in check
+        let ai = MLAI::with_feature_set(FeatureSet::GameFeatures);
+        let mut state = GameState::new();
+        let mut states = vec![state.clone()];
+        for col in [3, 3, 2, 4, 0] {
+            state.make_move(col).unwrap();
+            states.push(state.clone());
+        }
+
+        let model = ai.to_model_file(TrainingMetadata::default());
+        for precision in [Precision::F16, Precision::Int8] {
+            let quantized = MLAI::from_binary(&model.to_binary(precision)).unwrap();
+            assert_eq!(quantized.precision(), precision);
+            let drift = measure_drift(&ai, &quantized, &states);
+            assert_eq!(drift.positions, states.len());
+            assert!(drift.max_value_error < 0.05, "{}: {:?}", precision, drift);
+            assert!(drift.max_policy_error < 0.05, "{}: {:?}", precision, drift);
+        }
+
+        let mut f32_again = MLAI::from_binary(&model.to_binary(Precision::Int8)).unwrap();
+        let (value, policy) = f32_again.get_networks();
+        let (value_weights, policy_weights) = (value.get_weights(), policy.get_weights());
+        f32_again
+            .load_weights(&value_weights, &policy_weights)
+            .unwrap();
+        assert_eq!(f32_again.precision(), Precision::F32);
+    }
+
+    #[test]
+    fn test_ml_ai_builds_conv_networks_from_model_file() {
+        use crate::neural_network::{ConvNetworkConfig, Network};
+
+        let spec = |output_size, output_activation| {
+            let mut config = ConvNetworkConfig::connect_four(output_size, output_activation);
+            config.filters = 4;
+            config.residual_blocks = 1;
+            let architecture = Architecture::Conv(config);
+            NetworkSpec {
+                weights: Network::new(&architecture).get_weights(),
+                architecture,
+            }
+        };
+        let model = ModelFile::new(
+            FeatureSet::BoardPlanes,
+            spec(1, OutputActivation::Tanh),
+            spec(COLS, OutputActivation::Softmax),
+            TrainingMetadata::default(),
+        );
+
+        let ai = MLAI::from_model_file(&model).unwrap();
+        assert_eq!(ai.feature_set(), FeatureSet::BoardPlanes);
+        let value = ai.evaluate_position(&GameState::new());
+        assert!((-1.0..=1.0).contains(&value));
+        assert!((ai.policy(&GameState::new()).iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_ml_ai_rejects_mismatched_weights() {
+        let mut ai = MLAI::new();
+        let (value, policy) = ai.get_networks();
+        let value_weights = value.get_weights();
+        let policy_weights = policy.get_weights();
+
+        assert!(ai
+            .load_weights(&value_weights[1..], &policy_weights)
+            .is_err());
+        assert!(ai.load_weights(&value_weights, &policy_weights).is_ok());
+
+        // A model over a different feature set than its networks expect
+        let mut model = ai.to_model_file(TrainingMetadata::default());
+        model.features = FeatureSet::BoardPlanes.into();
+        assert!(MLAI::from_model_file(&model).is_err());
     }
 
     #[test]
@@ -276,6 +542,41 @@ mod tests {
         assert!(!evaluation.is_infinite());
     }
 
+    #[test]
+    fn test_ml_ai_evaluate_batch_matches_single_evaluation() {
+        let ai = MLAI::new();
+        let mut states = vec![GameState::new()];
+        for col in [3, 2, 4] {
+            let mut next = states.last().unwrap().clone();
+            next.make_move(col).unwrap();
+            states.push(next);
+        }
+
+        let evaluations = ai.evaluate_batch(&states);
+        assert_eq!(evaluations.len(), states.len());
+        for (state, (value, policy)) in states.iter().zip(&evaluations) {
+            assert!((value - ai.evaluate_position(state)).abs() < 1e-6);
+            assert_eq!(policy.len(), COLS);
+            assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+        assert!(ai.evaluate_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_ml_ai_policy_ignores_full_columns() {
+        let ai = MLAI::new();
+        let mut state = GameState::new();
+        for _ in 0..crate::ROWS {
+            state.make_move(0).unwrap();
+        }
+
+        let policy = ai.policy(&state);
+        assert_eq!(policy[0], 0.0);
+        assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        let (_, batch_policy) = &ai.evaluate_batch(&[state])[0];
+        assert_eq!(batch_policy[0], 0.0);
+    }
+
     #[test]
     fn test_ml_ai_center_preference() {
         let mut ai = MLAI::new();
@@ -304,4 +605,59 @@ mod tests {
             );
         }
     }
+
+    // Dense heads without hidden layers: the value only rewards a disc in
+    // the bottom cell of column 0 and the policy is uniform
+    fn corner_model(feature_set: FeatureSet) -> MLAI {
+        let inputs = feature_set.size();
+        let network = |output_size, output_activation, weights| NetworkSpec {
+            architecture: Architecture::Dense(NetworkConfig {
+                input_size: inputs,
+                hidden_sizes: vec![],
+                output_size,
+                output_activation,
+            }),
+            weights,
+        };
+        let mut value_weights = vec![0.0; inputs + 1];
+        value_weights[crate::ROWS - 1] = 5.0;
+        let model = ModelFile::new(
+            feature_set,
+            network(1, OutputActivation::Tanh, value_weights),
+            network(
+                COLS,
+                OutputActivation::Softmax,
+                vec![0.0; (inputs + 1) * COLS],
+            ),
+            TrainingMetadata::default(),
+        );
+        MLAI::from_model_file(&model).unwrap()
+    }
+
+    #[test]
+    fn test_ml_ai_plays_both_colours() {
+        use crate::mcts::{RolloutPolicy, MCTS};
+        use crate::Player;
+
+        for feature_set in [FeatureSet::GameFeatures, FeatureSet::RelativeFeatures] {
+            let mut ai = corner_model(feature_set);
+            for first in [Player::Player1, Player::Player2] {
+                let mut state = GameState::new();
+                state.current_player = first;
+                let context = format!("{} playing {:?}", feature_set, first);
+                assert_eq!(ai.get_best_move(&state).r#move, Some(0), "{}", context);
+
+                let mut mcts = MCTS::new(1.0, 200);
+                mcts.rollout_policy = RolloutPolicy::ValueOnly;
+                let evaluate_batch = |states: &[GameState]| ai.evaluate_batch(states);
+                let result = mcts.search_batched(state.clone(), &evaluate_batch, 8);
+                assert_eq!(result.best_move, 0, "{}", context);
+
+                // Values are now the opponent's, who is lost
+                state.make_move(0).unwrap();
+                assert!(ai.evaluate_position(&state) < -0.9, "{}", context);
+                assert!(ai.evaluate_batch(&[state])[0].0 < -0.9, "{}", context);
+            }
+        }
+    }
 }
@@ -0,0 +1,1099 @@
+//! Versioned, self-describing model files.
+//!
+//! A `ModelFile` carries everything needed to rebuild `MLAI`'s networks:
+//! each network's architecture and output activation, the feature set it was
+//! trained on and the normalizer fitted to it, training metadata, and a
+//! checksum over the weights. Files from
+//! before the format existed, with bare `value_network.weights` arrays, and
+//! PyTorch exports holding each network's `state_dict` are converted on load.
+//!
+//! Models can also be written in a compact binary form: a short header, the
+//! JSON description without weights, then the weights as little-endian f32,
+//! f16 or block-scaled int8. `ModelFile::load` accepts either form.
+
+use crate::features::FeatureSet;
+use crate::neural_network::{Architecture, NetworkConfig, OutputActivation};
+use crate::normalization::FeatureNormalizer;
+use crate::quantization::{decode_weights, encode_weights, Precision};
+use crate::{COLS, ROWS};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+pub const FORMAT_NAME: &str = "connect-four-model";
+/// Version 2 added the optional feature normalizer.
+pub const FORMAT_VERSION: u32 = 2;
+pub const BINARY_MAGIC: &[u8; 4] = b"C4MF";
+pub const BINARY_VERSION: u32 = 1;
+
+// JSON header of a binary model: the model without its weights, which
+// follow the header in `precision`
+#[derive(Serialize, Deserialize)]
+struct BinaryHeader {
+    precision: Precision,
+    #[serde(flatten)]
+    model: ModelFile,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeatureSpec {
+    pub set: FeatureSet,
+    pub version: u32,
+    pub size: usize,
+}
+
+impl From<FeatureSet> for FeatureSpec {
+    fn from(set: FeatureSet) -> Self {
+        FeatureSpec {
+            set,
+            version: set.version(),
+            size: set.size(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSpec {
+    pub architecture: Architecture,
+    pub weights: Vec<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trainer: Option<String>,
+    /// When training finished; RFC 3339 in files this crate writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epochs: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_loss: Option<f32>,
+    /// Anything else the trainer wants to keep, such as its configuration.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelFile {
+    pub format: String,
+    pub version: u32,
+    pub features: FeatureSpec,
+    /// Scaling applied to the features before both networks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalizer: Option<FeatureNormalizer>,
+    pub value_network: NetworkSpec,
+    pub policy_network: NetworkSpec,
+    #[serde(default)]
+    pub metadata: TrainingMetadata,
+    pub checksum: String,
+}
+
+impl ModelFile {
+    pub fn new(
+        features: FeatureSet,
+        value_network: NetworkSpec,
+        policy_network: NetworkSpec,
+        metadata: TrainingMetadata,
+    ) -> Self {
+        let mut model = ModelFile {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+            features: features.into(),
+            normalizer: None,
+            value_network,
+            policy_network,
+            metadata,
+            checksum: String::new(),
+        };
+        model.checksum = model.compute_checksum();
+        model
+    }
+
+    /// Attaches the normalizer the networks were trained behind.
+    pub fn with_normalizer(mut self, normalizer: FeatureNormalizer) -> Self {
+        self.normalizer = Some(normalizer);
+        self.checksum = self.compute_checksum();
+        self
+    }
+
+    /// FNV-1a over the little-endian bytes of the value weights followed by
+    /// the policy weights and, when there is one, the normalizer's means and
+    /// deviations, so other tools can reproduce it without matching this
+    /// crate's JSON formatting.
+    pub fn compute_checksum(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let normalizer = self
+            .normalizer
+            .iter()
+            .flat_map(|normalizer| normalizer.mean.iter().chain(&normalizer.std));
+        let weights = self
+            .value_network
+            .weights
+            .iter()
+            .chain(&self.policy_network.weights)
+            .chain(normalizer);
+        for weight in weights {
+            for byte in weight.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        format!("fnv1a64:{:016x}", hash)
+    }
+
+    /// Checks that the file can be used as-is: a supported format version,
+    /// the current version of its feature set, a normalizer sized to it,
+    /// networks whose shapes match their weights and the value/policy roles,
+    /// and an intact checksum.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format != FORMAT_NAME {
+            return Err(format!("not a model file (format {:?})", self.format));
+        }
+        if self.version > FORMAT_VERSION {
+            return Err(format!(
+                "model format version {} is newer than the supported version {}",
+                self.version, FORMAT_VERSION
+            ));
+        }
+
+        let set = self.features.set;
+        if self.features.version != set.version() || self.features.size != set.size() {
+            return Err(format!(
+                "model was trained on {} v{} ({} inputs), this build provides v{} ({} inputs)",
+                set,
+                self.features.version,
+                self.features.size,
+                set.version(),
+                set.size()
+            ));
+        }
+
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.validate(set.size())?;
+        }
+
+        let roles = [
+            ("value", &self.value_network, 1),
+            ("policy", &self.policy_network, COLS),
+        ];
+        for (role, network, output_size) in roles {
+            let architecture = &network.architecture;
+            if architecture.input_size() != set.size() {
+                return Err(format!(
+                    "{} network takes {} inputs but {} has {}",
+                    role,
+                    architecture.input_size(),
+                    set,
+                    set.size()
+                ));
+            }
+            if architecture.output_size() != output_size {
+                return Err(format!(
+                    "{} network has {} outputs, expected {}",
+                    role,
+                    architecture.output_size(),
+                    output_size
+                ));
+            }
+            if network.weights.len() != architecture.total_weights() {
+                return Err(format!(
+                    "{} network has {} weights, its architecture needs {}",
+                    role,
+                    network.weights.len(),
+                    architecture.total_weights()
+                ));
+            }
+        }
+        if self.value_network.architecture.output_activation() == OutputActivation::Softmax {
+            return Err("value network cannot use a softmax output".to_string());
+        }
+        if self.policy_network.architecture.output_activation() != OutputActivation::Softmax {
+            return Err("policy network must use a softmax output".to_string());
+        }
+
+        let checksum = self.compute_checksum();
+        if self.checksum != checksum {
+            return Err(format!(
+                "checksum mismatch: file says {}, weights hash to {}",
+                self.checksum, checksum
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parses and validates a model, converting the pre-format layout.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| format!("invalid model JSON: {}", e))?;
+        let model = if value.get("format").is_some() {
+            serde_json::from_value(value).map_err(|e| format!("invalid model file: {}", e))?
+        } else if is_state_dict(&value["value_network"]) {
+            Self::from_state_dict(&value, FeatureSet::GameFeatures)?
+        } else {
+            Self::from_legacy(&value)?
+        };
+        model.validate()?;
+        Ok(model)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Binary encoding with weights stored at `precision`. The checksum is
+    /// recomputed over the weights as they will decode, so a quantised file
+    /// validates on its own terms.
+    pub fn to_binary(&self, precision: Precision) -> Vec<u8> {
+        let value_bytes = encode_weights(&self.value_network.weights, precision);
+        let policy_bytes = encode_weights(&self.policy_network.weights, precision);
+
+        let mut model = self.clone();
+        let decode = |bytes: &[u8], count| decode_weights(bytes, count, precision).unwrap();
+        model.value_network.weights = decode(&value_bytes, self.value_network.weights.len());
+        model.policy_network.weights = decode(&policy_bytes, self.policy_network.weights.len());
+        model.checksum = model.compute_checksum();
+        model.value_network.weights.clear();
+        model.policy_network.weights.clear();
+        let header = serde_json::to_vec(&BinaryHeader { precision, model }).unwrap();
+
+        let mut bytes =
+            Vec::with_capacity(12 + header.len() + value_bytes.len() + policy_bytes.len());
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&value_bytes);
+        bytes.extend_from_slice(&policy_bytes);
+        bytes
+    }
+
+    /// Parses and validates a binary model, expanding its weights to f32.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, String> {
+        let (precision, mut model, header_end) = Self::binary_header(bytes)?;
+
+        let value_count = model.value_network.architecture.total_weights();
+        let policy_count = model.policy_network.architecture.total_weights();
+        let value_end = header_end + precision.encoded_len(value_count);
+        let payload_end = value_end + precision.encoded_len(policy_count);
+        if bytes.len() != payload_end {
+            return Err(format!(
+                "binary model should be {} bytes, got {}",
+                payload_end,
+                bytes.len()
+            ));
+        }
+        model.value_network.weights =
+            decode_weights(&bytes[header_end..value_end], value_count, precision)?;
+        model.policy_network.weights =
+            decode_weights(&bytes[value_end..], policy_count, precision)?;
+        model.validate()?;
+        Ok(model)
+    }
+
+    /// Precision a binary model's weights are stored at, from its header.
+    pub fn binary_precision(bytes: &[u8]) -> Result<Precision, String> {
+        Self::binary_header(bytes).map(|(precision, ..)| precision)
+    }
+
+    // Precision, weightless model and the offset where the weights start
+    fn binary_header(bytes: &[u8]) -> Result<(Precision, ModelFile, usize), String> {
+        if bytes.len() < 12 || &bytes[..4] != BINARY_MAGIC {
+            return Err("not a binary model file".to_string());
+        }
+        let version = read_u32(bytes, 4);
+        if version > BINARY_VERSION {
+            return Err(format!(
+                "binary model version {} is newer than the supported version {}",
+                version, BINARY_VERSION
+            ));
+        }
+        let header_end = 12 + read_u32(bytes, 8) as usize;
+        let header = bytes
+            .get(12..header_end)
+            .ok_or("binary model header is truncated")?;
+        let header: BinaryHeader = serde_json::from_slice(header)
+            .map_err(|e| format!("invalid binary model header: {}", e))?;
+        Ok((header.precision, header.model, header_end))
+    }
+
+    /// Loads a JSON or binary model, telling them apart by the binary magic.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let model = if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_binary(&bytes)
+        } else {
+            std::str::from_utf8(&bytes)
+                .map_err(|e| format!("model is neither binary nor UTF-8 JSON: {}", e))
+                .and_then(Self::from_json)
+        };
+        model.map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save_binary(&self, path: &str, precision: Precision) -> Result<(), String> {
+        std::fs::write(path, self.to_binary(precision))
+            .map_err(|e| format!("failed to write {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_json()).map_err(|e| format!("failed to write {}: {}", path, e))
+    }
+
+    /// Converts a PyTorch export: `value_network` and `policy_network` each
+    /// hold a `state_dict` of `nn.Linear` tensors, as written by
+    /// `train_pytorch.py`. The shapes define the architecture, which must
+    /// agree with `metadata.architecture` when the file has one. Networks
+    /// over the 42 board cells, as `simple_train.py` writes, read the board
+    /// like `from_legacy_weights` and move onto `GameFeatures`.
+    pub fn from_state_dict(value: &Value, features: FeatureSet) -> Result<Self, String> {
+        let network = |key: &str, output_activation| {
+            let state_dict = &value[key];
+            let config = network_config_from_state_dict(state_dict, output_activation)
+                .map_err(|e| format!("{}: {}", key, e))?;
+            let weights = weights_from_state_dict(state_dict, &config)
+                .map_err(|e| format!("{}: {}", key, e))?;
+            Ok::<_, String>((config, weights))
+        };
+        let (value_config, value_weights) = network("value_network", OutputActivation::Tanh)?;
+        let (policy_config, policy_weights) = network("policy_network", OutputActivation::Softmax)?;
+
+        let exported = &value["metadata"];
+        let architecture = &exported["architecture"];
+        if let Some(input_size) = architecture["input_size"].as_u64() {
+            if input_size as usize != value_config.input_size {
+                return Err(format!(
+                    "metadata says {} inputs but the tensors take {}",
+                    input_size, value_config.input_size
+                ));
+            }
+        }
+        if let Some(hidden_sizes) = architecture.get("hidden_sizes") {
+            let hidden_sizes: Vec<usize> = serde_json::from_value(hidden_sizes.clone())
+                .map_err(|e| format!("invalid metadata hidden_sizes: {}", e))?;
+            if hidden_sizes != value_config.hidden_sizes {
+                return Err(format!(
+                    "metadata says hidden sizes {:?} but the tensors have {:?}",
+                    hidden_sizes, value_config.hidden_sizes
+                ));
+            }
+        }
+
+        let mut metadata = TrainingMetadata {
+            trainer: exported["model_type"].as_str().map(str::to_string),
+            created_at: exported["saved_at"].as_str().map(str::to_string),
+            epochs: exported["epochs_completed"]
+                .as_u64()
+                .map(|epochs| epochs as usize),
+            final_loss: exported["final_val_loss"].as_f64().map(|loss| loss as f32),
+            ..TrainingMetadata::default()
+        };
+        metadata
+            .extra
+            .insert("pytorch_metadata".to_string(), exported.clone());
+
+        let board = ROWS * COLS;
+        let network = |config: NetworkConfig, weights: Vec<f32>| {
+            if features == FeatureSet::GameFeatures && config.input_size == board {
+                board_network_on_game_features(config, &weights)
+            } else {
+                NetworkSpec {
+                    architecture: Architecture::Dense(config),
+                    weights,
+                }
+            }
+        };
+        let model = ModelFile::new(
+            features,
+            network(value_config, value_weights),
+            network(policy_config, policy_weights),
+            metadata,
+        );
+        model.validate()?;
+        Ok(model)
+    }
+
+    // Old trainers wrote `metadata.architecture.{input_size, hidden_sizes}`
+    // and one flat weight array per network, always over `GameFeatures`.
+    // Files without the architecture hold the board networks
+    // `from_legacy_weights` reads
+    fn from_legacy(value: &Value) -> Result<Self, String> {
+        let architecture = &value["metadata"]["architecture"];
+        if architecture.is_null() && value["value_network"]["weights"].is_array() {
+            return Self::from_legacy_weights(
+                &legacy_flat_weights(value, "value_network")?,
+                &legacy_flat_weights(value, "policy_network")?,
+            );
+        }
+        let input_size = architecture["input_size"]
+            .as_u64()
+            .ok_or("not a model file: no format or legacy architecture")?
+            as usize;
+        let hidden_sizes = architecture["hidden_sizes"]
+            .as_array()
+            .ok_or("legacy model has no hidden_sizes")?
+            .iter()
+            .map(|size| size.as_u64().map(|size| size as usize))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("legacy hidden_sizes must be integers")?;
+
+        let network = |key: &str, output_size, output_activation| {
+            let weights = legacy_flat_weights(value, key)?;
+            Ok::<_, String>(NetworkSpec {
+                architecture: Architecture::Dense(NetworkConfig {
+                    input_size,
+                    hidden_sizes: hidden_sizes.clone(),
+                    output_size,
+                    output_activation,
+                }),
+                weights,
+            })
+        };
+        let value_network = network("value_network", 1, OutputActivation::Tanh)?;
+        let policy_network = network("policy_network", COLS, OutputActivation::Softmax)?;
+
+        let legacy_metadata = &value["metadata"];
+        let mut metadata = TrainingMetadata {
+            trainer: legacy_metadata["model_type"].as_str().map(str::to_string),
+            created_at: legacy_metadata["saved_at"].as_str().map(str::to_string),
+            epochs: legacy_metadata["epochs_completed"]
+                .as_u64()
+                .map(|epochs| epochs as usize),
+            final_loss: legacy_metadata["final_val_loss"]
+                .as_f64()
+                .map(|loss| loss as f32),
+            ..TrainingMetadata::default()
+        };
+        metadata
+            .extra
+            .insert("legacy_metadata".to_string(), legacy_metadata.clone());
+
+        Ok(ModelFile::new(
+            FeatureSet::GameFeatures,
+            value_network,
+            policy_network,
+            metadata,
+        ))
+    }
+
+    /// Converts the flat vectors of the 42-input [64, 32] board networks
+    /// shipped as `ml_ai_weights_*.json`: PyTorch `nn.Linear` layers, each
+    /// weight matrix (by output) followed by its bias, reading the board row
+    /// by row from the bottom with Player1's discs as 1. Their first layer
+    /// moves onto `GameFeatures`' board slots, ignoring the other features.
+    pub fn from_legacy_weights(
+        value_weights: &[f32],
+        policy_weights: &[f32],
+    ) -> Result<Self, String> {
+        let value_network = legacy_board_network(value_weights, 1, OutputActivation::Tanh)
+            .map_err(|e| format!("value network: {}", e))?;
+        let policy_network = legacy_board_network(policy_weights, COLS, OutputActivation::Softmax)
+            .map_err(|e| format!("policy network: {}", e))?;
+        let metadata = TrainingMetadata {
+            trainer: Some("legacy_board_weights".to_string()),
+            ..TrainingMetadata::default()
+        };
+        let model = ModelFile::new(
+            FeatureSet::GameFeatures,
+            value_network,
+            policy_network,
+            metadata,
+        );
+        model.validate()?;
+        Ok(model)
+    }
+}
+
+fn legacy_flat_weights(value: &Value, key: &str) -> Result<Vec<f32>, String> {
+    value[key]["weights"]
+        .as_array()
+        .ok_or(format!("legacy model has no flat {}.weights array", key))?
+        .iter()
+        .map(|w| w.as_f64().map(|w| w as f32))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(format!("legacy {}.weights must be numbers", key))
+}
+
+const LEGACY_HIDDEN_SIZES: [usize; 2] = [64, 32];
+
+// Rebuilds a legacy board network in this crate's layout, weights by input
+// then biases per layer, over `GameFeatures`
+fn legacy_board_network(
+    flat: &[f32],
+    output_size: usize,
+    output_activation: OutputActivation,
+) -> Result<NetworkSpec, String> {
+    let board = ROWS * COLS;
+    let mut sizes = vec![board];
+    sizes.extend(LEGACY_HIDDEN_SIZES);
+    sizes.push(output_size);
+    let expected: usize = sizes.windows(2).map(|size| (size[0] + 1) * size[1]).sum();
+    if flat.len() != expected {
+        return Err(format!(
+            "{} weights, the legacy board layout has {}",
+            flat.len(),
+            expected
+        ));
+    }
+
+    let config = NetworkConfig {
+        input_size: board,
+        hidden_sizes: LEGACY_HIDDEN_SIZES.to_vec(),
+        output_size,
+        output_activation,
+    };
+    let mut weights = Vec::with_capacity(expected);
+    let mut offset = 0;
+    for size in sizes.windows(2) {
+        let (inputs, outputs) = (size[0], size[1]);
+        let (matrix, bias) =
+            flat[offset..offset + (inputs + 1) * outputs].split_at(inputs * outputs);
+        offset += (inputs + 1) * outputs;
+        for input in 0..inputs {
+            weights.extend((0..outputs).map(|output| matrix[output * inputs + input]));
+        }
+        weights.extend_from_slice(bias);
+    }
+    Ok(board_network_on_game_features(config, &weights))
+}
+
+// Moves a network over the 42 board cells, read row by row from the bottom,
+// onto `GameFeatures`, which lists the board column by column from the top.
+// The other features get zero weights.
+fn board_network_on_game_features(config: NetworkConfig, weights: &[f32]) -> NetworkSpec {
+    let board = ROWS * COLS;
+    let outputs = config
+        .hidden_sizes
+        .first()
+        .copied()
+        .unwrap_or(config.output_size);
+    let config = NetworkConfig {
+        input_size: FeatureSet::GameFeatures.size(),
+        ..config
+    };
+    let mut remapped = Vec::with_capacity(config.total_weights());
+    for input in 0..config.input_size {
+        match (input < board).then(|| (ROWS - 1 - input % ROWS) * COLS + input / ROWS) {
+            Some(cell) => {
+                remapped.extend_from_slice(&weights[cell * outputs..(cell + 1) * outputs])
+            }
+            None => remapped.extend(std::iter::repeat_n(0.0, outputs)),
+        }
+    }
+    remapped.extend_from_slice(&weights[board * outputs..]);
+    NetworkSpec {
+        architecture: Architecture::Dense(config),
+        weights: remapped,
+    }
+}
+
+fn is_state_dict(network: &Value) -> bool {
+    network
+        .as_object()
+        .is_some_and(|tensors| tensors.keys().any(|key| key.ends_with(".weight")))
+}
+
+// `nn.Linear` layers of a state_dict in file order, which is PyTorch's
+// module registration order, as (name, weight, bias)
+fn linear_layers(state_dict: &Value) -> Result<Vec<(&str, &Value, &Value)>, String> {
+    let tensors = state_dict
+        .as_object()
+        .ok_or("state_dict must be an object")?;
+    let mut layers = Vec::new();
+    for (key, tensor) in tensors {
+        if let Some(name) = key.strip_suffix(".weight") {
+            let bias = tensors
+                .get(&format!("{}.bias", name))
+                .ok_or(format!("{} has no bias", name))?;
+            layers.push((name, tensor, bias));
+        } else if !key.ends_with(".bias") {
+            return Err(format!(
+                "unsupported tensor {} (only linear layers can be imported)",
+                key
+            ));
+        }
+    }
+    if layers.is_empty() {
+        return Err("state_dict has no layers".to_string());
+    }
+    Ok(layers)
+}
+
+fn tensor_shape(tensor: &Value) -> Vec<usize> {
+    let mut shape = Vec::new();
+    let mut current = tensor;
+    while let Some(values) = current.as_array() {
+        shape.push(values.len());
+        match values.first() {
+            Some(first) => current = first,
+            None => break,
+        }
+    }
+    shape
+}
+
+/// The dense architecture a state_dict's linear layers describe.
+pub fn network_config_from_state_dict(
+    state_dict: &Value,
+    output_activation: OutputActivation,
+) -> Result<NetworkConfig, String> {
+    let layers = linear_layers(state_dict)?;
+    let mut sizes = Vec::with_capacity(layers.len() + 1);
+    for (name, weight, _) in &layers {
+        match tensor_shape(weight)[..] {
+            [outputs, inputs] => {
+                if sizes.is_empty() {
+                    sizes.push(inputs);
+                }
+                sizes.push(outputs);
+            }
+            ref shape => {
+                return Err(format!(
+                    "{}.weight has shape {:?}, expected 2-D",
+                    name, shape
+                ))
+            }
+        }
+    }
+    let output_size = sizes.pop().unwrap();
+    let input_size = sizes.remove(0);
+    Ok(NetworkConfig {
+        input_size,
+        hidden_sizes: sizes,
+        output_size,
+        output_activation,
+    })
+}
+
+/// Flattens a state_dict into `NeuralNetwork::load_weights` order, checking
+/// every tensor against `config`. PyTorch stores each weight as
+/// `[outputs][inputs]`, the transpose of this crate's layout.
+pub fn weights_from_state_dict(
+    state_dict: &Value,
+    config: &NetworkConfig,
+) -> Result<Vec<f32>, String> {
+    let layers = linear_layers(state_dict)?;
+    let mut sizes = vec![config.input_size];
+    sizes.extend(&config.hidden_sizes);
+    sizes.push(config.output_size);
+    if layers.len() != sizes.len() - 1 {
+        return Err(format!(
+            "state_dict has {} linear layers, the network has {}",
+            layers.len(),
+            sizes.len() - 1
+        ));
+    }
+
+    let mut weights = Vec::with_capacity(config.total_weights());
+    for ((name, weight, bias), size) in layers.into_iter().zip(sizes.windows(2)) {
+        let (inputs, outputs) = (size[0], size[1]);
+        if tensor_shape(weight) != [outputs, inputs] {
+            return Err(format!(
+                "{}.weight has shape {:?}, expected [{}, {}]",
+                name,
+                tensor_shape(weight),
+                outputs,
+                inputs
+            ));
+        }
+        if tensor_shape(bias) != [outputs] {
+            return Err(format!(
+                "{}.bias has shape {:?}, expected [{}]",
+                name,
+                tensor_shape(bias),
+                outputs
+            ));
+        }
+
+        let number = |value: &Value| {
+            value
+                .as_f64()
+                .map(|value| value as f32)
+                .ok_or(format!("{} has a non-numeric entry", name))
+        };
+        for input in 0..inputs {
+            for output in 0..outputs {
+                weights.push(number(&weight[output][input])?);
+            }
+        }
+        for output in 0..outputs {
+            weights.push(number(&bias[output])?);
+        }
+    }
+    Ok(weights)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_model() -> ModelFile {
+        let network = |output_size, output_activation| {
+            let config = NetworkConfig {
+                input_size: FeatureSet::GameFeatures.size(),
+                hidden_sizes: vec![4],
+                output_size,
+                output_activation,
+            };
+            NetworkSpec {
+                weights: (0..config.total_weights())
+                    .map(|i| i as f32 * 1e-3)
+                    .collect(),
+                architecture: Architecture::Dense(config),
+            }
+        };
+        ModelFile::new(
+            FeatureSet::GameFeatures,
+            network(1, OutputActivation::Tanh),
+            network(COLS, OutputActivation::Softmax),
+            TrainingMetadata {
+                epochs: Some(3),
+                ..TrainingMetadata::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_model_round_trips_through_file() {
+        let model = small_model();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        model.save(path).unwrap();
+        let loaded = ModelFile::load(path).unwrap();
+        assert_eq!(loaded, model);
+
+        let json: Value = serde_json::from_str(&model.to_json()).unwrap();
+        assert_eq!(json["format"], FORMAT_NAME);
+        assert_eq!(json["features"]["set"], "game_features");
+        assert_eq!(json["value_network"]["architecture"]["type"], "dense");
+        assert_eq!(
+            json["policy_network"]["architecture"]["output_activation"],
+            "softmax"
+        );
+    }
+
+    #[test]
+    fn test_corrupted_weights_fail_checksum() {
+        let mut model = small_model();
+        model.policy_network.weights[5] += 1.0;
+        let error = ModelFile::from_json(&model.to_json()).unwrap_err();
+        assert!(error.contains("checksum"), "{}", error);
+    }
+
+    #[test]
+    fn test_mismatched_models_are_rejected() {
+        let mut truncated = small_model();
+        truncated.value_network.weights.pop();
+        truncated.checksum = truncated.compute_checksum();
+        assert!(truncated.validate().unwrap_err().contains("weights"));
+
+        let mut newer = small_model();
+        newer.version = FORMAT_VERSION + 1;
+        assert!(newer.validate().unwrap_err().contains("newer"));
+
+        let mut stale_features = small_model();
+        stale_features.features.version += 1;
+        assert!(stale_features.validate().is_err());
+
+        let mut swapped = small_model();
+        std::mem::swap(&mut swapped.value_network, &mut swapped.policy_network);
+        swapped.checksum = swapped.compute_checksum();
+        assert!(swapped.validate().unwrap_err().contains("outputs"));
+    }
+
+    #[test]
+    fn test_normalizer_is_stored_and_checksummed() {
+        let size = FeatureSet::GameFeatures.size();
+        let normalizer = FeatureNormalizer {
+            mean: vec![0.5; size],
+            std: vec![2.0; size],
+        };
+        let model = small_model().with_normalizer(normalizer);
+
+        let loaded = ModelFile::from_json(&model.to_json()).unwrap();
+        assert_eq!(loaded, model);
+        let loaded = ModelFile::from_binary(&model.to_binary(Precision::F16)).unwrap();
+        assert_eq!(loaded.normalizer, model.normalizer);
+
+        let mut tampered = model.clone();
+        tampered.normalizer.as_mut().unwrap().mean[3] = 1.0;
+        assert!(tampered.validate().unwrap_err().contains("checksum"));
+
+        let mut short = model;
+        short.normalizer.as_mut().unwrap().std.pop();
+        short.checksum = short.compute_checksum();
+        assert!(short.validate().unwrap_err().contains("normalizer"));
+    }
+
+    #[test]
+    fn test_binary_models_round_trip_at_each_precision() {
+        let model = small_model();
+
+        let bytes = model.to_binary(Precision::F32);
+        assert_eq!(ModelFile::from_binary(&bytes).unwrap(), model);
+        assert_eq!(ModelFile::binary_precision(&bytes), Ok(Precision::F32));
+
+        for precision in [Precision::F16, Precision::Int8] {
+            let bytes = model.to_binary(precision);
+            let loaded = ModelFile::from_binary(&bytes).unwrap();
+            assert_eq!(loaded.metadata, model.metadata);
+            assert_eq!(loaded.checksum, loaded.compute_checksum());
+            let weights = loaded
+                .value_network
+                .weights
+                .iter()
+                .zip(&model.value_network.weights);
+            for (loaded, original) in weights {
+                assert!((loaded - original).abs() < 5e-3, "{}", precision);
+            }
+        }
+        assert!(model.to_binary(Precision::Int8).len() < model.to_binary(Precision::F16).len());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        model.save_binary(path, Precision::F32).unwrap();
+        assert_eq!(ModelFile::load(path).unwrap(), model);
+    }
+
+    #[test]
+    fn test_damaged_binary_models_are_rejected() {
+        let bytes = small_model().to_binary(Precision::F16);
+        assert!(ModelFile::from_binary(&bytes[..bytes.len() - 1])
+            .unwrap_err()
+            .contains("bytes"));
+        assert!(ModelFile::from_binary(&bytes[..20]).is_err());
+        assert!(ModelFile::from_binary(b"{}").is_err());
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 0x40;
+        assert!(ModelFile::from_binary(&flipped)
+            .unwrap_err()
+            .contains("checksum"));
+    }
+
+    // The PyTorch layout of a network's flat weights: `layers.{3i}` per
+    // linear layer, weights as [outputs][inputs]
+    fn state_dict(spec: &NetworkSpec) -> Value {
+        let Architecture::Dense(config) = &spec.architecture else {
+            unreachable!()
+        };
+        let mut sizes = vec![config.input_size];
+        sizes.extend(&config.hidden_sizes);
+        sizes.push(config.output_size);
+        let mut weights = spec.weights.iter();
+        let mut tensors = Map::new();
+        for (idx, size) in sizes.windows(2).enumerate() {
+            let (inputs, outputs) = (size[0], size[1]);
+            let flat: Vec<f32> = weights.by_ref().take(inputs * outputs).copied().collect();
+            let matrix: Vec<Vec<f32>> = (0..outputs)
+                .map(|o| (0..inputs).map(|i| flat[i * outputs + o]).collect())
+                .collect();
+            let bias: Vec<f32> = weights.by_ref().take(outputs).copied().collect();
+            tensors.insert(format!("layers.{}.weight", idx * 3), matrix.into());
+            tensors.insert(format!("layers.{}.bias", idx * 3), bias.into());
+        }
+        Value::Object(tensors)
+    }
+
+    #[test]
+    fn test_state_dict_exports_are_imported() {
+        let model = small_model();
+        let export = serde_json::json!({
+            "metadata": {
+                "model_type": "pytorch",
+                "architecture": { "input_size": 100, "hidden_sizes": [4] },
+                "epochs_completed": 7
+            },
+            "value_network": state_dict(&model.value_network),
+            "policy_network": state_dict(&model.policy_network)
+        });
+
+        let imported = ModelFile::from_json(&export.to_string()).unwrap();
+        assert_eq!(imported.value_network, model.value_network);
+        assert_eq!(imported.policy_network, model.policy_network);
+        assert_eq!(imported.metadata.epochs, Some(7));
+        assert!(imported.metadata.extra.contains_key("pytorch_metadata"));
+
+        let mut wrong_hidden = export.clone();
+        wrong_hidden["metadata"]["architecture"]["hidden_sizes"] = serde_json::json!([8]);
+        let error = ModelFile::from_json(&wrong_hidden.to_string()).unwrap_err();
+        assert!(error.contains("hidden sizes"), "{}", error);
+    }
+
+    #[test]
+    fn test_malformed_state_dicts_are_rejected() {
+        let model = small_model();
+        let config = match &model.value_network.architecture {
+            Architecture::Dense(config) => config.clone(),
+            _ => unreachable!(),
+        };
+        let tensors = state_dict(&model.value_network);
+
+        let mut transposed = tensors.clone();
+        let weight = &transposed["layers.0.weight"];
+        let rows = weight.as_array().unwrap().len();
+        let cols = weight[0].as_array().unwrap().len();
+        let flipped: Vec<Vec<Value>> = (0..cols)
+            .map(|c| (0..rows).map(|r| weight[r][c].clone()).collect())
+            .collect();
+        transposed["layers.0.weight"] = serde_json::json!(flipped);
+        let error = weights_from_state_dict(&transposed, &config).unwrap_err();
+        assert!(error.contains("layers.0.weight"), "{}", error);
+
+        let mut missing_bias = tensors.clone();
+        missing_bias
+            .as_object_mut()
+            .unwrap()
+            .remove("layers.3.bias");
+        assert!(weights_from_state_dict(&missing_bias, &config)
+            .unwrap_err()
+            .contains("no bias"));
+
+        let mut batch_norm = tensors.clone();
+        batch_norm["layers.1.running_mean"] = serde_json::json!([0.0, 0.0, 0.0, 0.0]);
+        assert!(weights_from_state_dict(&batch_norm, &config)
+            .unwrap_err()
+            .contains("unsupported"));
+
+        let deeper = NetworkConfig {
+            hidden_sizes: vec![4, 4],
+            ..config
+        };
+        assert!(weights_from_state_dict(&tensors, &deeper)
+            .unwrap_err()
+            .contains("linear layers"));
+    }
+
+    #[test]
+    fn test_legacy_weights_are_converted() {
+        let model = small_model();
+        let legacy = serde_json::json!({
+            "metadata": {
+                "model_type": "self_play_advanced",
+                "architecture": { "input_size": 100, "hidden_sizes": [4] },
+                "epochs_completed": 2
+            },
+            "value_network": { "weights": model.value_network.weights },
+            "policy_network": { "weights": model.policy_network.weights }
+        });
+
+        let converted = ModelFile::from_json(&legacy.to_string()).unwrap();
+        assert_eq!(converted.value_network, model.value_network);
+        assert_eq!(converted.policy_network, model.policy_network);
+        assert_eq!(converted.metadata.epochs, Some(2));
+        assert_eq!(
+            converted.metadata.trainer.as_deref(),
+            Some("self_play_advanced")
+        );
+
+        let unsupported = serde_json::json!({ "metadata": {}, "value_network": {} });
+        assert!(ModelFile::from_json(&unsupported.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_legacy_board_weights_read_the_board_from_the_bottom() {
+        use crate::neural_network::NeuralNetwork;
+        use crate::{Cell, GameState, Player};
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let board = ROWS * COLS;
+        let layers = [(board, 64), (64, 32), (32, 1)];
+        let flat: Vec<Vec<f32>> = layers
+            .iter()
+            .map(|&(inputs, outputs)| {
+                (0..(inputs + 1) * outputs)
+                    .map(|_| rng.gen_range(-0.3..0.3))
+                    .collect()
+            })
+            .collect();
+        let policy = vec![0.0; 4832 + 33 * COLS];
+        let model = ModelFile::from_legacy_weights(&flat.concat(), &policy).unwrap();
+        assert!(ModelFile::from_legacy_weights(&flat[0], &policy).is_err());
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [3, 3, 0, 6] {
+            state.make_move(col).unwrap();
+        }
+        // The old encoding: row by row from the bottom, Player1 as 1
+        let mut input: Vec<f32> = vec![0.0; board];
+        for col in 0..COLS {
+            for row in 0..ROWS {
+                input[(ROWS - 1 - row) * COLS + col] = match state.board[col][row] {
+                    Cell::Empty => 0.0,
+                    Cell::Player1 => 1.0,
+                    Cell::Player2 => -1.0,
+                };
+            }
+        }
+        for (layer, &(inputs, outputs)) in flat.iter().zip(&layers) {
+            input = (0..outputs)
+                .map(|o| {
+                    let sum: f32 = (0..inputs).map(|i| layer[o * inputs + i] * input[i]).sum();
+                    sum + layer[inputs * outputs + o]
+                })
+                .map(|x| if outputs == 1 { x.tanh() } else { x.max(0.0) })
+                .collect();
+        }
+
+        let Architecture::Dense(config) = &model.value_network.architecture else {
+            panic!("legacy networks are dense");
+        };
+        let mut network = NeuralNetwork::new(config.clone());
+        network.load_weights(&model.value_network.weights).unwrap();
+        let features = FeatureSet::GameFeatures.encode(&state);
+        let output = network.forward(&features);
+        assert!(
+            (output[0] - input[0]).abs() < 1e-5,
+            "{} vs {}",
+            output[0],
+            input[0]
+        );
+    }
+
+    #[test]
+    fn test_board_state_dict_exports_are_imported() {
+        use crate::neural_network::NeuralNetwork;
+        use crate::{GameState, Player};
+
+        let model = ModelFile::load("../../ml/data/weights/simple_model_enhanced.json").unwrap();
+        assert_eq!(model.features.set, FeatureSet::GameFeatures);
+
+        let mut state = GameState::new();
+        state.current_player = Player::Player1;
+        for col in [3, 3, 0, 6] {
+            state.make_move(col).unwrap();
+        }
+        let features = FeatureSet::GameFeatures.encode(&state);
+        let forward = |spec: &NetworkSpec| {
+            let Architecture::Dense(config) = &spec.architecture else {
+                panic!("PyTorch exports are dense");
+            };
+            let mut network = NeuralNetwork::new(config.clone());
+            network.load_weights(&spec.weights).unwrap();
+            network.forward(&features).to_vec()
+        };
+
+        // The export's own networks on the same board, computed in PyTorch's layout
+        let value = forward(&model.value_network);
+        assert!((value[0] - 0.514_664_2).abs() < 1e-5, "{:?}", value);
+        let policy = forward(&model.policy_network);
+        let expected = [
+            0.066_346, 0.062_311, 0.068_606, 0.592_334, 0.071_133, 0.073_692, 0.065_579,
+        ];
+        for (output, expected) in policy.iter().zip(expected) {
+            assert!((output - expected).abs() < 1e-5, "{:?}", policy);
+        }
+    }
+}
@@ -1,12 +1,108 @@
-use ndarray::{Array1, Array2};
-use rand::Rng;
+use crate::features::PLANES;
+use crate::optimizer::{Gradients, LearningRateSchedule, Optimizer, OptimizerConfig};
+use crate::quantization::{Precision, QuantizedMatrix};
+use crate::{COLS, ROWS};
+use ndarray::{Array1, Array2, ArrayView1, ArrayViewMut1, Axis};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::f32;
 
-#[derive(Clone, Debug)]
+/// Activation applied to the output layer, which also fixes the loss used
+/// in training.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputActivation {
+    /// Value head in [-1, 1]; squared error loss.
+    Tanh,
+    /// Unbounded value head; squared error loss.
+    Linear,
+    /// Move distribution; cross-entropy loss. Illegal moves can be masked out.
+    Softmax,
+}
+
+impl OutputActivation {
+    fn apply(self, mut output: ArrayViewMut1<f32>, legal_moves: Option<ArrayView1<bool>>) {
+        match self {
+            OutputActivation::Tanh => output.mapv_inplace(f32::tanh),
+            OutputActivation::Linear => {}
+            OutputActivation::Softmax => softmax(output, legal_moves),
+        }
+    }
+
+    fn apply_rows(self, outputs: &mut Array2<f32>, legal_moves: Option<&Array2<bool>>) {
+        for (i, row) in outputs.rows_mut().into_iter().enumerate() {
+            self.apply(row, legal_moves.map(|mask| mask.row(i)));
+        }
+    }
+
+    // Summed loss of the activated `logits` and its gradient with respect
+    // to them. Cross-entropy works from log-probabilities so tiny
+    // probabilities need no clamping; masked moves are left out of the loss
+    // (they are constant) and get no gradient
+    fn loss_gradient(
+        self,
+        logits: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Array2<f32>) {
+        let mut outputs = logits.clone();
+        self.apply_rows(&mut outputs, legal_moves);
+        match self {
+            OutputActivation::Tanh => {
+                let diff = &outputs - targets;
+                let gradient = ndarray::Zip::from(&diff)
+                    .and(&outputs)
+                    .map_collect(|&d, &y| 2.0 * d * (1.0 - y * y));
+                (diff.mapv(|d| d * d).sum(), gradient)
+            }
+            OutputActivation::Linear => {
+                let diff = &outputs - targets;
+                (diff.mapv(|d| d * d).sum(), diff * 2.0)
+            }
+            OutputActivation::Softmax => {
+                let mut loss = 0.0;
+                let mut gradient = Array2::zeros(outputs.dim());
+                for (i, mut row) in gradient.rows_mut().into_iter().enumerate() {
+                    let mask = legal_moves.map(|mask| mask.row(i));
+                    let legal =
+                        |j: usize| mask.is_none_or(|mask| mask[j] || !mask.iter().any(|&l| l));
+                    let logits = logits.row(i);
+                    let max_logit = (0..logits.len())
+                        .filter(|&j| legal(j))
+                        .fold(f32::NEG_INFINITY, |max, j| max.max(logits[j]));
+                    let log_sum = (0..logits.len())
+                        .filter(|&j| legal(j))
+                        .map(|j| (logits[j] - max_logit).exp())
+                        .sum::<f32>()
+                        .ln()
+                        + max_logit;
+
+                    // d/dz of -sum(t * ln softmax(z)) is p * sum(t) - t
+                    let target_mass: f32 = (0..logits.len())
+                        .filter(|&j| legal(j))
+                        .map(|j| targets[[i, j]])
+                        .sum();
+                    for (j, g) in row.iter_mut().enumerate() {
+                        if legal(j) {
+                            loss -= targets[[i, j]] * (logits[j] - log_sum);
+                            *g = outputs[[i, j]] * target_mass - targets[[i, j]];
+                        }
+                    }
+                }
+                (loss, gradient)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub input_size: usize,
     pub hidden_sizes: Vec<usize>,
     pub output_size: usize,
+    pub output_activation: OutputActivation,
 }
 
 impl NetworkConfig {
@@ -28,6 +124,8 @@ impl NetworkConfig {
 pub struct Layer {
     weights: Array2<f32>,
     biases: Array1<f32>,
+    // Reduced-precision copy used by the forward pass once quantised
+    quantized: Option<QuantizedMatrix>,
 }
 
 impl Layer {
@@ -41,7 +139,11 @@ impl Layer {
 
         let biases = Array1::from_shape_fn(output_size, |_| rng.gen_range(-0.1..0.1));
 
-        Layer { weights, biases }
+        Layer {
+            weights,
+            biases,
+            quantized: None,
+        }
     }
 
     pub fn update_weights(
@@ -52,25 +154,70 @@ impl Layer {
     ) {
         self.weights = &self.weights - &(weight_gradients * learning_rate);
         self.biases = &self.biases - &(bias_gradients * learning_rate);
+        self.quantized = None;
+    }
+
+    /// Runs the forward pass at `precision` from now on. The f32 weights
+    /// are replaced by their rounded values, so `get_weights` and training
+    /// see the model that is actually evaluated; any later update returns
+    /// the layer to f32.
+    pub fn quantize(&mut self, precision: Precision) {
+        self.quantized = QuantizedMatrix::quantize(&self.weights, precision);
+        if let Some(quantized) = &self.quantized {
+            self.weights = quantized.dequantize();
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        match self.quantized {
+            None => Precision::F32,
+            Some(QuantizedMatrix::F16(_)) => Precision::F16,
+            Some(QuantizedMatrix::Int8 { .. }) => Precision::Int8,
+        }
     }
 
     pub fn forward(&self, input: &Array1<f32>) -> Array1<f32> {
-        let linear = input.dot(&self.weights) + &self.biases;
+        let linear = self.forward_linear(input);
         linear.mapv(|x| x.max(0.0)) // ReLU activation
     }
 
     pub fn forward_linear(&self, input: &Array1<f32>) -> Array1<f32> {
-        input.dot(&self.weights) + &self.biases
+        match &self.quantized {
+            Some(quantized) => {
+                let input = input.view().insert_axis(Axis(0)).to_owned();
+                quantized.dot(&input).row(0).to_owned() + &self.biases
+            }
+            None => input.dot(&self.weights) + &self.biases,
+        }
+    }
+
+    /// Forward pass over a batch, one sample per row.
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        self.forward_linear_batch(input).mapv(|x| x.max(0.0)) // ReLU activation
+    }
+
+    pub fn forward_linear_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        match &self.quantized {
+            Some(quantized) => quantized.dot(input) + &self.biases,
+            None => input.dot(&self.weights) + &self.biases,
+        }
+    }
+
+    pub fn forward_batch_with_cache(&self, input: &Array2<f32>) -> (Array2<f32>, Array2<f32>) {
+        let linear = self.forward_linear_batch(input);
+        let activated = linear.mapv(|x| x.max(0.0)); // ReLU activation
+        (activated, linear)
     }
 
     pub fn forward_with_cache(&self, input: &Array1<f32>) -> (Array1<f32>, Array1<f32>) {
-        let linear = input.dot(&self.weights) + &self.biases;
+        let linear = self.forward_linear(input);
         let activated = linear.mapv(|x| x.max(0.0)); // ReLU activation
         (activated, linear)
     }
 
     pub fn load_weights(&mut self, weights: &[f32]) -> usize {
         let mut idx = 0;
+        self.quantized = None;
 
         // Load weights
         for i in 0..self.weights.shape()[0] {
@@ -112,6 +259,57 @@ impl Layer {
     }
 }
 
+/// Settings for `NeuralNetwork::train_network`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainNetworkConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    /// Fraction of the samples held out to measure validation loss.
+    pub validation_split: f32,
+    /// Stop after this many epochs without a better validation loss; 0
+    /// trains for every epoch.
+    pub early_stopping_patience: usize,
+    pub optimizer: OptimizerConfig,
+    pub schedule: LearningRateSchedule,
+    /// Seeds the split and the per-epoch shuffles.
+    pub seed: u64,
+}
+
+impl Default for TrainNetworkConfig {
+    fn default() -> Self {
+        TrainNetworkConfig {
+            epochs: 50,
+            batch_size: 32,
+            validation_split: 0.2,
+            early_stopping_patience: 5,
+            optimizer: OptimizerConfig::adam(0.001),
+            schedule: LearningRateSchedule::Constant,
+            seed: 42,
+        }
+    }
+}
+
+/// Per-epoch losses and learning rates from `train_network`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingHistory {
+    pub train_loss: Vec<f32>,
+    pub validation_loss: Vec<f32>,
+    pub learning_rates: Vec<f32>,
+    /// Epoch whose weights the network was left with.
+    pub best_epoch: usize,
+    pub stopped_early: bool,
+}
+
+impl TrainingHistory {
+    pub fn epochs(&self) -> usize {
+        self.train_loss.len()
+    }
+
+    pub fn best_validation_loss(&self) -> Option<f32> {
+        self.validation_loss.get(self.best_epoch).copied()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct NeuralNetwork {
     layers: Vec<Layer>,
@@ -136,175 +334,1312 @@ impl NeuralNetwork {
     }
 
     pub fn forward(&self, input: &Array1<f32>) -> Array1<f32> {
+        let mut output = self.logits(input);
+        self.config.output_activation.apply(output.view_mut(), None);
+        output
+    }
+
+    /// Like `forward`, but a softmax head gives zero probability to every
+    /// output whose `legal_moves` entry is false.
+    pub fn forward_masked(&self, input: &Array1<f32>, legal_moves: &[bool]) -> Array1<f32> {
+        let mut output = self.logits(input);
+        self.config
+            .output_activation
+            .apply(output.view_mut(), Some(ArrayView1::from(legal_moves)));
+        output
+    }
+
+    /// Same as `forward` for every row of `input` in a single pass.
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        let mut output = self.logits_batch(input);
+        self.config.output_activation.apply_rows(&mut output, None);
+        output
+    }
+
+    /// Same as `forward_masked` for every row, with one mask row per sample.
+    pub fn forward_batch_masked(
+        &self,
+        input: &Array2<f32>,
+        legal_moves: &Array2<bool>,
+    ) -> Array2<f32> {
+        let mut output = self.logits_batch(input);
+        self.config
+            .output_activation
+            .apply_rows(&mut output, Some(legal_moves));
+        output
+    }
+
+    // Output layer values before the head activation
+    fn logits(&self, input: &Array1<f32>) -> Array1<f32> {
+        let (output_layer, hidden_layers) = self.layers.split_last().unwrap();
         let mut current = input.clone();
+        for layer in hidden_layers {
+            current = layer.forward(&current);
+        }
+        output_layer.forward_linear(&current)
+    }
 
-        for (i, layer) in self.layers.iter().enumerate() {
-            if i == self.layers.len() - 1 {
-                // Final layer: linear only, no ReLU
-                current = layer.forward_linear(&current);
+    fn logits_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        let (output_layer, hidden_layers) = self.layers.split_last().unwrap();
+        let mut current = input.clone();
+        for layer in hidden_layers {
+            current = layer.forward_batch(&current);
+        }
+        output_layer.forward_linear_batch(&current)
+    }
+
+    pub fn config(&self) -> &NetworkConfig {
+        &self.config
+    }
+
+    pub fn output_activation(&self) -> OutputActivation {
+        self.config.output_activation
+    }
+
+    pub fn load_weights(&mut self, weights: &[f32]) -> Result<(), String> {
+        let expected = self.config.total_weights();
+        if weights.len() != expected {
+            return Err(format!(
+                "expected {} weights, got {}",
+                expected,
+                weights.len()
+            ));
+        }
+
+        let mut idx = 0;
+        for layer in &mut self.layers {
+            idx += layer.load_weights(&weights[idx..]);
+        }
+        Ok(())
+    }
+
+    pub fn get_weights(&self) -> Vec<f32> {
+        let mut weights = Vec::new();
+
+        for layer in &self.layers {
+            weights.extend(layer.get_weights());
+        }
+
+        weights
+    }
+
+    pub fn save_weights(&self) -> Vec<f32> {
+        self.get_weights()
+    }
+
+    pub fn load_weights_from_file(
+        &mut self,
+        filename: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(filename)?;
+        let weights: Vec<f32> = serde_json::from_str(&content)?;
+        self.load_weights(&weights)?;
+        Ok(())
+    }
+
+    pub fn save_weights_to_file(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let weights = self.get_weights();
+        let content = serde_json::to_string(&weights)?;
+        std::fs::write(filename, content)?;
+        Ok(())
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// One plain SGD update on a single sample; returns its loss.
+    pub fn train_step(
+        &mut self,
+        input: &Array1<f32>,
+        target: &Array1<f32>,
+        learning_rate: f32,
+    ) -> f32 {
+        let inputs = input.clone().insert_axis(Axis(0));
+        let targets = target.clone().insert_axis(Axis(0));
+        let mut optimizer = Optimizer::new(OptimizerConfig::sgd(learning_rate, 0.0));
+        self.train_batch(&inputs, &targets, &mut optimizer)
+    }
+
+    /// One optimizer step on a mini-batch (one sample per row); returns the
+    /// mean loss.
+    pub fn train_batch(
+        &mut self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        optimizer: &mut Optimizer,
+    ) -> f32 {
+        let (loss, gradients) = self.compute_gradients(inputs, targets);
+        self.apply_gradients(&gradients, optimizer);
+        loss
+    }
+
+    /// Mean loss and mean gradients over a batch, without touching the
+    /// weights. Sum several with `Gradients::accumulate` for larger
+    /// effective batches.
+    pub fn compute_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+    ) -> (f32, Gradients) {
+        let (loss, gradients, _) = self.backward(inputs, targets, None);
+        (loss, gradients)
+    }
+
+    /// `compute_gradients` for a softmax head whose predictions are masked
+    /// to the legal moves, as in `forward_batch_masked`.
+    pub fn compute_masked_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: &Array2<bool>,
+    ) -> (f32, Gradients) {
+        let (loss, gradients, _) = self.backward(inputs, targets, Some(legal_moves));
+        (loss, gradients)
+    }
+
+    // Mean loss, mean parameter gradients, and the gradient with respect to
+    // `inputs`
+    fn backward(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients, Array2<f32>) {
+        let batch_size = inputs.nrows().max(1) as f32;
+        let (output_layer, hidden_layers) = self.layers.split_last().unwrap();
+
+        // Forward pass with caching
+        let mut activations = vec![inputs.clone()];
+        let mut linear_outputs = Vec::new();
+        for layer in hidden_layers {
+            let (activated, linear) = layer.forward_batch_with_cache(activations.last().unwrap());
+            activations.push(activated);
+            linear_outputs.push(linear);
+        }
+        let logits = output_layer.forward_linear_batch(activations.last().unwrap());
+        let (loss, mut gradient) =
+            self.config
+                .output_activation
+                .loss_gradient(&logits, targets, legal_moves);
+        gradient.mapv_inplace(|g| g / batch_size);
+
+        // Backward pass: the output layer is linear, hidden layers use ReLU
+        let mut layer_gradients = Vec::with_capacity(self.layers.len());
+        let (weight_gradients, bias_gradients, input_gradient) =
+            linear_layer_gradients(output_layer, activations.last().unwrap(), &gradient);
+        gradient = input_gradient;
+        layer_gradients.push((weight_gradients, bias_gradients));
+        for (layer_idx, layer) in hidden_layers.iter().enumerate().rev() {
+            let (weight_gradients, bias_gradients, input_gradient) = self.compute_layer_gradients(
+                layer,
+                &activations[layer_idx],
+                &linear_outputs[layer_idx],
+                &gradient,
+            );
+            gradient = input_gradient;
+            layer_gradients.push((weight_gradients, bias_gradients));
+        }
+        layer_gradients.reverse();
+
+        (
+            loss / batch_size,
+            Gradients {
+                layers: layer_gradients,
+            },
+            gradient,
+        )
+    }
+
+    pub fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+        for (idx, (layer, layer_gradients)) in
+            self.layers.iter_mut().zip(&gradients.layers).enumerate()
+        {
+            optimizer.update_layer(idx, &mut layer.weights, &mut layer.biases, layer_gradients);
+            layer.quantized = None;
+        }
+        optimizer.finish_step();
+    }
+
+    /// Switches every layer's forward pass to `precision`; see
+    /// `Layer::quantize`.
+    pub fn quantize(&mut self, precision: Precision) {
+        for layer in &mut self.layers {
+            layer.quantize(precision);
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.layers
+            .first()
+            .map_or(Precision::F32, |layer| layer.precision())
+    }
+
+    /// Fits the network to `inputs` and `targets`, one sample per row. A
+    /// seeded shuffle holds out `validation_split` of the rows; each epoch
+    /// trains on shuffled mini-batches and the held-out loss then drives the
+    /// learning-rate schedule and early stopping. The network ends up with
+    /// the weights of its best epoch.
+    pub fn train_network(
+        &mut self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        config: &TrainNetworkConfig,
+    ) -> TrainingHistory {
+        assert_eq!(inputs.nrows(), targets.nrows(), "one target row per input");
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut indices: Vec<usize> = (0..inputs.nrows()).collect();
+        indices.shuffle(&mut rng);
+        let validation_size = ((inputs.nrows() as f32 * config.validation_split).round() as usize)
+            .min(inputs.nrows().saturating_sub(1));
+        let mut training = indices.split_off(validation_size);
+        let validation_inputs = inputs.select(Axis(0), &indices);
+        let validation_targets = targets.select(Axis(0), &indices);
+
+        let mut optimizer = Optimizer::new(config.optimizer);
+        let initial_rate = config.optimizer.learning_rate();
+        let mut rate = initial_rate;
+        let mut history = TrainingHistory::default();
+        let mut best = (f32::INFINITY, self.layers.clone());
+        let mut stale_epochs = 0;
+
+        for epoch in 0..config.epochs {
+            rate = config.schedule.learning_rate(
+                initial_rate,
+                rate,
+                epoch,
+                config.epochs,
+                stale_epochs,
+            );
+            optimizer.set_learning_rate(rate);
+
+            training.shuffle(&mut rng);
+            let mut train_loss = 0.0;
+            for batch in training.chunks(config.batch_size.max(1)) {
+                let loss = self.train_batch(
+                    &inputs.select(Axis(0), batch),
+                    &targets.select(Axis(0), batch),
+                    &mut optimizer,
+                );
+                train_loss += loss * batch.len() as f32;
+            }
+            train_loss /= training.len().max(1) as f32;
+            // With nothing held out, select on the training loss instead
+            let validation_loss = if indices.is_empty() {
+                train_loss
             } else {
-                // Hidden layers: linear + ReLU
-                current = layer.forward(&current);
+                self.loss(&validation_inputs, &validation_targets)
+            };
+
+            history.train_loss.push(train_loss);
+            history.validation_loss.push(validation_loss);
+            history.learning_rates.push(rate);
+            if validation_loss < best.0 {
+                best = (validation_loss, self.layers.clone());
+                history.best_epoch = epoch;
+                stale_epochs = 0;
+            } else {
+                stale_epochs += 1;
+                if config.early_stopping_patience > 0
+                    && stale_epochs >= config.early_stopping_patience
+                {
+                    history.stopped_early = true;
+                    break;
+                }
+            }
+        }
+
+        if best.0.is_finite() {
+            self.layers = best.1;
+        }
+        history
+    }
+
+    /// Mean loss over a batch without computing weight gradients.
+    pub fn loss(&self, inputs: &Array2<f32>, targets: &Array2<f32>) -> f32 {
+        let (loss, _) =
+            self.config
+                .output_activation
+                .loss_gradient(&self.logits_batch(inputs), targets, None);
+        loss / inputs.nrows().max(1) as f32
+    }
+
+    fn compute_layer_gradients(
+        &self,
+        layer: &Layer,
+        input: &Array2<f32>,
+        linear_output: &Array2<f32>,
+        output_gradient: &Array2<f32>,
+    ) -> (Array2<f32>, Array1<f32>, Array2<f32>) {
+        // Compute activation gradient (ReLU derivative)
+        let activation_gradient = linear_output.mapv(|x| if x > 0.0 { 1.0 } else { 0.0 });
+        let layer_gradient = output_gradient * &activation_gradient;
+        linear_layer_gradients(layer, input, &layer_gradient)
+    }
+}
+
+// Weight, bias and input gradients of `input · W + b`, summed over the batch
+fn linear_layer_gradients(
+    layer: &Layer,
+    input: &Array2<f32>,
+    layer_gradient: &Array2<f32>,
+) -> (Array2<f32>, Array1<f32>, Array2<f32>) {
+    let weight_gradients = input.t().dot(layer_gradient);
+    let bias_gradients = layer_gradient.sum_axis(Axis(0));
+    let input_gradient = layer_gradient.dot(&layer.weights.t());
+    (weight_gradients, bias_gradients, input_gradient)
+}
+
+// Numerically stable softmax in place. Entries masked out get probability
+// zero, unless nothing is legal, in which case the mask is ignored.
+fn softmax(mut values: ArrayViewMut1<f32>, legal_moves: Option<ArrayView1<bool>>) {
+    let legal = |i: usize| legal_moves.is_none_or(|mask| mask[i]);
+    let masked = legal_moves.is_some_and(|mask| mask.iter().any(|&l| l));
+
+    let max_val = values
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| !masked || legal(i))
+        .fold(f32::NEG_INFINITY, |a, (_, &b)| a.max(b));
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = if !masked || legal(i) {
+            (*value - max_val).exp()
+        } else {
+            0.0
+        };
+    }
+    let sum = values.sum();
+    values.mapv_inplace(|x| x / sum);
+}
+
+const BATCH_NORM_MOMENTUM: f32 = 0.9;
+const BATCH_NORM_EPSILON: f32 = 1e-5;
+
+/// Square, zero-padded ("same") 2D convolution over a `rows` × `cols` board.
+///
+/// Convolutional activations hold one row per board cell (sample-major,
+/// then row-major over the board) and one column per channel, so a batch is
+/// an `Array2` of shape `(batch * rows * cols, channels)`. The kernel is a
+/// dense `Layer` applied to each cell's flattened `channels × k × k` patch.
+#[derive(Clone, Debug)]
+pub struct Conv2d {
+    kernel: Layer,
+    in_channels: usize,
+    kernel_size: usize,
+    rows: usize,
+    cols: usize,
+}
+
+impl Conv2d {
+    pub fn new(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        rows: usize,
+        cols: usize,
+    ) -> Self {
+        assert!(kernel_size % 2 == 1, "kernel size must be odd");
+        Conv2d {
+            kernel: Layer::new(in_channels * kernel_size * kernel_size, out_channels),
+            in_channels,
+            kernel_size,
+            rows,
+            cols,
+        }
+    }
+
+    pub fn out_channels(&self) -> usize {
+        self.kernel.biases.len()
+    }
+
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        self.kernel.forward_linear_batch(&self.patches(input))
+    }
+
+    // Calls `f(cell, tap, source)` for every kernel tap that lands on the
+    // board: `cell` is the output row, `tap` is `dy * k + dx` and `source`
+    // the input row it reads
+    fn for_each_tap(&self, cells: usize, mut f: impl FnMut(usize, usize, usize)) {
+        let k = self.kernel_size;
+        let pad = k / 2;
+        let board = self.rows * self.cols;
+        for cell in 0..cells {
+            let (sample, position) = (cell / board, cell % board);
+            let (row, col) = (position / self.cols, position % self.cols);
+            for dy in 0..k {
+                for dx in 0..k {
+                    let (r, c) = (row + dy, col + dx);
+                    if r < pad || c < pad || r - pad >= self.rows || c - pad >= self.cols {
+                        continue;
+                    }
+                    let source = sample * board + (r - pad) * self.cols + (c - pad);
+                    f(cell, dy * k + dx, source);
+                }
+            }
+        }
+    }
+
+    // im2col: each cell's neighbourhood as one row, channel-major
+    fn patches(&self, input: &Array2<f32>) -> Array2<f32> {
+        let taps = self.kernel_size * self.kernel_size;
+        let mut patches = Array2::zeros((input.nrows(), self.in_channels * taps));
+        self.for_each_tap(input.nrows(), |cell, tap, source| {
+            for channel in 0..self.in_channels {
+                patches[[cell, channel * taps + tap]] = input[[source, channel]];
+            }
+        });
+        patches
+    }
+
+    // Inverse of `patches`: adds each patch entry's gradient back onto the
+    // cell it was copied from
+    fn scatter_patches(&self, patch_gradient: &Array2<f32>) -> Array2<f32> {
+        let taps = self.kernel_size * self.kernel_size;
+        let mut input_gradient = Array2::zeros((patch_gradient.nrows(), self.in_channels));
+        self.for_each_tap(patch_gradient.nrows(), |cell, tap, source| {
+            for channel in 0..self.in_channels {
+                input_gradient[[source, channel]] += patch_gradient[[cell, channel * taps + tap]];
+            }
+        });
+        input_gradient
+    }
+
+    fn backward(
+        &self,
+        patches: &Array2<f32>,
+        output_gradient: &Array2<f32>,
+    ) -> (Array2<f32>, Array1<f32>, Array2<f32>) {
+        let (weight_gradients, bias_gradients, patch_gradient) =
+            linear_layer_gradients(&self.kernel, patches, output_gradient);
+        (
+            weight_gradients,
+            bias_gradients,
+            self.scatter_patches(&patch_gradient),
+        )
+    }
+}
+
+/// Per-channel batch normalisation. Training normalises with the batch's
+/// own statistics; inference uses running averages of them.
+#[derive(Clone, Debug)]
+pub struct BatchNorm {
+    // 1 × channels so that scale and shift pair up like a layer's weights
+    // and biases in `Gradients`
+    scale: Array2<f32>,
+    shift: Array1<f32>,
+    running_mean: Array1<f32>,
+    running_var: Array1<f32>,
+}
+
+struct BatchNormCache {
+    normalized: Array2<f32>,
+    inv_std: Array1<f32>,
+    mean: Array1<f32>,
+    var: Array1<f32>,
+}
+
+impl BatchNorm {
+    pub fn new(channels: usize) -> Self {
+        BatchNorm {
+            scale: Array2::ones((1, channels)),
+            shift: Array1::zeros(channels),
+            running_mean: Array1::zeros(channels),
+            running_var: Array1::ones(channels),
+        }
+    }
+
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        let inv_std = self
+            .running_var
+            .mapv(|v| 1.0 / (v + BATCH_NORM_EPSILON).sqrt());
+        (input - &self.running_mean) * &inv_std * self.scale.row(0) + &self.shift
+    }
+
+    fn forward_train(&self, input: &Array2<f32>) -> (Array2<f32>, BatchNormCache) {
+        let mean = input.mean_axis(Axis(0)).unwrap();
+        let centered = input - &mean;
+        let var = centered.mapv(|x| x * x).mean_axis(Axis(0)).unwrap();
+        let inv_std = var.mapv(|v| 1.0 / (v + BATCH_NORM_EPSILON).sqrt());
+        let normalized = centered * &inv_std;
+        let output = &normalized * &self.scale.row(0) + &self.shift;
+        (
+            output,
+            BatchNormCache {
+                normalized,
+                inv_std,
+                mean,
+                var,
+            },
+        )
+    }
+
+    fn backward(
+        &self,
+        cache: &BatchNormCache,
+        output_gradient: &Array2<f32>,
+    ) -> (Array2<f32>, Array1<f32>, Array2<f32>) {
+        let n = output_gradient.nrows() as f32;
+        let shift_gradient = output_gradient.sum_axis(Axis(0));
+        let scale_gradient = (output_gradient * &cache.normalized).sum_axis(Axis(0));
+
+        // The batch mean and variance depend on every input, hence the two
+        // correction terms
+        let coefficient = &self.scale.row(0) * &cache.inv_std / n;
+        let input_gradient =
+            (output_gradient * n - &shift_gradient - &cache.normalized * &scale_gradient)
+                * &coefficient;
+
+        (
+            scale_gradient.insert_axis(Axis(0)),
+            shift_gradient,
+            input_gradient,
+        )
+    }
+
+    fn update_running_stats(&mut self, mean: &Array1<f32>, var: &Array1<f32>) {
+        self.running_mean =
+            &self.running_mean * BATCH_NORM_MOMENTUM + mean * (1.0 - BATCH_NORM_MOMENTUM);
+        self.running_var =
+            &self.running_var * BATCH_NORM_MOMENTUM + var * (1.0 - BATCH_NORM_MOMENTUM);
+    }
+}
+
+// A convolution, optionally followed by batch norm
+#[derive(Clone, Debug)]
+struct ConvBlock {
+    conv: Conv2d,
+    norm: Option<BatchNorm>,
+}
+
+struct ConvBlockCache {
+    patches: Array2<f32>,
+    norm: Option<BatchNormCache>,
+}
+
+impl ConvBlock {
+    fn new(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        rows: usize,
+        cols: usize,
+        batch_norm: bool,
+    ) -> Self {
+        ConvBlock {
+            conv: Conv2d::new(in_channels, out_channels, kernel_size, rows, cols),
+            norm: batch_norm.then(|| BatchNorm::new(out_channels)),
+        }
+    }
+
+    fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        let output = self.conv.forward_batch(input);
+        match &self.norm {
+            Some(norm) => norm.forward_batch(&output),
+            None => output,
+        }
+    }
+
+    fn forward_train(&self, input: &Array2<f32>) -> (Array2<f32>, ConvBlockCache) {
+        let patches = self.conv.patches(input);
+        let output = self.conv.kernel.forward_linear_batch(&patches);
+        match &self.norm {
+            Some(norm) => {
+                let (output, norm_cache) = norm.forward_train(&output);
+                let cache = ConvBlockCache {
+                    patches,
+                    norm: Some(norm_cache),
+                };
+                (output, cache)
+            }
+            None => (
+                output,
+                ConvBlockCache {
+                    patches,
+                    norm: None,
+                },
+            ),
+        }
+    }
+
+    // Pushes parameter gradients last-to-first onto `gradients`
+    fn backward(
+        &self,
+        cache: &ConvBlockCache,
+        output_gradient: &Array2<f32>,
+        gradients: &mut Vec<(Array2<f32>, Array1<f32>)>,
+    ) -> Array2<f32> {
+        let mut gradient = output_gradient.clone();
+        if let (Some(norm), Some(norm_cache)) = (&self.norm, &cache.norm) {
+            let (scale_gradient, shift_gradient, input_gradient) =
+                norm.backward(norm_cache, &gradient);
+            gradients.push((scale_gradient, shift_gradient));
+            gradient = input_gradient;
+        }
+        let (weight_gradients, bias_gradients, input_gradient) =
+            self.conv.backward(&cache.patches, &gradient);
+        gradients.push((weight_gradients, bias_gradients));
+        input_gradient
+    }
+}
+
+/// Two convolutions around a skip connection:
+/// `relu(second(relu(first(x))) + x)`.
+#[derive(Clone, Debug)]
+pub struct ResidualBlock {
+    first: ConvBlock,
+    second: ConvBlock,
+}
+
+struct ResidualCache {
+    first: ConvBlockCache,
+    hidden_linear: Array2<f32>,
+    second: ConvBlockCache,
+    output_linear: Array2<f32>,
+}
+
+impl ResidualBlock {
+    pub fn new(
+        channels: usize,
+        kernel_size: usize,
+        rows: usize,
+        cols: usize,
+        batch_norm: bool,
+    ) -> Self {
+        ResidualBlock {
+            first: ConvBlock::new(channels, channels, kernel_size, rows, cols, batch_norm),
+            second: ConvBlock::new(channels, channels, kernel_size, rows, cols, batch_norm),
+        }
+    }
+
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        let hidden = relu(&self.first.forward_batch(input));
+        relu(&(self.second.forward_batch(&hidden) + input))
+    }
+
+    fn forward_train(&self, input: &Array2<f32>) -> (Array2<f32>, ResidualCache) {
+        let (hidden_linear, first) = self.first.forward_train(input);
+        let (output, second) = self.second.forward_train(&relu(&hidden_linear));
+        let output_linear = output + input;
+        (
+            relu(&output_linear),
+            ResidualCache {
+                first,
+                hidden_linear,
+                second,
+                output_linear,
+            },
+        )
+    }
+
+    fn backward(
+        &self,
+        cache: &ResidualCache,
+        output_gradient: &Array2<f32>,
+        gradients: &mut Vec<(Array2<f32>, Array1<f32>)>,
+    ) -> Array2<f32> {
+        let gradient = relu_backward(output_gradient, &cache.output_linear);
+        let hidden_gradient = self.second.backward(&cache.second, &gradient, gradients);
+        let hidden_gradient = relu_backward(&hidden_gradient, &cache.hidden_linear);
+        // The skip connection passes the gradient straight through
+        self.first
+            .backward(&cache.first, &hidden_gradient, gradients)
+            + gradient
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConvNetworkConfig {
+    pub input_channels: usize,
+    pub rows: usize,
+    pub cols: usize,
+    pub filters: usize,
+    pub kernel_size: usize,
+    pub residual_blocks: usize,
+    pub batch_norm: bool,
+    /// Channels kept by the 1×1 convolution in front of the dense head.
+    pub head_channels: usize,
+    /// Hidden units in the dense head; 0 goes straight to the output.
+    pub head_hidden_size: usize,
+    pub output_size: usize,
+    pub output_activation: OutputActivation,
+}
+
+impl ConvNetworkConfig {
+    /// A small AlphaZero-style tower over `BoardPlanes` input.
+    pub fn connect_four(output_size: usize, output_activation: OutputActivation) -> Self {
+        ConvNetworkConfig {
+            input_channels: PLANES,
+            rows: ROWS,
+            cols: COLS,
+            filters: 32,
+            kernel_size: 3,
+            residual_blocks: 4,
+            batch_norm: true,
+            head_channels: 2,
+            head_hidden_size: 64,
+            output_size,
+            output_activation,
+        }
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.rows * self.cols * self.input_channels
+    }
+
+    /// Length of `ConvNetwork::get_weights`, running statistics included.
+    pub fn total_weights(&self) -> usize {
+        let conv_block = |in_channels: usize, out_channels: usize, kernel_size: usize| {
+            let norm = if self.batch_norm { 4 * out_channels } else { 0 };
+            (in_channels * kernel_size * kernel_size + 1) * out_channels + norm
+        };
+        let dense = NetworkConfig {
+            input_size: self.rows * self.cols * self.head_channels,
+            hidden_sizes: match self.head_hidden_size {
+                0 => vec![],
+                size => vec![size],
+            },
+            output_size: self.output_size,
+            output_activation: self.output_activation,
+        };
+
+        conv_block(self.input_channels, self.filters, self.kernel_size)
+            + 2 * self.residual_blocks * conv_block(self.filters, self.filters, self.kernel_size)
+            + conv_block(self.filters, self.head_channels, 1)
+            + dense.total_weights()
+    }
+}
+
+/// Convolutional tower (stem, residual blocks, 1×1 head convolution)
+/// feeding a dense output head. Inputs are flattened board planes, one
+/// sample per row, laid out cell by cell with channels innermost as
+/// produced by `BoardPlanes`.
+#[derive(Clone, Debug)]
+pub struct ConvNetwork {
+    stem: ConvBlock,
+    blocks: Vec<ResidualBlock>,
+    head: ConvBlock,
+    dense: NeuralNetwork,
+    config: ConvNetworkConfig,
+}
+
+impl ConvNetwork {
+    pub fn new(config: ConvNetworkConfig) -> Self {
+        let conv_block = |in_channels, out_channels, kernel_size| {
+            ConvBlock::new(
+                in_channels,
+                out_channels,
+                kernel_size,
+                config.rows,
+                config.cols,
+                config.batch_norm,
+            )
+        };
+        let stem = conv_block(config.input_channels, config.filters, config.kernel_size);
+        let blocks = (0..config.residual_blocks)
+            .map(|_| {
+                ResidualBlock::new(
+                    config.filters,
+                    config.kernel_size,
+                    config.rows,
+                    config.cols,
+                    config.batch_norm,
+                )
+            })
+            .collect();
+        let head = conv_block(config.filters, config.head_channels, 1);
+        let dense = NeuralNetwork::new(NetworkConfig {
+            input_size: config.rows * config.cols * config.head_channels,
+            hidden_sizes: match config.head_hidden_size {
+                0 => vec![],
+                size => vec![size],
+            },
+            output_size: config.output_size,
+            output_activation: config.output_activation,
+        });
+
+        ConvNetwork {
+            stem,
+            blocks,
+            head,
+            dense,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &ConvNetworkConfig {
+        &self.config
+    }
+
+    pub fn output_activation(&self) -> OutputActivation {
+        self.config.output_activation
+    }
+
+    pub fn forward(&self, input: &Array1<f32>) -> Array1<f32> {
+        let inputs = input.clone().insert_axis(Axis(0));
+        self.forward_batch(&inputs).row(0).to_owned()
+    }
+
+    pub fn forward_masked(&self, input: &Array1<f32>, legal_moves: &[bool]) -> Array1<f32> {
+        let inputs = input.clone().insert_axis(Axis(0));
+        let mask = Array2::from_shape_vec((1, legal_moves.len()), legal_moves.to_vec()).unwrap();
+        self.forward_batch_masked(&inputs, &mask).row(0).to_owned()
+    }
+
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        self.dense.forward_batch(&self.tower(input))
+    }
+
+    pub fn forward_batch_masked(
+        &self,
+        input: &Array2<f32>,
+        legal_moves: &Array2<bool>,
+    ) -> Array2<f32> {
+        self.dense
+            .forward_batch_masked(&self.tower(input), legal_moves)
+    }
+
+    // Flattened head features for each sample
+    fn tower(&self, input: &Array2<f32>) -> Array2<f32> {
+        let mut current = relu(
+            &self
+                .stem
+                .forward_batch(&to_cells(input, self.config.input_channels)),
+        );
+        for block in &self.blocks {
+            current = block.forward_batch(&current);
+        }
+        let head = relu(&self.head.forward_batch(&current));
+        from_cells(&head, input.nrows())
+    }
+
+    /// Mean loss and mean gradients over a batch, in the same order as
+    /// `apply_gradients` expects.
+    pub fn compute_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+    ) -> (f32, Gradients) {
+        let (loss, gradients, _) = self.backward(inputs, targets, None);
+        (loss, gradients)
+    }
+
+    pub fn compute_masked_gradients(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: &Array2<bool>,
+    ) -> (f32, Gradients) {
+        let (loss, gradients, _) = self.backward(inputs, targets, Some(legal_moves));
+        (loss, gradients)
+    }
+
+    /// One optimizer step on a mini-batch; also folds the batch statistics
+    /// into the batch-norm running averages. Returns the mean loss.
+    pub fn train_batch(
+        &mut self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        optimizer: &mut Optimizer,
+    ) -> f32 {
+        let (loss, gradients, batch_stats) = self.backward(inputs, targets, None);
+        self.apply_gradients(&gradients, optimizer);
+        let norms = self
+            .conv_blocks_mut()
+            .into_iter()
+            .filter_map(|b| b.norm.as_mut());
+        for (norm, (mean, var)) in norms.zip(&batch_stats) {
+            norm.update_running_stats(mean, var);
+        }
+        loss
+    }
+
+    // Batch-norm layers use batch statistics here, which are returned in
+    // `conv_blocks` order
+    #[allow(clippy::type_complexity)]
+    fn backward(
+        &self,
+        inputs: &Array2<f32>,
+        targets: &Array2<f32>,
+        legal_moves: Option<&Array2<bool>>,
+    ) -> (f32, Gradients, Vec<(Array1<f32>, Array1<f32>)>) {
+        let (stem_linear, stem_cache) = self
+            .stem
+            .forward_train(&to_cells(inputs, self.config.input_channels));
+        let mut current = relu(&stem_linear);
+        let mut block_caches = Vec::with_capacity(self.blocks.len());
+        for block in &self.blocks {
+            let (output, cache) = block.forward_train(&current);
+            current = output;
+            block_caches.push(cache);
+        }
+        let (head_linear, head_cache) = self.head.forward_train(&current);
+        let features = from_cells(&relu(&head_linear), inputs.nrows());
+
+        let (loss, dense_gradients, feature_gradient) =
+            self.dense.backward(&features, targets, legal_moves);
+
+        let mut gradients = Vec::new();
+        let gradient = to_cells(&feature_gradient, self.config.head_channels);
+        let gradient = relu_backward(&gradient, &head_linear);
+        let mut gradient = self.head.backward(&head_cache, &gradient, &mut gradients);
+        for (block, cache) in self.blocks.iter().zip(&block_caches).rev() {
+            gradient = block.backward(cache, &gradient, &mut gradients);
+        }
+        let gradient = relu_backward(&gradient, &stem_linear);
+        self.stem.backward(&stem_cache, &gradient, &mut gradients);
+        gradients.reverse();
+        gradients.extend(dense_gradients.layers);
+
+        let mut caches = vec![&stem_cache];
+        for cache in &block_caches {
+            caches.push(&cache.first);
+            caches.push(&cache.second);
+        }
+        caches.push(&head_cache);
+        let batch_stats = caches
+            .into_iter()
+            .filter_map(|cache| cache.norm.as_ref())
+            .map(|norm| (norm.mean.clone(), norm.var.clone()))
+            .collect();
+
+        (loss, Gradients { layers: gradients }, batch_stats)
+    }
+
+    pub fn apply_gradients(&mut self, gradients: &Gradients, optimizer: &mut Optimizer) {
+        for (idx, ((weights, biases), layer_gradients)) in self
+            .parameters_mut()
+            .into_iter()
+            .zip(&gradients.layers)
+            .enumerate()
+        {
+            optimizer.update_layer(idx, weights, biases, layer_gradients);
+        }
+        optimizer.finish_step();
+        for layer in self.layers_mut() {
+            layer.quantized = None;
+        }
+    }
+
+    /// Quantises the convolution kernels and the dense head. Batch-norm
+    /// parameters stay f32.
+    pub fn quantize(&mut self, precision: Precision) {
+        for layer in self.layers_mut() {
+            layer.quantize(precision);
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.stem.conv.kernel.precision()
+    }
+
+    fn layers_mut(&mut self) -> Vec<&mut Layer> {
+        let mut layers = Vec::new();
+        let ConvNetwork {
+            stem,
+            blocks,
+            head,
+            dense,
+            ..
+        } = self;
+        layers.push(&mut stem.conv.kernel);
+        for block in blocks {
+            layers.push(&mut block.first.conv.kernel);
+            layers.push(&mut block.second.conv.kernel);
+        }
+        layers.push(&mut head.conv.kernel);
+        layers.extend(dense.layers.iter_mut());
+        layers
+    }
+
+    // Stem, each residual block's two convolutions, then the head
+    fn conv_blocks(&self) -> Vec<&ConvBlock> {
+        let mut blocks = vec![&self.stem];
+        for block in &self.blocks {
+            blocks.push(&block.first);
+            blocks.push(&block.second);
+        }
+        blocks.push(&self.head);
+        blocks
+    }
+
+    fn conv_blocks_mut(&mut self) -> Vec<&mut ConvBlock> {
+        let mut blocks = vec![&mut self.stem];
+        for block in &mut self.blocks {
+            blocks.push(&mut block.first);
+            blocks.push(&mut block.second);
+        }
+        blocks.push(&mut self.head);
+        blocks
+    }
+
+    fn parameters(&self) -> Vec<(&Array2<f32>, &Array1<f32>)> {
+        let mut parameters = Vec::new();
+        for block in self.conv_blocks() {
+            parameters.push((&block.conv.kernel.weights, &block.conv.kernel.biases));
+            if let Some(norm) = &block.norm {
+                parameters.push((&norm.scale, &norm.shift));
+            }
+        }
+        for layer in &self.dense.layers {
+            parameters.push((&layer.weights, &layer.biases));
+        }
+        parameters
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn parameters_mut(&mut self) -> Vec<(&mut Array2<f32>, &mut Array1<f32>)> {
+        let mut parameters = Vec::new();
+        let ConvNetwork {
+            stem,
+            blocks,
+            head,
+            dense,
+            ..
+        } = self;
+        let mut conv_blocks = vec![stem];
+        for block in blocks {
+            conv_blocks.push(&mut block.first);
+            conv_blocks.push(&mut block.second);
+        }
+        conv_blocks.push(head);
+        for block in conv_blocks {
+            parameters.push((
+                &mut block.conv.kernel.weights,
+                &mut block.conv.kernel.biases,
+            ));
+            if let Some(norm) = &mut block.norm {
+                parameters.push((&mut norm.scale, &mut norm.shift));
             }
         }
-
-        // Apply tanh activation to output for value network
-        if self.config.output_size == 1 {
-            current.mapv(|x| x.tanh())
-        } else {
-            // Apply softmax for policy network
-            self.softmax(&current)
+        for layer in &mut dense.layers {
+            parameters.push((&mut layer.weights, &mut layer.biases));
         }
+        parameters
     }
 
-    fn softmax(&self, input: &Array1<f32>) -> Array1<f32> {
-        let max_val = input.fold(f32::NEG_INFINITY, |a, &b| a.max(b));
-        let exp_input = input.mapv(|x| (x - max_val).exp());
-        let sum = exp_input.sum();
-        exp_input.mapv(|x| x / sum)
+    /// Length of the trainable prefix of `get_weights`, before the
+    /// batch-norm running statistics.
+    pub fn trainable_weight_count(&self) -> usize {
+        self.parameters()
+            .iter()
+            .map(|(weights, biases)| weights.len() + biases.len())
+            .sum()
     }
 
-    pub fn load_weights(&mut self, weights: &[f32]) {
-        let mut idx = 0;
+    /// Trainable parameters (weights then biases, layer by layer) followed
+    /// by each batch-norm layer's running mean and variance.
+    pub fn get_weights(&self) -> Vec<f32> {
+        let mut weights = Vec::new();
+        for (layer_weights, biases) in self.parameters() {
+            weights.extend(layer_weights.iter());
+            weights.extend(biases.iter());
+        }
+        for norm in self
+            .conv_blocks()
+            .into_iter()
+            .filter_map(|b| b.norm.as_ref())
+        {
+            weights.extend(norm.running_mean.iter());
+            weights.extend(norm.running_var.iter());
+        }
+        weights
+    }
 
-        for layer in &mut self.layers {
-            idx += layer.load_weights(&weights[idx..]);
+    pub fn load_weights(&mut self, weights: &[f32]) {
+        for layer in self.layers_mut() {
+            layer.quantized = None;
+        }
+        let mut values = weights.iter();
+        for (layer_weights, biases) in self.parameters_mut() {
+            fill(
+                &mut values,
+                layer_weights.iter_mut().chain(biases.iter_mut()),
+            );
+        }
+        let norms = self
+            .conv_blocks_mut()
+            .into_iter()
+            .filter_map(|b| b.norm.as_mut());
+        for norm in norms {
+            fill(
+                &mut values,
+                norm.running_mean
+                    .iter_mut()
+                    .chain(norm.running_var.iter_mut()),
+            );
         }
     }
+}
 
-    pub fn get_weights(&self) -> Vec<f32> {
-        let mut weights = Vec::new();
+/// Shape of a network, as recorded in model files.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Architecture {
+    Dense(NetworkConfig),
+    Conv(ConvNetworkConfig),
+}
 
-        for layer in &self.layers {
-            weights.extend(layer.get_weights());
+impl Architecture {
+    pub fn input_size(&self) -> usize {
+        match self {
+            Architecture::Dense(config) => config.input_size,
+            Architecture::Conv(config) => config.input_size(),
         }
-
-        weights
     }
 
-    pub fn save_weights(&self) -> Vec<f32> {
-        self.get_weights()
+    pub fn output_size(&self) -> usize {
+        match self {
+            Architecture::Dense(config) => config.output_size,
+            Architecture::Conv(config) => config.output_size,
+        }
     }
 
-    pub fn load_weights_from_file(
-        &mut self,
-        filename: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(filename)?;
-        let weights: Vec<f32> = serde_json::from_str(&content)?;
-        self.load_weights(&weights);
-        Ok(())
+    pub fn output_activation(&self) -> OutputActivation {
+        match self {
+            Architecture::Dense(config) => config.output_activation,
+            Architecture::Conv(config) => config.output_activation,
+        }
     }
 
-    pub fn save_weights_to_file(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let weights = self.get_weights();
-        let content = serde_json::to_string(&weights)?;
-        std::fs::write(filename, content)?;
-        Ok(())
+    pub fn total_weights(&self) -> usize {
+        match self {
+            Architecture::Dense(config) => config.total_weights(),
+            Architecture::Conv(config) => config.total_weights(),
+        }
     }
+}
 
-    pub fn num_layers(&self) -> usize {
-        self.layers.len()
-    }
+/// Either kind of network behind one interface, for callers that get their
+/// architecture from a model file.
+#[derive(Clone, Debug)]
+pub enum Network {
+    Dense(NeuralNetwork),
+    Conv(Box<ConvNetwork>),
+}
 
-    pub fn train_step(
-        &mut self,
-        input: &Array1<f32>,
-        target: &Array1<f32>,
-        learning_rate: f32,
-    ) -> f32 {
-        // Forward pass with caching
-        let mut activations = vec![input.clone()];
-        let mut linear_outputs = Vec::new();
+impl Network {
+    pub fn new(architecture: &Architecture) -> Self {
+        match architecture {
+            Architecture::Dense(config) => Network::Dense(NeuralNetwork::new(config.clone())),
+            Architecture::Conv(config) => Network::Conv(Box::new(ConvNetwork::new(config.clone()))),
+        }
+    }
 
-        for layer in &self.layers {
-            let (activated, linear) = layer.forward_with_cache(&activations.last().unwrap());
-            activations.push(activated);
-            linear_outputs.push(linear);
+    pub fn architecture(&self) -> Architecture {
+        match self {
+            Network::Dense(network) => Architecture::Dense(network.config().clone()),
+            Network::Conv(network) => Architecture::Conv(network.config().clone()),
         }
+    }
 
-        // Calculate loss and initial gradient
-        let output = activations.last().unwrap();
-        let (loss, mut gradient) = if self.config.output_size == 1 {
-            // MSE loss for value network
-            let diff = output - target;
-            let loss = diff.dot(&diff);
-            (loss, diff)
-        } else {
-            // Cross-entropy loss for policy network
-            let epsilon = 1e-7;
-            let mut ce_loss = 0.0;
-            let mut grad = Array1::zeros(output.len());
-
-            for i in 0..output.len() {
-                let pred = output[i].max(epsilon).min(1.0 - epsilon);
-                let true_val = target[i];
-                ce_loss -= true_val * pred.ln();
-                grad[i] = pred - true_val;
-            }
-            (ce_loss, grad)
-        };
+    pub fn forward(&self, input: &Array1<f32>) -> Array1<f32> {
+        match self {
+            Network::Dense(network) => network.forward(input),
+            Network::Conv(network) => network.forward(input),
+        }
+    }
 
-        // Backward pass through layers
-        let num_layers = self.layers.len();
-        for layer_idx in (0..num_layers).rev() {
-            let layer_input = &activations[layer_idx];
-            let linear_output = &linear_outputs[layer_idx];
+    pub fn forward_masked(&self, input: &Array1<f32>, legal_moves: &[bool]) -> Array1<f32> {
+        match self {
+            Network::Dense(network) => network.forward_masked(input, legal_moves),
+            Network::Conv(network) => network.forward_masked(input, legal_moves),
+        }
+    }
 
-            // Compute gradients for this layer
-            let (weight_gradients, bias_gradients, input_gradient) = self.compute_layer_gradients(
-                &self.layers[layer_idx],
-                layer_input,
-                linear_output,
-                &gradient,
-            );
+    pub fn forward_batch(&self, input: &Array2<f32>) -> Array2<f32> {
+        match self {
+            Network::Dense(network) => network.forward_batch(input),
+            Network::Conv(network) => network.forward_batch(input),
+        }
+    }
 
-            // Update weights and biases
-            self.layers[layer_idx].update_weights(
-                &weight_gradients,
-                &bias_gradients,
-                learning_rate,
-            );
+    pub fn forward_batch_masked(
+        &self,
+        input: &Array2<f32>,
+        legal_moves: &Array2<bool>,
+    ) -> Array2<f32> {
+        match self {
+            Network::Dense(network) => network.forward_batch_masked(input, legal_moves),
+            Network::Conv(network) => network.forward_batch_masked(input, legal_moves),
+        }
+    }
 
-            // Propagate gradient to previous layer
-            if layer_idx > 0 {
-                gradient = input_gradient;
-            }
+    pub fn get_weights(&self) -> Vec<f32> {
+        match self {
+            Network::Dense(network) => network.get_weights(),
+            Network::Conv(network) => network.get_weights(),
         }
+    }
 
-        loss
+    pub fn quantize(&mut self, precision: Precision) {
+        match self {
+            Network::Dense(network) => network.quantize(precision),
+            Network::Conv(network) => network.quantize(precision),
+        }
     }
 
-    fn compute_layer_gradients(
-        &self,
-        layer: &Layer,
-        input: &Array1<f32>,
-        linear_output: &Array1<f32>,
-        output_gradient: &Array1<f32>,
-    ) -> (Array2<f32>, Array1<f32>, Array1<f32>) {
-        // Compute activation gradient (ReLU derivative)
-        let activation_gradient = linear_output.mapv(|x| if x > 0.0 { 1.0 } else { 0.0 });
-        let layer_gradient = output_gradient * &activation_gradient;
+    pub fn precision(&self) -> Precision {
+        match self {
+            Network::Dense(network) => network.precision(),
+            Network::Conv(network) => network.precision(),
+        }
+    }
 
-        // Compute weight gradients
-        let shape = layer.weights.shape();
-        let mut weight_gradients = Array2::zeros((shape[0], shape[1]));
-        for i in 0..shape[0] {
-            for j in 0..shape[1] {
-                weight_gradients[[i, j]] = input[i] * layer_gradient[j];
-            }
+    /// Loads weights, rejecting a vector of the wrong length instead of
+    /// partially filling the network.
+    pub fn load_weights(&mut self, weights: &[f32]) -> Result<(), String> {
+        let expected = self.architecture().total_weights();
+        if weights.len() != expected {
+            return Err(format!(
+                "expected {} weights, got {}",
+                expected,
+                weights.len()
+            ));
+        }
+        match self {
+            Network::Dense(network) => network.load_weights(weights)?,
+            Network::Conv(network) => network.load_weights(weights),
         }
+        Ok(())
+    }
+}
 
-        // Compute bias gradients
-        let bias_gradients = layer_gradient.clone();
+// Per-sample rows of flattened planes to one row per board cell, and back
+fn to_cells(input: &Array2<f32>, channels: usize) -> Array2<f32> {
+    let cells = input.len() / channels;
+    input
+        .as_standard_layout()
+        .into_owned()
+        .into_shape((cells, channels))
+        .unwrap()
+}
 
-        // Compute input gradients for backpropagation
-        let input_gradient = layer_gradient.dot(&layer.weights.t());
+fn from_cells(cells: &Array2<f32>, batch_size: usize) -> Array2<f32> {
+    let width = cells.len() / batch_size.max(1);
+    cells
+        .as_standard_layout()
+        .into_owned()
+        .into_shape((batch_size, width))
+        .unwrap()
+}
 
-        (weight_gradients, bias_gradients, input_gradient)
+// Copies values into `targets` until either runs out
+fn fill<'a>(values: &mut std::slice::Iter<f32>, targets: impl Iterator<Item = &'a mut f32>) {
+    for (target, &value) in targets.zip(values) {
+        *target = value;
     }
 }
 
+fn relu(input: &Array2<f32>) -> Array2<f32> {
+    input.mapv(|x| x.max(0.0))
+}
+
+fn relu_backward(output_gradient: &Array2<f32>, linear: &Array2<f32>) -> Array2<f32> {
+    ndarray::Zip::from(output_gradient)
+        .and(linear)
+        .map_collect(|&g, &x| if x > 0.0 { g } else { 0.0 })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -315,6 +1650,7 @@ mod tests {
             input_size: 10,
             hidden_sizes: vec![5, 3],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         assert_eq!(
@@ -367,6 +1703,7 @@ mod tests {
             input_size: 10,
             hidden_sizes: vec![5, 3],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let network = NeuralNetwork::new(config);
@@ -379,6 +1716,7 @@ mod tests {
             input_size: 3,
             hidden_sizes: vec![2],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let network = NeuralNetwork::new(config);
@@ -395,6 +1733,7 @@ mod tests {
             input_size: 3,
             hidden_sizes: vec![2],
             output_size: 4,
+            output_activation: OutputActivation::Softmax,
         };
 
         let network = NeuralNetwork::new(config);
@@ -407,12 +1746,41 @@ mod tests {
         assert!(output.iter().all(|&x| x >= 0.0));
     }
 
+    #[test]
+    fn test_network_forward_batch_matches_forward() {
+        for (output_size, output_activation) in [
+            (1, OutputActivation::Tanh),
+            (1, OutputActivation::Linear),
+            (4, OutputActivation::Softmax),
+        ] {
+            let network = NeuralNetwork::new(NetworkConfig {
+                input_size: 3,
+                hidden_sizes: vec![5],
+                output_size,
+                output_activation,
+            });
+            let batch =
+                Array2::from_shape_vec((3, 3), vec![1.0, 2.0, 3.0, -1.0, 0.5, 0.0, 0.0, 0.0, 0.0])
+                    .unwrap();
+
+            let outputs = network.forward_batch(&batch);
+            assert_eq!(outputs.shape(), &[3, output_size]);
+            for (row, output) in batch.rows().into_iter().zip(outputs.rows()) {
+                let expected = network.forward(&row.to_owned());
+                for (a, b) in output.iter().zip(expected.iter()) {
+                    assert!((a - b).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_network_weight_saving_loading() {
         let config = NetworkConfig {
             input_size: 2,
             hidden_sizes: vec![3],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let network = NeuralNetwork::new(config.clone());
@@ -425,19 +1793,48 @@ mod tests {
 
         // Create new network and load weights
         let mut new_network = NeuralNetwork::new(config);
-        new_network.load_weights(&weights);
+        new_network.load_weights(&weights).unwrap();
 
         // Verify outputs are identical
         let new_output = new_network.forward(&input);
         assert!((original_output[0] - new_output[0]).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_network_rejects_weights_of_the_wrong_length() {
+        let config = NetworkConfig {
+            input_size: 2,
+            hidden_sizes: vec![3],
+            output_size: 1,
+            output_activation: OutputActivation::Tanh,
+        };
+        let mut network = NeuralNetwork::new(config.clone());
+        let original = network.get_weights();
+        let total = config.total_weights();
+
+        for len in [0, total - 1, total + 1] {
+            assert_eq!(
+                network.load_weights(&vec![0.5; len]),
+                Err(format!("expected {} weights, got {}", total, len))
+            );
+            assert_eq!(network.get_weights(), original);
+        }
+
+        let path = std::env::temp_dir().join(format!("short_weights_{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&vec![0.5; total - 1]).unwrap()).unwrap();
+        let result = network.load_weights_from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+        assert_eq!(network.get_weights(), original);
+    }
+
     #[test]
     fn test_network_training() {
         let config = NetworkConfig {
             input_size: 2,
             hidden_sizes: vec![3],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let mut network = NeuralNetwork::new(config);
@@ -476,6 +1873,7 @@ mod tests {
             input_size: 2,
             hidden_sizes: vec![3],
             output_size: 4,
+            output_activation: OutputActivation::Softmax,
         };
 
         let mut network = NeuralNetwork::new(config);
@@ -556,13 +1954,14 @@ mod tests {
             input_size: 2,
             hidden_sizes: vec![3],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let network = NeuralNetwork::new(config);
         let layer = &network.layers[0];
-        let input = Array1::from_vec(vec![1.0, 2.0]);
-        let linear_output = Array1::from_vec(vec![0.5, -0.3, 1.2]);
-        let output_gradient = Array1::from_vec(vec![0.1, 0.2, 0.3]);
+        let input = Array2::from_shape_vec((1, 2), vec![1.0, 2.0]).unwrap();
+        let linear_output = Array2::from_shape_vec((1, 3), vec![0.5, -0.3, 1.2]).unwrap();
+        let output_gradient = Array2::from_shape_vec((1, 3), vec![0.1, 0.2, 0.3]).unwrap();
 
         let (weight_gradients, bias_gradients, input_gradient) =
             network.compute_layer_gradients(layer, &input, &linear_output, &output_gradient);
@@ -570,13 +1969,315 @@ mod tests {
         // Check dimensions
         assert_eq!(weight_gradients.shape(), [2, 3]);
         assert_eq!(bias_gradients.len(), 3);
-        assert_eq!(input_gradient.len(), 2);
+        assert_eq!(input_gradient.shape(), [1, 2]);
 
         // Check that gradients are computed (not all zero)
         assert!(weight_gradients.iter().any(|&x| x != 0.0));
         assert!(bias_gradients.iter().any(|&x| x != 0.0));
     }
 
+    #[test]
+    fn test_batch_gradients_average_sample_gradients() {
+        let network = NeuralNetwork::new(NetworkConfig {
+            input_size: 3,
+            hidden_sizes: vec![4],
+            output_size: 2,
+            output_activation: OutputActivation::Softmax,
+        });
+        let inputs = Array2::from_shape_vec((2, 3), vec![1.0, -0.5, 2.0, 0.3, 0.8, -1.0]).unwrap();
+        let targets = Array2::from_shape_vec((2, 2), vec![1.0, 0.0, 0.0, 1.0]).unwrap();
+
+        let (batch_loss, batch_gradients) = network.compute_gradients(&inputs, &targets);
+
+        let mut summed: Option<Gradients> = None;
+        let mut summed_loss = 0.0;
+        for row in 0..2 {
+            let (loss, gradients) = network.compute_gradients(
+                &inputs.slice(ndarray::s![row..row + 1, ..]).to_owned(),
+                &targets.slice(ndarray::s![row..row + 1, ..]).to_owned(),
+            );
+            summed_loss += loss;
+            match summed.as_mut() {
+                Some(total) => total.accumulate(&gradients),
+                None => summed = Some(gradients),
+            }
+        }
+        let mut averaged = summed.unwrap();
+        averaged.scale(0.5);
+
+        assert!((batch_loss - summed_loss / 2.0).abs() < 1e-5);
+        for (batch, single) in batch_gradients.layers.iter().zip(&averaged.layers) {
+            for (a, b) in batch.0.iter().zip(single.0.iter()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+            for (a, b) in batch.1.iter().zip(single.1.iter()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_train_batch_reduces_loss_with_each_optimizer() {
+        let inputs = Array2::from_shape_fn((8, 2), |(i, j)| (i as f32 * 0.25) - j as f32 * 0.5);
+        let targets = Array2::from_shape_fn((8, 1), |(i, _)| 0.1 + i as f32 * 0.1);
+
+        for config in [
+            OptimizerConfig::sgd(0.01, 0.9),
+            OptimizerConfig::adam(0.01),
+            OptimizerConfig::adamw(0.01, 0.01),
+        ] {
+            let network_config = NetworkConfig {
+                input_size: 2,
+                hidden_sizes: vec![8],
+                output_size: 1,
+                output_activation: OutputActivation::Tanh,
+            };
+            // Start from fixed positive weights so no ReLU unit begins dead
+            let mut network = NeuralNetwork::new(network_config.clone());
+            network
+                .load_weights(&vec![0.1; network_config.total_weights()])
+                .unwrap();
+            let mut optimizer = Optimizer::new(config);
+
+            let (initial_loss, _) = network.compute_gradients(&inputs, &targets);
+            for _ in 0..200 {
+                network.train_batch(&inputs, &targets, &mut optimizer);
+            }
+            let (final_loss, _) = network.compute_gradients(&inputs, &targets);
+
+            assert!(
+                final_loss < initial_loss,
+                "{:?}: loss went from {} to {}",
+                config,
+                initial_loss,
+                final_loss
+            );
+            assert_eq!(optimizer.steps(), 200);
+        }
+    }
+
+    #[test]
+    fn test_output_activations() {
+        let network_for = |output_size, output_activation| {
+            let config = NetworkConfig {
+                input_size: 2,
+                hidden_sizes: vec![3],
+                output_size,
+                output_activation,
+            };
+            let mut network = NeuralNetwork::new(config.clone());
+            network
+                .load_weights(&vec![1.0; config.total_weights()])
+                .unwrap();
+            network
+        };
+        let input = Array1::from_vec(vec![1.0, 2.0]);
+
+        // Logits are 3 * (1 + 2 + 1) + 1 = 13 everywhere
+        let linear = network_for(1, OutputActivation::Linear).forward(&input);
+        assert!((linear[0] - 13.0).abs() < 1e-4);
+        let tanh = network_for(1, OutputActivation::Tanh).forward(&input);
+        assert!((tanh[0] - 13.0f32.tanh()).abs() < 1e-6);
+        let softmax = network_for(4, OutputActivation::Softmax).forward(&input);
+        assert!(softmax.iter().all(|&p| (p - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_masked_softmax_zeroes_illegal_moves() {
+        let network = NeuralNetwork::new(NetworkConfig {
+            input_size: 3,
+            hidden_sizes: vec![4],
+            output_size: 4,
+            output_activation: OutputActivation::Softmax,
+        });
+        let input = Array1::from_vec(vec![0.5, -1.0, 2.0]);
+        let legal = [true, false, true, false];
+
+        let output = network.forward_masked(&input, &legal);
+        assert_eq!(output[1], 0.0);
+        assert_eq!(output[3], 0.0);
+        assert!((output.sum() - 1.0).abs() < 1e-6);
+
+        // Renormalised over the legal moves only
+        let unmasked = network.forward(&input);
+        let legal_mass = unmasked[0] + unmasked[2];
+        assert!((output[0] - unmasked[0] / legal_mass).abs() < 1e-5);
+
+        let batch = network.forward_batch_masked(
+            &input.clone().insert_axis(Axis(0)),
+            &Array2::from_shape_vec((1, 4), legal.to_vec()).unwrap(),
+        );
+        for (a, b) in batch.row(0).iter().zip(output.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+
+        // With nothing legal the mask is ignored rather than dividing by zero
+        let none_legal = network.forward_masked(&input, &[false; 4]);
+        assert!((none_legal.sum() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_output_gradients_match_finite_differences() {
+        let inputs = Array2::from_shape_vec((2, 2), vec![0.5, -1.0, 1.5, 0.25]).unwrap();
+        let heads = [
+            (
+                OutputActivation::Tanh,
+                Array2::from_shape_vec((2, 1), vec![-0.5, 0.3]).unwrap(),
+            ),
+            (
+                OutputActivation::Linear,
+                Array2::from_shape_vec((2, 1), vec![-2.0, 1.5]).unwrap(),
+            ),
+            (
+                OutputActivation::Softmax,
+                Array2::from_shape_vec((2, 3), vec![0.0, 1.0, 0.0, 0.2, 0.3, 0.5]).unwrap(),
+            ),
+        ];
+
+        for (output_activation, targets) in heads {
+            let config = NetworkConfig {
+                input_size: 2,
+                hidden_sizes: vec![3],
+                output_size: targets.ncols(),
+                output_activation,
+            };
+            let mut network = NeuralNetwork::new(config.clone());
+            let weights: Vec<f32> = (0..config.total_weights())
+                // Offset so no hidden unit sits exactly on the ReLU kink
+                .map(|i| ((i * 7 % 11) as f32 - 4.7) * 0.1)
+                .collect();
+            network.load_weights(&weights).unwrap();
+            let (_, gradients) = network.compute_gradients(&inputs, &targets);
+            let analytic: Vec<f32> = gradients
+                .layers
+                .iter()
+                .flat_map(|(w, b)| w.iter().chain(b.iter()).copied().collect::<Vec<_>>())
+                .collect();
+
+            let epsilon = 1e-2;
+            for i in 0..weights.len() {
+                let mut shifted = weights.clone();
+                shifted[i] += epsilon;
+                network.load_weights(&shifted).unwrap();
+                let (loss_up, _) = network.compute_gradients(&inputs, &targets);
+                shifted[i] -= 2.0 * epsilon;
+                network.load_weights(&shifted).unwrap();
+                let (loss_down, _) = network.compute_gradients(&inputs, &targets);
+
+                let numeric = (loss_up - loss_down) / (2.0 * epsilon);
+                assert!(
+                    (numeric - analytic[i]).abs() < 1e-2 * (1.0 + numeric.abs()),
+                    "{:?} weight {}: numeric {} vs analytic {}",
+                    output_activation,
+                    i,
+                    numeric,
+                    analytic[i]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_masked_gradients_leave_illegal_logits_alone() {
+        let network = NeuralNetwork::new(NetworkConfig {
+            input_size: 2,
+            hidden_sizes: vec![3],
+            output_size: 3,
+            output_activation: OutputActivation::Softmax,
+        });
+        let inputs = Array2::from_shape_vec((1, 2), vec![1.0, -0.5]).unwrap();
+        let targets = Array2::from_shape_vec((1, 3), vec![1.0, 0.0, 0.0]).unwrap();
+        let legal = Array2::from_shape_vec((1, 3), vec![true, true, false]).unwrap();
+
+        let (_, gradients) = network.compute_masked_gradients(&inputs, &targets, &legal);
+        let (output_weights, output_biases) = gradients.layers.last().unwrap();
+        assert_eq!(output_biases[2], 0.0);
+        assert!(output_weights.column(2).iter().all(|&g| g == 0.0));
+        assert!(output_biases[0] < 0.0);
+    }
+
+    #[test]
+    fn test_train_network_fits_and_keeps_best_epoch() {
+        let inputs = Array2::from_shape_fn((40, 2), |(i, j)| ((i * (j + 3)) % 7) as f32 * 0.2);
+        let targets = inputs.map_axis(Axis(1), |row| (row[0] - row[1]).tanh() * 0.8);
+        let targets = targets.insert_axis(Axis(1));
+        let config = NetworkConfig {
+            input_size: 2,
+            hidden_sizes: vec![8],
+            output_size: 1,
+            output_activation: OutputActivation::Tanh,
+        };
+        let mut network = NeuralNetwork::new(config.clone());
+        network
+            .load_weights(&vec![0.1; config.total_weights()])
+            .unwrap();
+        let initial_loss = network.loss(&inputs, &targets);
+
+        let train_config = TrainNetworkConfig {
+            epochs: 40,
+            batch_size: 8,
+            optimizer: OptimizerConfig::adam(0.01),
+            schedule: LearningRateSchedule::Cosine { min_rate: 0.001 },
+            early_stopping_patience: 0,
+            ..TrainNetworkConfig::default()
+        };
+        let history = network.train_network(&inputs, &targets, &train_config);
+        assert_eq!(history.epochs(), 40);
+        assert!(!history.stopped_early);
+        assert_eq!(history.learning_rates[0], 0.01);
+        assert!((history.learning_rates[39] - 0.001).abs() < 1e-6);
+        assert!(network.loss(&inputs, &targets) < initial_loss / 2.0);
+
+        // The kept weights are the best epoch's, not the last one's
+        let best = history.best_validation_loss().unwrap();
+        assert!(history.validation_loss.iter().all(|&loss| loss >= best));
+    }
+
+    #[test]
+    fn test_train_network_stops_early_without_improvement() {
+        let inputs = Array2::from_shape_fn((10, 2), |(i, j)| (i + j) as f32 * 0.1);
+        let targets = Array2::from_elem((10, 1), 0.5);
+        let mut network = NeuralNetwork::new(NetworkConfig {
+            input_size: 2,
+            hidden_sizes: vec![4],
+            output_size: 1,
+            output_activation: OutputActivation::Tanh,
+        });
+        let config = TrainNetworkConfig {
+            epochs: 20,
+            early_stopping_patience: 2,
+            optimizer: OptimizerConfig::sgd(0.0, 0.0),
+            ..TrainNetworkConfig::default()
+        };
+
+        let history = network.train_network(&inputs, &targets, &config);
+        assert!(history.stopped_early);
+        assert_eq!(history.epochs(), 3);
+        assert_eq!(history.best_epoch, 0);
+    }
+
+    #[test]
+    fn test_value_head_learns_negative_targets() {
+        let config = NetworkConfig {
+            input_size: 2,
+            hidden_sizes: vec![4],
+            output_size: 1,
+            output_activation: OutputActivation::Tanh,
+        };
+        let mut network = NeuralNetwork::new(config.clone());
+        network
+            .load_weights(&vec![0.1; config.total_weights()])
+            .unwrap();
+        let input = Array1::from_vec(vec![1.0, 0.5]);
+        let target = Array1::from_vec(vec![-0.6]);
+
+        for _ in 0..200 {
+            network.train_step(&input, &target, 0.05);
+        }
+
+        assert!((network.forward(&input)[0] + 0.6).abs() < 0.05);
+    }
+
     #[test]
     fn test_layer_weight_update() {
         let mut layer = Layer::new(2, 3);
@@ -600,6 +2301,7 @@ mod tests {
             input_size: 1,
             hidden_sizes: vec![4],
             output_size: 1,
+            output_activation: OutputActivation::Tanh,
         };
 
         let mut network = NeuralNetwork::new(config);
@@ -646,4 +2348,212 @@ mod tests {
         // The loss decrease and reasonable final error are sufficient indicators
         // that the training process is working correctly.
     }
+
+    fn tiny_conv_config(
+        output_size: usize,
+        output_activation: OutputActivation,
+    ) -> ConvNetworkConfig {
+        ConvNetworkConfig {
+            input_channels: 2,
+            rows: 3,
+            cols: 4,
+            filters: 3,
+            kernel_size: 3,
+            residual_blocks: 1,
+            batch_norm: true,
+            head_channels: 1,
+            head_hidden_size: 4,
+            output_size,
+            output_activation,
+        }
+    }
+
+    #[test]
+    fn test_conv2d_same_padding() {
+        let mut conv = Conv2d::new(1, 1, 3, 3, 3);
+        conv.kernel.weights.fill(1.0);
+        conv.kernel.biases.fill(0.0);
+
+        // Summing 3x3 neighbourhoods of ones counts the on-board cells
+        let output = conv.forward_batch(&Array2::ones((9, 1)));
+        assert_eq!(
+            output.column(0).to_vec(),
+            vec![4.0, 6.0, 4.0, 6.0, 9.0, 6.0, 4.0, 6.0, 4.0]
+        );
+
+        // Samples in a batch do not bleed into each other
+        let batch = conv.forward_batch(&Array2::ones((18, 1)));
+        assert_eq!(batch.slice(ndarray::s![9.., ..]), output);
+    }
+
+    #[test]
+    fn test_batch_norm_train_and_inference() {
+        let mut norm = BatchNorm::new(2);
+        let input =
+            Array2::from_shape_vec((4, 2), vec![1.0, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0])
+                .unwrap();
+
+        let (output, cache) = norm.forward_train(&input);
+        for column in output.columns() {
+            assert!(column.mean().unwrap().abs() < 1e-5);
+            assert!((column.mapv(|x| x * x).mean().unwrap() - 1.0).abs() < 1e-3);
+        }
+
+        // Fresh running statistics are the identity transform
+        assert!((norm.forward_batch(&input) - &input)
+            .iter()
+            .all(|d| d.abs() < 1e-3));
+        norm.update_running_stats(&cache.mean, &cache.var);
+        assert!((norm.running_mean[1] - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_residual_block_skip_connection() {
+        let mut block = ResidualBlock::new(2, 3, 2, 2, false);
+        for conv in [&mut block.first.conv, &mut block.second.conv] {
+            conv.kernel.weights.fill(0.0);
+            conv.kernel.biases.fill(0.0);
+        }
+
+        let input =
+            Array2::from_shape_vec((4, 2), vec![1.0, -1.0, 0.5, 2.0, -3.0, 0.0, 4.0, 1.0]).unwrap();
+        assert_eq!(block.forward_batch(&input), relu(&input));
+    }
+
+    #[test]
+    fn test_conv_network_heads() {
+        let input = Array1::from_shape_fn(24, |i| (i % 3) as f32);
+
+        let value = ConvNetwork::new(tiny_conv_config(1, OutputActivation::Tanh));
+        let output = value.forward(&input);
+        assert_eq!(output.len(), 1);
+        assert!(output[0] > -1.0 && output[0] < 1.0);
+
+        let policy = ConvNetwork::new(tiny_conv_config(4, OutputActivation::Softmax));
+        let output = policy.forward_masked(&input, &[true, false, true, true]);
+        assert_eq!(output[1], 0.0);
+        assert!((output.sum() - 1.0).abs() < 1e-5);
+
+        let batch = Array2::from_shape_fn((2, 24), |(_, i)| (i % 3) as f32);
+        for row in policy.forward_batch(&batch).rows() {
+            for (a, b) in row.iter().zip(policy.forward(&input).iter()) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_four_tower_runs_on_board_planes() {
+        use crate::features::BoardPlanes;
+        use crate::GameState;
+
+        let config = ConvNetworkConfig::connect_four(COLS, OutputActivation::Softmax);
+        let network = ConvNetwork::new(config.clone());
+        let mut state = GameState::new();
+        state.make_move(3).unwrap();
+
+        let planes = BoardPlanes::batch_array(&[GameState::new(), state]);
+        assert_eq!(planes.ncols(), config.input_size());
+        let policy = network.forward_batch(&planes);
+        assert_eq!(policy.shape(), &[2, COLS]);
+        assert!(policy
+            .rows()
+            .into_iter()
+            .all(|row| (row.sum() - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_conv_network_gradients_match_finite_differences() {
+        let inputs =
+            Array2::from_shape_fn((3, 24), |(b, i)| ((b * 5 + i * 7) % 4) as f32 * 0.5 - 0.6);
+        let targets = Array2::from_shape_vec((3, 1), vec![0.4, -0.3, 0.1]).unwrap();
+
+        let mut network = ConvNetwork::new(tiny_conv_config(1, OutputActivation::Tanh));
+        let trainable: usize = network
+            .parameters()
+            .iter()
+            .map(|(w, b)| w.len() + b.len())
+            .sum();
+        let mut weights = network.get_weights();
+        for (i, weight) in weights.iter_mut().take(trainable).enumerate() {
+            // Offset so no unit sits exactly on a ReLU kink
+            *weight = ((i * 7 % 13) as f32 - 5.7) * 0.08;
+        }
+        network.load_weights(&weights);
+
+        let (_, gradients) = network.compute_gradients(&inputs, &targets);
+        let analytic: Vec<f32> = gradients
+            .layers
+            .iter()
+            .flat_map(|(w, b)| w.iter().chain(b.iter()).copied().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(analytic.len(), trainable);
+
+        // A batch-norm scale shifts every cell of its channel, so a larger step
+        // pushes some of them across a ReLU kink
+        let epsilon = 3e-3;
+        let mut mismatches = 0;
+        for i in 0..trainable {
+            let mut shifted = weights.clone();
+            shifted[i] += epsilon;
+            network.load_weights(&shifted);
+            let (loss_up, _) = network.compute_gradients(&inputs, &targets);
+            shifted[i] -= 2.0 * epsilon;
+            network.load_weights(&shifted);
+            let (loss_down, _) = network.compute_gradients(&inputs, &targets);
+
+            let numeric = (loss_up - loss_down) / (2.0 * epsilon);
+            if (numeric - analytic[i]).abs() > 2e-2 * (1.0 + numeric.abs()) {
+                mismatches += 1;
+            }
+        }
+        assert_eq!(
+            mismatches, 0,
+            "{} of {} gradients disagree",
+            mismatches, trainable
+        );
+    }
+
+    #[test]
+    fn test_conv_network_weights_round_trip() {
+        let config = tiny_conv_config(4, OutputActivation::Softmax);
+        let mut network = ConvNetwork::new(config.clone());
+        let inputs = Array2::from_shape_fn((4, 24), |(b, i)| ((b + i) % 3) as f32);
+        let targets = Array2::from_shape_fn((4, 4), |(b, j)| if b == j { 1.0 } else { 0.0 });
+        network.train_batch(
+            &inputs,
+            &targets,
+            &mut Optimizer::new(OptimizerConfig::adam(0.01)),
+        );
+
+        let mut copy = ConvNetwork::new(config);
+        copy.load_weights(&network.get_weights());
+        assert_eq!(copy.get_weights(), network.get_weights());
+        assert_eq!(copy.forward_batch(&inputs), network.forward_batch(&inputs));
+    }
+
+    #[test]
+    fn test_conv_network_training_reduces_loss() {
+        // Without a hidden head layer, whose few ReLUs can all start dead
+        let config = ConvNetworkConfig {
+            head_hidden_size: 0,
+            ..tiny_conv_config(1, OutputActivation::Tanh)
+        };
+        let mut network = ConvNetwork::new(config);
+        let inputs = Array2::from_shape_fn((6, 24), |(b, i)| ((b * 3 + i) % 7) as f32 * 0.25);
+        let targets = Array2::from_shape_fn((6, 1), |(b, _)| if b % 2 == 0 { 0.5 } else { -0.5 });
+        let mut optimizer = Optimizer::new(OptimizerConfig::adam(0.01));
+
+        let (initial_loss, _) = network.compute_gradients(&inputs, &targets);
+        for _ in 0..100 {
+            network.train_batch(&inputs, &targets, &mut optimizer);
+        }
+        let (final_loss, _) = network.compute_gradients(&inputs, &targets);
+        assert!(
+            final_loss < initial_loss * 0.5,
+            "{} -> {}",
+            initial_loss,
+            final_loss
+        );
+    }
 }
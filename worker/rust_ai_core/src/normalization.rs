@@ -0,0 +1,116 @@
+//! Per-feature input scaling learned from training data.
+//!
+//! A `FeatureNormalizer` is fitted once over a dataset's feature matrix and
+//! stored in the model file, so `MLAI` scales its inputs exactly as the
+//! trainer did.
+
+use ndarray::{Array2, ArrayViewMut1, Axis};
+use serde::{Deserialize, Serialize};
+
+// Features that barely vary are only centred, not blown up
+const MIN_STD: f32 = 1e-6;
+
+/// Maps each feature `x` to `(x - mean) / std`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeatureNormalizer {
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>,
+}
+
+impl FeatureNormalizer {
+    /// Mean and standard deviation of every column of `features`, one row
+    /// per sample. Constant columns get a standard deviation of 1.
+    pub fn fit(features: &Array2<f32>) -> Self {
+        let Some(mean) = features.mean_axis(Axis(0)) else {
+            return FeatureNormalizer {
+                mean: vec![0.0; features.ncols()],
+                std: vec![1.0; features.ncols()],
+            };
+        };
+        let std = features.std_axis(Axis(0), 0.0);
+
+        FeatureNormalizer {
+            mean: mean.to_vec(),
+            std: std
+                .iter()
+                .map(|&std| if std > MIN_STD { std } else { 1.0 })
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mean.is_empty()
+    }
+
+    pub fn validate(&self, size: usize) -> Result<(), String> {
+        if self.mean.len() != size || self.std.len() != size {
+            return Err(format!(
+                "normalizer has {} means and {} deviations, the features have {} values",
+                self.mean.len(),
+                self.std.len(),
+                size
+            ));
+        }
+        if self.mean.iter().any(|m| !m.is_finite())
+            || self.std.iter().any(|s| !s.is_finite() || *s <= 0.0)
+        {
+            return Err("normalizer has a non-finite mean or non-positive deviation".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, mut features: ArrayViewMut1<f32>) {
+        for ((x, mean), std) in features.iter_mut().zip(&self.mean).zip(&self.std) {
+            *x = (*x - mean) / std;
+        }
+    }
+
+    /// Scales every row of `features` in place.
+    pub fn apply_batch(&self, features: &mut Array2<f32>) {
+        for row in features.rows_mut() {
+            self.apply(row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fitted_features_are_standardised() {
+        let raw = Array2::from_shape_fn((50, 3), |(i, j)| match j {
+            0 => i as f32 * 40.0,
+            1 => (i % 2) as f32 * 0.01 - 3.0,
+            _ => 7.0,
+        });
+        let normalizer = FeatureNormalizer::fit(&raw);
+        assert_eq!(normalizer.len(), 3);
+        assert_eq!(normalizer.std[2], 1.0);
+        normalizer.validate(3).unwrap();
+        assert!(normalizer.validate(4).is_err());
+
+        let mut features = raw.clone();
+        normalizer.apply_batch(&mut features);
+        for (col, column) in features.columns().into_iter().enumerate() {
+            let mean = column.mean().unwrap();
+            let var = column.mapv(|x| (x - mean) * (x - mean)).mean().unwrap();
+            assert!(mean.abs() < 1e-4, "column {} mean {}", col, mean);
+            let expected_var = if col == 2 { 0.0 } else { 1.0 };
+            assert!(
+                (var - expected_var).abs() < 1e-3,
+                "column {} var {}",
+                col,
+                var
+            );
+        }
+
+        let mut single = raw.row(7).to_owned();
+        normalizer.apply(single.view_mut());
+        assert_eq!(single, features.row(7));
+    }
+}
@@ -0,0 +1,356 @@
+//! Parameter update rules for `NeuralNetwork` training.
+//!
+//! An `Optimizer` owns the per-parameter state (momentum buffers, Adam
+//! moments) for one network and turns accumulated `Gradients` into weight
+//! updates via `NeuralNetwork::apply_gradients`.
+
+use ndarray::{Array, Array1, Array2, Dimension, Zip};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptimizerConfig {
+    Sgd {
+        learning_rate: f32,
+        momentum: f32,
+    },
+    Adam {
+        learning_rate: f32,
+        beta1: f32,
+        beta2: f32,
+        epsilon: f32,
+    },
+    /// Adam with decoupled weight decay (Loshchilov & Hutter).
+    AdamW {
+        learning_rate: f32,
+        beta1: f32,
+        beta2: f32,
+        epsilon: f32,
+        weight_decay: f32,
+    },
+}
+
+impl OptimizerConfig {
+    pub fn sgd(learning_rate: f32, momentum: f32) -> Self {
+        OptimizerConfig::Sgd {
+            learning_rate,
+            momentum,
+        }
+    }
+
+    pub fn adam(learning_rate: f32) -> Self {
+        OptimizerConfig::Adam {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+        }
+    }
+
+    pub fn adamw(learning_rate: f32, weight_decay: f32) -> Self {
+        OptimizerConfig::AdamW {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            weight_decay,
+        }
+    }
+
+    pub fn learning_rate(&self) -> f32 {
+        match *self {
+            OptimizerConfig::Sgd { learning_rate, .. }
+            | OptimizerConfig::Adam { learning_rate, .. }
+            | OptimizerConfig::AdamW { learning_rate, .. } => learning_rate,
+        }
+    }
+}
+
+/// How the learning rate changes from epoch to epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LearningRateSchedule {
+    #[default]
+    Constant,
+    /// Multiply the rate by `factor` every `every` epochs.
+    Step { every: usize, factor: f32 },
+    /// Cosine decay from the initial rate to `min_rate` over the run.
+    Cosine { min_rate: f32 },
+    /// Multiply the rate by `factor` each time `patience` epochs pass
+    /// without the validation loss improving.
+    ReduceOnPlateau { factor: f32, patience: usize },
+}
+
+impl LearningRateSchedule {
+    /// Rate for `epoch` (counting from 0) of `epochs`. `current` is the
+    /// rate used so far and `stale_epochs` the number of epochs since the
+    /// validation loss last improved; only `ReduceOnPlateau` uses them.
+    pub fn learning_rate(
+        &self,
+        initial: f32,
+        current: f32,
+        epoch: usize,
+        epochs: usize,
+        stale_epochs: usize,
+    ) -> f32 {
+        match *self {
+            LearningRateSchedule::Constant => initial,
+            LearningRateSchedule::Step { every, factor } => {
+                initial * factor.powi((epoch / every.max(1)) as i32)
+            }
+            LearningRateSchedule::Cosine { min_rate } => {
+                let progress = epoch as f32 / epochs.saturating_sub(1).max(1) as f32;
+                min_rate
+                    + (initial - min_rate) * 0.5 * (1.0 + (std::f32::consts::PI * progress).cos())
+            }
+            LearningRateSchedule::ReduceOnPlateau { factor, patience } => {
+                if stale_epochs > 0 && stale_epochs.is_multiple_of(patience.max(1)) {
+                    current * factor
+                } else {
+                    current
+                }
+            }
+        }
+    }
+}
+
+/// Weight and bias gradients for every layer of a network, summed or
+/// averaged over however many samples produced them.
+#[derive(Clone, Debug)]
+pub struct Gradients {
+    pub layers: Vec<(Array2<f32>, Array1<f32>)>,
+}
+
+impl Gradients {
+    /// Adds another batch's gradients, e.g. to accumulate several
+    /// mini-batches before a single optimizer step.
+    pub fn accumulate(&mut self, other: &Gradients) {
+        for ((weights, biases), (other_weights, other_biases)) in
+            self.layers.iter_mut().zip(&other.layers)
+        {
+            *weights += other_weights;
+            *biases += other_biases;
+        }
+    }
+
+    pub fn scale(&mut self, factor: f32) {
+        for (weights, biases) in &mut self.layers {
+            weights.mapv_inplace(|g| g * factor);
+            biases.mapv_inplace(|g| g * factor);
+        }
+    }
+}
+
+// First and second moment buffers for one layer; SGD only uses the first
+#[derive(Clone, Debug)]
+struct LayerState {
+    weight_moments: (Array2<f32>, Array2<f32>),
+    bias_moments: (Array1<f32>, Array1<f32>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Optimizer {
+    pub config: OptimizerConfig,
+    step: i32,
+    state: Vec<LayerState>,
+}
+
+impl Optimizer {
+    pub fn new(config: OptimizerConfig) -> Self {
+        Self {
+            config,
+            step: 0,
+            state: Vec::new(),
+        }
+    }
+
+    /// Number of updates applied so far.
+    pub fn steps(&self) -> i32 {
+        self.step
+    }
+
+    pub fn set_learning_rate(&mut self, rate: f32) {
+        match &mut self.config {
+            OptimizerConfig::Sgd { learning_rate, .. }
+            | OptimizerConfig::Adam { learning_rate, .. }
+            | OptimizerConfig::AdamW { learning_rate, .. } => *learning_rate = rate,
+        }
+    }
+
+    /// Updates one layer's parameters in place; `layer` indexes the
+    /// optimizer state, which is created on first use.
+    pub(crate) fn update_layer(
+        &mut self,
+        layer: usize,
+        weights: &mut Array2<f32>,
+        biases: &mut Array1<f32>,
+        gradients: &(Array2<f32>, Array1<f32>),
+    ) {
+        while self.state.len() <= layer {
+            self.state.push(LayerState {
+                weight_moments: (Array2::zeros(weights.dim()), Array2::zeros(weights.dim())),
+                bias_moments: (Array1::zeros(biases.dim()), Array1::zeros(biases.dim())),
+            });
+        }
+
+        let config = self.config;
+        let step = self.step + 1;
+        let state = &mut self.state[layer];
+        update(
+            config,
+            step,
+            weights,
+            &gradients.0,
+            &mut state.weight_moments,
+        );
+        update(config, step, biases, &gradients.1, &mut state.bias_moments);
+    }
+
+    /// Marks the end of one optimizer step across all layers.
+    pub(crate) fn finish_step(&mut self) {
+        self.step += 1;
+    }
+}
+
+fn update<D: Dimension>(
+    config: OptimizerConfig,
+    step: i32,
+    params: &mut Array<f32, D>,
+    gradients: &Array<f32, D>,
+    moments: &mut (Array<f32, D>, Array<f32, D>),
+) {
+    let (first, second) = moments;
+    match config {
+        OptimizerConfig::Sgd {
+            learning_rate,
+            momentum,
+        } => {
+            Zip::from(params)
+                .and(gradients)
+                .and(first)
+                .for_each(|p, &g, velocity| {
+                    *velocity = momentum * *velocity + g;
+                    *p -= learning_rate * *velocity;
+                });
+        }
+        OptimizerConfig::Adam {
+            learning_rate,
+            beta1,
+            beta2,
+            epsilon,
+        }
+        | OptimizerConfig::AdamW {
+            learning_rate,
+            beta1,
+            beta2,
+            epsilon,
+            ..
+        } => {
+            let weight_decay = match config {
+                OptimizerConfig::AdamW { weight_decay, .. } => weight_decay,
+                _ => 0.0,
+            };
+            let first_correction = 1.0 - beta1.powi(step);
+            let second_correction = 1.0 - beta2.powi(step);
+
+            Zip::from(params)
+                .and(gradients)
+                .and(first)
+                .and(second)
+                .for_each(|p, &g, m, v| {
+                    *m = beta1 * *m + (1.0 - beta1) * g;
+                    *v = beta2 * *v + (1.0 - beta2) * g * g;
+                    let m_hat = *m / first_correction;
+                    let v_hat = *v / second_correction;
+                    *p -= learning_rate * (m_hat / (v_hat.sqrt() + epsilon) + weight_decay * *p);
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_param_step(config: OptimizerConfig, param: f32, gradient: f32) -> f32 {
+        let mut optimizer = Optimizer::new(config);
+        let mut weights = Array2::from_elem((1, 1), param);
+        let mut biases = Array1::zeros(1);
+        let gradients = (Array2::from_elem((1, 1), gradient), Array1::zeros(1));
+        optimizer.update_layer(0, &mut weights, &mut biases, &gradients);
+        optimizer.finish_step();
+        weights[[0, 0]]
+    }
+
+    #[test]
+    fn test_sgd_step() {
+        let updated = single_param_step(OptimizerConfig::sgd(0.1, 0.9), 1.0, 2.0);
+        assert!((updated - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sgd_momentum_accumulates_velocity() {
+        let mut optimizer = Optimizer::new(OptimizerConfig::sgd(0.1, 0.5));
+        let mut weights = Array2::from_elem((1, 1), 0.0);
+        let mut biases = Array1::zeros(1);
+        let gradients = (Array2::from_elem((1, 1), 1.0), Array1::zeros(1));
+
+        for _ in 0..2 {
+            optimizer.update_layer(0, &mut weights, &mut biases, &gradients);
+            optimizer.finish_step();
+        }
+
+        // Velocities 1.0 then 1.5
+        assert!((weights[[0, 0]] + 0.25).abs() < 1e-6);
+        assert_eq!(optimizer.steps(), 2);
+    }
+
+    #[test]
+    fn test_adam_first_step_moves_by_learning_rate() {
+        // Bias correction makes the first Adam step exactly lr * sign(g)
+        let updated = single_param_step(OptimizerConfig::adam(0.01), 1.0, 123.0);
+        assert!((updated - 0.99).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_adamw_decays_weights_without_gradient() {
+        let updated = single_param_step(OptimizerConfig::adamw(0.1, 0.5), 2.0, 0.0);
+        assert!((updated - 1.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_learning_rate_schedules() {
+        let step = LearningRateSchedule::Step {
+            every: 2,
+            factor: 0.5,
+        };
+        let rates: Vec<f32> = (0..5)
+            .map(|epoch| step.learning_rate(0.1, 0.1, epoch, 5, 0))
+            .collect();
+        assert_eq!(rates, vec![0.1, 0.1, 0.05, 0.05, 0.025]);
+
+        let cosine = LearningRateSchedule::Cosine { min_rate: 0.01 };
+        assert!((cosine.learning_rate(0.1, 0.1, 0, 11, 0) - 0.1).abs() < 1e-6);
+        assert!((cosine.learning_rate(0.1, 0.1, 5, 11, 0) - 0.055).abs() < 1e-6);
+        assert!((cosine.learning_rate(0.1, 0.1, 10, 11, 0) - 0.01).abs() < 1e-6);
+
+        let plateau = LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.1,
+            patience: 2,
+        };
+        assert_eq!(plateau.learning_rate(1.0, 0.5, 3, 10, 1), 0.5);
+        assert_eq!(plateau.learning_rate(1.0, 0.5, 3, 10, 2), 0.05);
+        assert_eq!(plateau.learning_rate(1.0, 0.5, 3, 10, 0), 0.5);
+    }
+
+    #[test]
+    fn test_gradients_accumulate_and_scale() {
+        let mut total = Gradients {
+            layers: vec![(Array2::from_elem((2, 2), 1.0), Array1::from_elem(2, 2.0))],
+        };
+        total.accumulate(&total.clone());
+        total.scale(0.25);
+
+        assert!(total.layers[0].0.iter().all(|&g| (g - 0.5).abs() < 1e-6));
+        assert!(total.layers[0].1.iter().all(|&g| (g - 1.0).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,513 @@
+//! Closed-loop self-play training with a gating match.
+//!
+//! Each generation plays games with the current best network, fine-tunes a
+//! copy of it on the newest shards of that data, and plays the candidate
+//! against the incumbent. The candidate replaces it only when it scores at
+//! least `0.5 + margin`. A run directory holds everything:
+//!
+//! ```text
+//! shards/                      self-play data (see `ShardWriter`)
+//! generations/0003/model.json  each generation's candidate
+//! generations/0003/metrics.json
+//! best.json                    the current best model
+//! history.json                 every generation's metrics
+//! ```
+
+use crate::dataset::ShardWriter;
+use crate::mcts::MCTS;
+use crate::ml_ai::MLAI;
+use crate::model::{ModelFile, TrainingMetadata};
+use crate::neural_network::TrainNetworkConfig;
+use crate::replay_buffer::ReplayBuffer;
+use crate::self_play::{AdjudicationStats, SelfPlayConfig, SelfPlayTrainer};
+use crate::supervised::{fine_tune_model, SelfPlayDataset};
+use crate::{GameState, Player};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct GatingConfig {
+    /// Games per match; each opening is played once from either side.
+    pub games: usize,
+    pub mcts_simulations: usize,
+    pub exploration_constant: f32,
+    /// Random moves before the networks take over, so deterministic
+    /// searches don't replay the same game.
+    pub opening_moves: usize,
+    /// Score above an even match the candidate needs, e.g. 0.05 for 55%.
+    pub margin: f32,
+    pub seed: u64,
+}
+
+impl Default for GatingConfig {
+    fn default() -> Self {
+        GatingConfig {
+            games: 40,
+            mcts_simulations: 200,
+            exploration_constant: 1.0,
+            opening_moves: 2,
+            margin: 0.05,
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PipelineConfig {
+    pub generations: usize,
+    pub self_play: SelfPlayConfig,
+    pub training: TrainNetworkConfig,
+    pub samples_per_shard: usize,
+    /// Shards the candidate trains on, newest first.
+    pub replay_shards: usize,
+    pub gating: GatingConfig,
+}
+
+/// A match from the candidate's side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Wins plus half the draws, as a fraction of the games.
+    pub fn score(&self) -> f32 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        (self.wins as f32 + 0.5 * self.draws as f32) / self.games() as f32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenerationMetrics {
+    pub generation: usize,
+    /// Samples this generation's self-play added.
+    pub samples: usize,
+    /// Resignations and draw calls in this generation's self-play, with
+    /// their audited error rates.
+    #[serde(default)]
+    pub adjudication: AdjudicationStats,
+    /// Samples in the replay window the candidate trained on.
+    pub training_samples: usize,
+    pub final_loss: Option<f32>,
+    pub gating: MatchResult,
+    pub promoted: bool,
+    /// Generation of the best model once gating is done; 0 is the
+    /// starting network.
+    pub best_generation: usize,
+    pub finished_at: String,
+}
+
+/// Plays `candidate` against `incumbent` with MCTS on both sides.
+pub fn play_match(candidate: &MLAI, incumbent: &MLAI, config: &GatingConfig) -> MatchResult {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let openings: Vec<GameState> = (0..config.games.div_ceil(2))
+        .map(|_| random_opening(config.opening_moves, &mut rng))
+        .collect();
+
+    let outcomes: Vec<Option<bool>> = (0..config.games)
+        .into_par_iter()
+        .map(|game| {
+            let candidate_side = if game % 2 == 0 {
+                Player::Player1
+            } else {
+                Player::Player2
+            };
+            let opening = openings[game / 2].clone();
+            play_gating_game(candidate, incumbent, opening, candidate_side, config)
+                .map(|winner| winner == candidate_side)
+        })
+        .collect();
+
+    let mut result = MatchResult::default();
+    for outcome in outcomes {
+        match outcome {
+            Some(true) => result.wins += 1,
+            Some(false) => result.losses += 1,
+            None => result.draws += 1,
+        }
+    }
+    result
+}
+
+fn random_opening(moves: usize, rng: &mut StdRng) -> GameState {
+    let mut state = GameState::new();
+    state.current_player = Player::Player1;
+    for _ in 0..moves {
+        match state.get_valid_moves().choose(rng) {
+            Some(&col) if !state.is_game_over() => state.make_move(col).unwrap(),
+            _ => break,
+        }
+    }
+    state
+}
+
+// Winner of one game, `None` for a draw
+fn play_gating_game(
+    candidate: &MLAI,
+    incumbent: &MLAI,
+    mut state: GameState,
+    candidate_side: Player,
+    config: &GatingConfig,
+) -> Option<Player> {
+    let mut mcts = MCTS::new(config.exploration_constant, config.mcts_simulations);
+    while !state.is_game_over() {
+        let ai = if state.current_player == candidate_side {
+            candidate
+        } else {
+            incumbent
+        };
+        // The two players mustn't share a tree
+        mcts.reset();
+        let evaluate_batch = |states: &[GameState]| ai.evaluate_batch(states);
+        let result = mcts.search_batched(state.clone(), &evaluate_batch, 16);
+        if state.make_move(result.best_move).is_err() {
+            break;
+        }
+    }
+    state.get_winner()
+}
+
+/// A training run in a directory, picking up where an earlier run in the
+/// same directory left off.
+pub struct Pipeline {
+    pub config: PipelineConfig,
+    dir: PathBuf,
+    best: MLAI,
+    history: Vec<GenerationMetrics>,
+    // Base of every generation's self-play seed
+    seed: u64,
+}
+
+impl Pipeline {
+    /// Opens `dir`, starting from `initial` unless it already holds a best
+    /// model.
+    pub fn open(config: PipelineConfig, dir: &Path, initial: MLAI) -> Result<Self, String> {
+        create_dir(dir)?;
+        let history_path = dir.join("history.json");
+        let history = if history_path.exists() {
+            let json = fs::read_to_string(&history_path)
+                .map_err(|e| format!("failed to read {}: {}", history_path.display(), e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| format!("{}: invalid history: {}", history_path.display(), e))?
+        } else {
+            Vec::new()
+        };
+
+        let best_path = dir.join("best.json");
+        let best = if best_path.exists() {
+            MLAI::load_model(path_str(&best_path)?)?
+        } else {
+            let model = initial.to_model_file(TrainingMetadata {
+                trainer: Some("pipeline".to_string()),
+                created_at: Some(chrono::Utc::now().to_rfc3339()),
+                ..TrainingMetadata::default()
+            });
+            let first = generation_dir(dir, 0);
+            create_dir(&first)?;
+            model.save(path_str(&first.join("model.json"))?)?;
+            model.save(path_str(&best_path)?)?;
+            initial
+        };
+
+        let seed = config.self_play.seed.unwrap_or_else(rand::random);
+        Ok(Pipeline {
+            config,
+            dir: dir.to_path_buf(),
+            best,
+            history,
+            seed,
+        })
+    }
+
+    pub fn best(&self) -> &MLAI {
+        &self.best
+    }
+
+    pub fn history(&self) -> &[GenerationMetrics] {
+        &self.history
+    }
+
+    pub fn best_generation(&self) -> usize {
+        self.history.last().map_or(0, |m| m.best_generation)
+    }
+
+    /// Self-play settings for `generation`, seeded past the games of every
+    /// earlier generation so a rejected candidate's data isn't replayed.
+    pub fn self_play_config(&self, generation: usize) -> SelfPlayConfig {
+        let games = (generation * self.config.self_play.num_games) as u64;
+        SelfPlayConfig {
+            seed: Some(self.seed.wrapping_add(games)),
+            ..self.config.self_play.clone()
+        }
+    }
+
+    /// Runs the configured number of generations.
+    pub fn run(&mut self) -> Result<(), String> {
+        for _ in 0..self.config.generations {
+            self.run_generation()?;
+        }
+        Ok(())
+    }
+
+    /// Self-play, training and gating for one generation.
+    pub fn run_generation(&mut self) -> Result<GenerationMetrics, String> {
+        let generation = self.history.len() + 1;
+        println!(
+            "🧬 Generation {}: self-play with generation {}",
+            generation,
+            self.best_generation()
+        );
+
+        let shard_dir = self.dir.join("shards");
+        let mut trainer =
+            SelfPlayTrainer::with_ai(self.self_play_config(generation), self.best.clone());
+        let mut writer = ShardWriter::create(
+            &shard_dir,
+            &trainer.dataset_metadata(),
+            self.config.samples_per_shard,
+        )?;
+        let samples = trainer.generate_to_shards(&mut writer)?;
+        writer.finish()?;
+
+        let buffer = ReplayBuffer::open(&shard_dir, self.config.replay_shards)?;
+        let dataset = SelfPlayDataset::from_dataset(&buffer.to_dataset()?)?;
+        println!(
+            "🏋️  Generation {}: training on {} samples",
+            generation,
+            dataset.len()
+        );
+        let mut model = fine_tune_model(&self.best, &dataset, &self.config.training)?;
+        model
+            .metadata
+            .extra
+            .insert("generation".to_string(), generation.into());
+        let candidate = MLAI::from_model_file(&model)?;
+
+        let gating = play_match(&candidate, &self.best, &self.config.gating);
+        let promoted = gating.score() >= 0.5 + self.config.gating.margin;
+        println!(
+            "⚔️  Generation {}: {}W {}D {}L against generation {} ({:.1}%), {}",
+            generation,
+            gating.wins,
+            gating.draws,
+            gating.losses,
+            self.best_generation(),
+            gating.score() * 100.0,
+            if promoted { "promoted" } else { "rejected" }
+        );
+
+        let metrics = GenerationMetrics {
+            generation,
+            samples,
+            adjudication: trainer.adjudication,
+            training_samples: dataset.len(),
+            final_loss: model.metadata.final_loss,
+            gating,
+            promoted,
+            best_generation: if promoted {
+                generation
+            } else {
+                self.best_generation()
+            },
+            finished_at: chrono::Utc::now().to_rfc3339(),
+        };
+        self.record(&model, &metrics)?;
+        if promoted {
+            self.best = candidate;
+        }
+        self.history.push(metrics.clone());
+        Ok(metrics)
+    }
+
+    // Writes the generation's files, then the best model and history that
+    // make it part of the run
+    fn record(&self, model: &ModelFile, metrics: &GenerationMetrics) -> Result<(), String> {
+        let dir = generation_dir(&self.dir, metrics.generation);
+        create_dir(&dir)?;
+        model.save(path_str(&dir.join("model.json"))?)?;
+        write_json(&dir.join("metrics.json"), metrics)?;
+        if metrics.promoted {
+            model.save(path_str(&self.dir.join("best.json"))?)?;
+        }
+        let mut history = self.history.clone();
+        history.push(metrics.clone());
+        write_json(&self.dir.join("history.json"), &history)
+    }
+}
+
+fn generation_dir(dir: &Path, generation: usize) -> PathBuf {
+    dir.join("generations").join(format!("{:04}", generation))
+}
+
+fn create_dir(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))
+}
+
+fn path_str(path: &Path) -> Result<&str, String> {
+    path.to_str()
+        .ok_or(format!("{} is not valid UTF-8", path.display()))
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).unwrap();
+    fs::write(path, json).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::FeatureSet;
+    use crate::normalization::FeatureNormalizer;
+    use crate::optimizer::OptimizerConfig;
+    use crate::COLS;
+
+    fn tiny_ai() -> MLAI {
+        MLAI::with_hidden_sizes(FeatureSet::RelativeFeatures, &[8])
+    }
+
+    fn tiny_config() -> PipelineConfig {
+        PipelineConfig {
+            generations: 2,
+            self_play: SelfPlayConfig {
+                num_games: 2,
+                mcts_simulations: 8,
+                ..SelfPlayConfig::default()
+            },
+            training: TrainNetworkConfig {
+                epochs: 2,
+                validation_split: 0.0,
+                ..TrainNetworkConfig::default()
+            },
+            samples_per_shard: 16,
+            replay_shards: 4,
+            gating: GatingConfig {
+                games: 2,
+                mcts_simulations: 8,
+                ..GatingConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_match_results_are_scored_from_the_candidate_side() {
+        let result = MatchResult {
+            wins: 5,
+            draws: 2,
+            losses: 3,
+        };
+        assert_eq!(result.games(), 10);
+        assert!((result.score() - 0.6).abs() < 1e-6);
+        assert_eq!(MatchResult::default().score(), 0.0);
+
+        let ai = MLAI::with_feature_set(FeatureSet::RelativeFeatures);
+        let config = GatingConfig {
+            games: 4,
+            mcts_simulations: 8,
+            ..GatingConfig::default()
+        };
+        assert_eq!(play_match(&ai, &ai, &config).games(), 4);
+    }
+
+    #[test]
+    fn test_generations_are_versioned_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pipeline = Pipeline::open(tiny_config(), dir.path(), tiny_ai()).unwrap();
+        pipeline.run().unwrap();
+
+        let history = pipeline.history().to_vec();
+        assert_eq!(history.len(), 2);
+        for (idx, metrics) in history.iter().enumerate() {
+            assert_eq!(metrics.generation, idx + 1);
+            assert_eq!(metrics.gating.games(), 2);
+            let generation = generation_dir(dir.path(), idx + 1);
+            let saved = ModelFile::load(path_str(&generation.join("model.json")).unwrap()).unwrap();
+            assert_eq!(saved.metadata.extra["generation"], idx + 1);
+            assert!(generation.join("metrics.json").exists());
+        }
+        assert!(generation_dir(dir.path(), 0).join("model.json").exists());
+
+        // A reopened run carries on numbering from its history
+        let mut config = tiny_config();
+        config.gating.margin = -1.0;
+        let mut reopened = Pipeline::open(config, dir.path(), tiny_ai()).unwrap();
+        assert_eq!(reopened.history(), &history[..]);
+        let metrics = reopened.run_generation().unwrap();
+        assert_eq!(metrics.generation, 3);
+        assert!(metrics.promoted);
+        assert_eq!(reopened.best_generation(), 3);
+        let best = ModelFile::load(path_str(&dir.path().join("best.json")).unwrap()).unwrap();
+        assert_eq!(best.metadata.extra["generation"], 3);
+    }
+
+    #[test]
+    fn test_generations_play_different_games() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = tiny_config();
+        config.self_play.seed = Some(7);
+        let pipeline = Pipeline::open(config, dir.path(), tiny_ai()).unwrap();
+
+        let (first, second) = (pipeline.self_play_config(1), pipeline.self_play_config(2));
+        assert_eq!((first.seed, second.seed), (Some(9), Some(11)));
+        let play = |config| {
+            SelfPlayTrainer::with_ai(config, pipeline.best().clone())
+                .generate_training_data()
+                .unwrap()
+                .samples
+        };
+        assert_ne!(play(first), play(second));
+    }
+
+    #[test]
+    fn test_candidates_are_fine_tuned_from_the_best_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = tiny_config();
+        config.gating.margin = -1.0;
+        config.training.optimizer = OptimizerConfig::adam(1e-4);
+
+        // A parent with a normalizer of its own, which the candidate keeps
+        let states: Vec<GameState> = (0..COLS as u8)
+            .map(|col| {
+                let mut state = GameState::new();
+                state.make_move(col).unwrap();
+                state
+            })
+            .collect();
+        let normalizer =
+            FeatureNormalizer::fit(&FeatureSet::RelativeFeatures.encode_batch(&states));
+        let model = tiny_ai()
+            .to_model_file(TrainingMetadata::default())
+            .with_normalizer(normalizer);
+        let parent = MLAI::from_model_file(&model).unwrap();
+
+        let mut pipeline = Pipeline::open(config, dir.path(), parent.clone()).unwrap();
+        assert!(pipeline.run_generation().unwrap().promoted);
+        let best = pipeline.best();
+        assert_eq!(best.normalizer(), parent.normalizer());
+
+        // A few small steps away from the parent, not a fresh network
+        let (parent_value, parent_policy) = parent.get_networks();
+        let (value, policy) = best.get_networks();
+        for (before, after) in [(parent_value, value), (parent_policy, policy)] {
+            let (before, after) = (before.get_weights(), after.get_weights());
+            assert_eq!(before.len(), after.len());
+            assert_ne!(before, after);
+            assert!(before
+                .iter()
+                .zip(&after)
+                .all(|(before, after)| (before - after).abs() < 0.01));
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Reduced-precision weights for smaller model files and cheaper inference.
+//!
+//! Weights can be stored as f16 or as int8 with a scale per output column
+//! (per block of values on disk). `measure_drift` compares a quantised model
+//! against its f32 original so the loss in playing strength can be checked
+//! before shipping one.
+
+use crate::ml_ai::MLAI;
+use crate::GameState;
+use ndarray::{Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Values per int8 block in the binary weight encoding, each block sharing
+/// one f32 scale.
+pub const INT8_BLOCK_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    #[default]
+    F32,
+    F16,
+    Int8,
+}
+
+impl Precision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Precision::F32 => "f32",
+            Precision::F16 => "f16",
+            Precision::Int8 => "int8",
+        }
+    }
+
+    /// Bytes needed to encode `count` weights.
+    pub fn encoded_len(self, count: usize) -> usize {
+        match self {
+            Precision::F32 => count * 4,
+            Precision::F16 => count * 2,
+            Precision::Int8 => count + count.div_ceil(INT8_BLOCK_SIZE) * 4,
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Precision::F32),
+            "f16" => Ok(Precision::F16),
+            "int8" => Ok(Precision::Int8),
+            _ => Err(format!(
+                "unknown precision {:?} (expected f32, f16 or int8)",
+                s
+            )),
+        }
+    }
+}
+
+/// IEEE 754 half-precision bits for `value`, rounding to nearest even.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    let (half, shift, full) = if exponent <= 0 {
+        // Subnormal in f16; too small even for that rounds to zero
+        if exponent < -10 {
+            return sign;
+        }
+        let full = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (full >> shift, shift, full)
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), 13, mantissa)
+    };
+
+    let remainder = full & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // A carry out of the mantissa correctly bumps the exponent
+    let rounded = if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
+
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    match exponent {
+        0 => {
+            let magnitude = mantissa as f32 * 2f32.powi(-24);
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+/// A layer's weight matrix held at reduced precision and multiplied without
+/// expanding it back to f32.
+#[derive(Clone, Debug)]
+pub(crate) enum QuantizedMatrix {
+    F16(Array2<u16>),
+    /// Symmetric int8 with one scale per output column.
+    Int8 {
+        values: Array2<i8>,
+        scales: Array1<f32>,
+    },
+}
+
+impl QuantizedMatrix {
+    /// `None` for `Precision::F32`, which needs no separate copy.
+    pub(crate) fn quantize(weights: &Array2<f32>, precision: Precision) -> Option<Self> {
+        match precision {
+            Precision::F32 => None,
+            Precision::F16 => Some(QuantizedMatrix::F16(weights.mapv(f32_to_f16))),
+            Precision::Int8 => {
+                let scales = weights.map_axis(Axis(0), |column| {
+                    column.iter().fold(0.0f32, |max, w| max.max(w.abs())) / 127.0
+                });
+                let values = Array2::from_shape_fn(weights.dim(), |(i, j)| {
+                    quantize_int8(weights[[i, j]], scales[j])
+                });
+                Some(QuantizedMatrix::Int8 { values, scales })
+            }
+        }
+    }
+
+    pub(crate) fn dequantize(&self) -> Array2<f32> {
+        match self {
+            QuantizedMatrix::F16(bits) => bits.mapv(f16_to_f32),
+            QuantizedMatrix::Int8 { values, scales } => {
+                Array2::from_shape_fn(values.dim(), |(i, j)| values[[i, j]] as f32 * scales[j])
+            }
+        }
+    }
+
+    /// `input · W`, accumulating row by row so zero inputs (most of a
+    /// board encoding) cost nothing.
+    pub(crate) fn dot(&self, input: &Array2<f32>) -> Array2<f32> {
+        let (rows, cols) = match self {
+            QuantizedMatrix::F16(bits) => bits.dim(),
+            QuantizedMatrix::Int8 { values, .. } => values.dim(),
+        };
+        assert_eq!(input.ncols(), rows, "input size does not match layer");
+
+        let mut output = Array2::zeros((input.nrows(), cols));
+        for (sample, mut out) in input.rows().into_iter().zip(output.rows_mut()) {
+            for (i, &x) in sample.iter().enumerate() {
+                if x == 0.0 {
+                    continue;
+                }
+                match self {
+                    QuantizedMatrix::F16(bits) => {
+                        for (o, &w) in out.iter_mut().zip(bits.row(i)) {
+                            *o += x * f16_to_f32(w);
+                        }
+                    }
+                    QuantizedMatrix::Int8 { values, .. } => {
+                        for (o, &w) in out.iter_mut().zip(values.row(i)) {
+                            *o += x * w as f32;
+                        }
+                    }
+                }
+            }
+        }
+        if let QuantizedMatrix::Int8 { scales, .. } = self {
+            output *= scales;
+        }
+        output
+    }
+}
+
+fn quantize_int8(value: f32, scale: f32) -> i8 {
+    if scale == 0.0 {
+        0
+    } else {
+        (value / scale).round().clamp(-127.0, 127.0) as i8
+    }
+}
+
+/// Little-endian encoding of a flat weight vector. Int8 is written in
+/// blocks of `INT8_BLOCK_SIZE` values, each preceded by its f32 scale.
+pub fn encode_weights(weights: &[f32], precision: Precision) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(precision.encoded_len(weights.len()));
+    match precision {
+        Precision::F32 => {
+            for weight in weights {
+                bytes.extend_from_slice(&weight.to_le_bytes());
+            }
+        }
+        Precision::F16 => {
+            for &weight in weights {
+                bytes.extend_from_slice(&f32_to_f16(weight).to_le_bytes());
+            }
+        }
+        Precision::Int8 => {
+            for block in weights.chunks(INT8_BLOCK_SIZE) {
+                let scale = block.iter().fold(0.0f32, |max, w| max.max(w.abs())) / 127.0;
+                bytes.extend_from_slice(&scale.to_le_bytes());
+                bytes.extend(block.iter().map(|&w| quantize_int8(w, scale) as u8));
+            }
+        }
+    }
+    bytes
+}
+
+/// Decodes `count` weights written by `encode_weights`, which must fill
+/// `bytes` exactly.
+pub fn decode_weights(
+    bytes: &[u8],
+    count: usize,
+    precision: Precision,
+) -> Result<Vec<f32>, String> {
+    let expected = precision.encoded_len(count);
+    if bytes.len() != expected {
+        return Err(format!(
+            "expected {} bytes for {} {} weights, got {}",
+            expected,
+            count,
+            precision,
+            bytes.len()
+        ));
+    }
+
+    let weights = match precision {
+        Precision::F32 => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        Precision::F16 => bytes
+            .chunks_exact(2)
+            .map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))
+            .collect(),
+        Precision::Int8 => bytes
+            .chunks(INT8_BLOCK_SIZE + 4)
+            .flat_map(|block| {
+                let scale = f32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+                block[4..].iter().map(move |&q| q as i8 as f32 * scale)
+            })
+            .collect(),
+    };
+    Ok(weights)
+}
+
+/// How far a candidate model's outputs stray from a reference model's.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DriftReport {
+    pub positions: usize,
+    pub mean_value_error: f32,
+    pub max_value_error: f32,
+    /// L1 distance between the two move distributions (0 to 2).
+    pub mean_policy_error: f32,
+    pub max_policy_error: f32,
+    /// Fraction of positions where both policies pick the same best move.
+    pub top_move_agreement: f32,
+}
+
+pub fn measure_drift(reference: &MLAI, candidate: &MLAI, states: &[GameState]) -> DriftReport {
+    let mut report = DriftReport {
+        positions: states.len(),
+        ..DriftReport::default()
+    };
+    if states.is_empty() {
+        return report;
+    }
+
+    let expected = reference.evaluate_batch(states);
+    let actual = candidate.evaluate_batch(states);
+    let mut agreements = 0;
+    for ((value, policy), (candidate_value, candidate_policy)) in expected.iter().zip(&actual) {
+        let value_error = (value - candidate_value).abs();
+        let policy_error: f32 = policy
+            .iter()
+            .zip(candidate_policy)
+            .map(|(p, q)| (p - q).abs())
+            .sum();
+        report.mean_value_error += value_error;
+        report.max_value_error = report.max_value_error.max(value_error);
+        report.mean_policy_error += policy_error;
+        report.max_policy_error = report.max_policy_error.max(policy_error);
+        if argmax(policy) == argmax(candidate_policy) {
+            agreements += 1;
+        }
+    }
+
+    let count = states.len() as f32;
+    report.mean_value_error /= count;
+    report.mean_policy_error /= count;
+    report.top_move_agreement = agreements as f32 / count;
+    report
+}
+
+fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (i, &v)| {
+            if v > best.1 {
+                (i, v)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f16_round_trips_representable_values() {
+        for value in [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.099975586,
+            65504.0,
+            6.1035156e-5,
+            5.9604645e-8,
+        ] {
+            let bits = f32_to_f16(value);
+            assert_eq!(f16_to_f32(bits).to_bits(), value.to_bits(), "{}", value);
+        }
+        assert_eq!(f16_to_f32(f32_to_f16(1e6)), f32::INFINITY);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        assert_eq!(f16_to_f32(f32_to_f16(1e-9)), 0.0);
+    }
+
+    #[test]
+    fn test_f16_rounding_error_is_bounded() {
+        for i in -1000..1000 {
+            let value = i as f32 * 0.0137;
+            let error = (f16_to_f32(f32_to_f16(value)) - value).abs();
+            assert!(error <= value.abs() / 2048.0 + 1e-7, "{}", value);
+        }
+        // Ties go to the even mantissa
+        assert_eq!(f16_to_f32(f32_to_f16(1.0 + 1.0 / 2048.0)), 1.0);
+        assert_eq!(
+            f16_to_f32(f32_to_f16(1.0 + 3.0 / 2048.0)),
+            1.0 + 2.0 / 1024.0
+        );
+    }
+
+    #[test]
+    fn test_weight_encoding_round_trip() {
+        let weights: Vec<f32> = (0..150).map(|i| ((i as f32) * 0.37).sin()).collect();
+        for precision in [Precision::F32, Precision::F16, Precision::Int8] {
+            let bytes = encode_weights(&weights, precision);
+            assert_eq!(bytes.len(), precision.encoded_len(weights.len()));
+            let decoded = decode_weights(&bytes, weights.len(), precision).unwrap();
+            let tolerance = match precision {
+                Precision::F32 => 0.0,
+                Precision::F16 => 1e-3,
+                Precision::Int8 => 1.0 / 254.0 + 1e-6,
+            };
+            for (w, d) in weights.iter().zip(&decoded) {
+                assert!((w - d).abs() <= tolerance, "{}: {} vs {}", precision, w, d);
+            }
+        }
+        assert!(decode_weights(&[0; 7], 2, Precision::F32).is_err());
+    }
+
+    #[test]
+    fn test_quantized_dot_matches_dequantized_weights() {
+        let weights = Array2::from_shape_fn((5, 3), |(i, j)| (i as f32 - 2.0) * (j as f32 + 0.5));
+        let input = Array2::from_shape_fn((2, 5), |(s, i)| {
+            if i == 1 {
+                0.0
+            } else {
+                s as f32 + i as f32 * 0.3
+            }
+        });
+        for precision in [Precision::F16, Precision::Int8] {
+            let quantized = QuantizedMatrix::quantize(&weights, precision).unwrap();
+            let expected = input.dot(&quantized.dequantize());
+            let actual = quantized.dot(&input);
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert!((e - a).abs() < 1e-4, "{}: {} vs {}", precision, e, a);
+            }
+        }
+        assert!(QuantizedMatrix::quantize(&weights, Precision::F32).is_none());
+    }
+}
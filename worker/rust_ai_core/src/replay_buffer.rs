@@ -0,0 +1,253 @@
+//! Training window over the newest self-play shards.
+//!
+//! Holds the samples of at most `max_shards` shards from a `ShardWriter`
+//! directory. `refresh` pulls in shards written since the last call and
+//! evicts the oldest, so training keeps to recent games while memory stays
+//! bounded by the window size. A held shard that is deleted or rewritten,
+//! as after `ShardWriter::rewind`, makes `refresh` reload the window.
+
+use crate::dataset::{read_shard, Dataset, ShardedDataset, TrainingSample};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Size and modification time, to tell a rewritten shard from the one loaded
+type Stamp = (u64, SystemTime);
+
+struct HeldShard {
+    path: PathBuf,
+    stamp: Stamp,
+    samples: Vec<TrainingSample>,
+}
+
+pub struct ReplayBuffer {
+    dir: PathBuf,
+    max_shards: usize,
+    shards: VecDeque<HeldShard>,
+    len: usize,
+}
+
+impl ReplayBuffer {
+    /// A buffer over `dir`, filled with its newest `max_shards` shards.
+    pub fn open(dir: &Path, max_shards: usize) -> Result<Self, String> {
+        let mut buffer = ReplayBuffer {
+            dir: dir.to_path_buf(),
+            max_shards: max_shards.max(1),
+            shards: VecDeque::new(),
+            len: 0,
+        };
+        buffer.refresh()?;
+        Ok(buffer)
+    }
+
+    /// Loads shards newer than those held, dropping the oldest beyond the
+    /// window. Returns how many shards were added.
+    pub fn refresh(&mut self) -> Result<usize, String> {
+        let shards = ShardedDataset::open(&self.dir)?.shards;
+        let changed = self
+            .shards
+            .iter()
+            .any(|held| !shards.contains(&held.path) || stamp(&held.path).ok() != Some(held.stamp));
+        if changed {
+            self.shards.clear();
+            self.len = 0;
+        }
+
+        let newest = self.shards.back().map(|held| held.path.clone());
+        let unseen = match newest.and_then(|newest| shards.iter().position(|p| *p == newest)) {
+            Some(pos) => &shards[pos + 1..],
+            None => &shards[..],
+        };
+        let fresh = &unseen[unseen.len().saturating_sub(self.max_shards)..];
+
+        for path in fresh {
+            let stamp = stamp(path)?;
+            let samples = read_shard(path)?.collect::<Result<Vec<_>, _>>()?;
+            self.len += samples.len();
+            self.shards.push_back(HeldShard {
+                path: path.clone(),
+                stamp,
+                samples,
+            });
+            if self.shards.len() > self.max_shards {
+                let evicted = self.shards.pop_front().unwrap();
+                self.len -= evicted.samples.len();
+            }
+        }
+        Ok(fresh.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Shards currently held, oldest first.
+    pub fn shards(&self) -> impl Iterator<Item = &Path> {
+        self.shards.iter().map(|held| held.path.as_path())
+    }
+
+    /// `count` samples drawn uniformly, with replacement, across every
+    /// held shard.
+    pub fn sample<R: Rng>(&self, count: usize, rng: &mut R) -> Vec<&TrainingSample> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        (0..count)
+            .map(|_| {
+                let mut idx = rng.gen_range(0..self.len);
+                for held in &self.shards {
+                    if idx < held.samples.len() {
+                        return &held.samples[idx];
+                    }
+                    idx -= held.samples.len();
+                }
+                unreachable!("index within the buffer's length")
+            })
+            .collect()
+    }
+
+    /// Everything in the window as one dataset, for `train_model`.
+    pub fn to_dataset(&self) -> Result<Dataset, String> {
+        Ok(Dataset {
+            metadata: ShardedDataset::open(&self.dir)?.metadata,
+            samples: self
+                .shards
+                .iter()
+                .flat_map(|held| held.samples.iter().cloned())
+                .collect(),
+        })
+    }
+}
+
+fn stamp(path: &Path) -> Result<Stamp, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let modified = metadata
+        .modified()
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok((metadata.len(), modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{DatasetMetadata, SampleMetadata, ShardWriter};
+    use crate::features::FeatureSet;
+    use crate::COLS;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sample(game_id: usize, ply: usize) -> TrainingSample {
+        TrainingSample {
+            features: vec![0.0; FeatureSet::RelativeFeatures.size()],
+            policy_target: vec![1.0 / COLS as f32; COLS],
+            value_target: 0.0,
+            game_id,
+            ply,
+            metadata: SampleMetadata::default(),
+        }
+    }
+
+    fn write_games(dir: &Path, games: std::ops::Range<usize>) {
+        let metadata = DatasetMetadata::new(FeatureSet::RelativeFeatures, games.len());
+        let mut writer = ShardWriter::create(dir, &metadata, 4).unwrap();
+        for game in games {
+            writer
+                .write_all(&[sample(game, 0), sample(game, 1)])
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_buffer_keeps_the_newest_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        write_games(dir.path(), 0..6);
+        let sharded = ShardedDataset::open(dir.path()).unwrap();
+        assert_eq!(sharded.shards.len(), 3);
+        assert_eq!(sharded.samples().count(), 12);
+
+        let mut buffer = ReplayBuffer::open(dir.path(), 2).unwrap();
+        assert_eq!(buffer.len(), 8);
+        let games = |buffer: &ReplayBuffer| {
+            let mut games: Vec<usize> = buffer
+                .to_dataset()
+                .unwrap()
+                .samples
+                .iter()
+                .map(|s| s.game_id)
+                .collect();
+            games.dedup();
+            games
+        };
+        assert_eq!(games(&buffer), vec![2, 3, 4, 5]);
+
+        // Appending carries on numbering after the last shard
+        write_games(dir.path(), 6..9);
+        assert_eq!(buffer.refresh().unwrap(), 2);
+        assert_eq!(games(&buffer), vec![6, 7, 8]);
+        assert_eq!(buffer.shards().count(), 2);
+        assert_eq!(buffer.refresh().unwrap(), 0);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let drawn = buffer.sample(50, &mut rng);
+        assert_eq!(drawn.len(), 50);
+        assert!(drawn.iter().all(|s| s.game_id >= 6));
+        assert!(drawn.iter().any(|s| s.game_id == 6));
+        assert!(drawn.iter().any(|s| s.game_id == 8));
+    }
+
+    #[test]
+    fn test_buffer_reloads_rewritten_and_deleted_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = DatasetMetadata::new(FeatureSet::RelativeFeatures, 0);
+        let mut writer = ShardWriter::create(dir.path(), &metadata, 2).unwrap();
+        for game in 0..3 {
+            writer.write(&sample(game, 0)).unwrap();
+            writer.write(&sample(game, 1)).unwrap();
+        }
+        writer.flush().unwrap();
+        let mut buffer = ReplayBuffer::open(dir.path(), 2).unwrap();
+        let games = |buffer: &ReplayBuffer| {
+            let mut games: Vec<usize> = buffer
+                .to_dataset()
+                .unwrap()
+                .samples
+                .iter()
+                .map(|s| s.game_id)
+                .collect();
+            games.dedup();
+            games
+        };
+        assert_eq!(games(&buffer), vec![1, 2]);
+
+        // A rewound writer replaces the newest shard under the same path
+        writer.rewind(2).unwrap();
+        writer
+            .write_all(&[sample(10, 0), sample(10, 1), sample(10, 2)])
+            .unwrap();
+        writer.finish().unwrap();
+        buffer.refresh().unwrap();
+        assert_eq!(games(&buffer), vec![10]);
+        assert_eq!(buffer.len(), 3);
+
+        // Deleting the newest held shard mustn't load the rest twice
+        for path in buffer
+            .shards()
+            .skip(1)
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>()
+        {
+            fs::remove_file(path).unwrap();
+        }
+        buffer.refresh().unwrap();
+        assert_eq!(games(&buffer), vec![1, 10]);
+        assert_eq!(buffer.len(), 4);
+    }
+}
@@ -0,0 +1,284 @@
+//! Snapshots of explored search trees for offline inspection.
+//!
+//! Both engines can hand out a `SearchTreeNode` describing what they looked
+//! at: MCTS fills in visit statistics, minimax the alpha-beta window it was
+//! searched with. The tree serializes to JSON and renders to Graphviz DOT.
+
+use crate::{Cell, GameState, Player, COLS, ROWS};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Limits on how much of a tree gets exported.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeExportOptions {
+    /// Plies below the root to include.
+    pub max_depth: usize,
+    /// MCTS children with fewer visits are left out.
+    pub min_visits: u32,
+}
+
+impl Default for TreeExportOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            min_visits: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchTreeNode {
+    /// Column played to reach this node; `None` for the root.
+    #[serde(rename = "move")]
+    pub mv: Option<u8>,
+    /// Side to move in this position.
+    pub player: Player,
+    pub depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visits: Option<u32>,
+    /// Mean backed-up value for the player whose move led here.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q_value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prior: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ucb: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proven_value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amaf_visits: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amaf_q_value: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beta: Option<f32>,
+    /// Minimax stopped searching this node's moves at a beta cutoff.
+    pub cutoff: bool,
+    /// Minimax answered this node from the transposition table.
+    pub cache_hit: bool,
+    pub children: Vec<SearchTreeNode>,
+}
+
+impl SearchTreeNode {
+    pub fn new(mv: Option<u8>, player: Player, depth: usize) -> Self {
+        Self {
+            mv,
+            player,
+            depth,
+            visits: None,
+            q_value: None,
+            prior: None,
+            ucb: None,
+            proven_value: None,
+            amaf_visits: None,
+            amaf_q_value: None,
+            score: None,
+            alpha: None,
+            beta: None,
+            cutoff: false,
+            cache_hit: false,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        1 + self.children.iter().map(|c| c.node_count()).sum::<usize>()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Graphviz rendering: one box per node, edges labelled with the column.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph search_tree {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let style = if self.cache_hit {
+            ", style=dotted"
+        } else if self.cutoff {
+            ", style=dashed"
+        } else {
+            ""
+        };
+        writeln!(
+            dot,
+            "    n{} [label=\"{}\"{}];",
+            id,
+            self.dot_label(),
+            style
+        )
+        .unwrap();
+
+        for child in &self.children {
+            let child_id = child.write_dot(dot, next_id);
+            let column = child.mv.map(|mv| mv.to_string()).unwrap_or_default();
+            writeln!(dot, "    n{} -> n{} [label=\"{}\"];", id, child_id, column).unwrap();
+        }
+
+        id
+    }
+
+    fn dot_label(&self) -> String {
+        let mut lines = vec![match self.mv {
+            Some(mv) => format!("col {} ({:?} to move)", mv, self.player),
+            None => format!("root ({:?} to move)", self.player),
+        }];
+
+        let stats = [
+            ("visits", self.visits.map(|v| v.to_string())),
+            ("Q", self.q_value.map(|v| format!("{:.3}", v))),
+            ("prior", self.prior.map(|v| format!("{:.3}", v))),
+            ("UCB", self.ucb.map(|v| format!("{:.3}", v))),
+            ("proven", self.proven_value.map(|v| format!("{:.0}", v))),
+            ("AMAF visits", self.amaf_visits.map(|v| v.to_string())),
+            ("AMAF Q", self.amaf_q_value.map(|v| format!("{:.3}", v))),
+            ("score", self.score.map(|v| format!("{:.1}", v))),
+            ("alpha", self.alpha.map(|v| format!("{:.1}", v))),
+            ("beta", self.beta.map(|v| format!("{:.1}", v))),
+        ];
+        for (name, value) in stats {
+            if let Some(value) = value {
+                lines.push(format!("{}={}", name, value));
+            }
+        }
+        if self.cutoff {
+            lines.push("cutoff".to_string());
+        }
+        if self.cache_hit {
+            lines.push("cache hit".to_string());
+        }
+
+        lines.join("\\n")
+    }
+}
+
+// Builds a tree while minimax recurses: every call opens a node on entry and
+// closes it, attaching it to its parent, on exit.
+pub(crate) struct TreeRecorder {
+    options: TreeExportOptions,
+    stack: Vec<(SearchTreeNode, [[Cell; ROWS]; COLS])>,
+    // Calls below `max_depth` that are not being recorded
+    skipped: usize,
+    root: Option<SearchTreeNode>,
+}
+
+impl TreeRecorder {
+    pub(crate) fn new(options: TreeExportOptions) -> Self {
+        Self {
+            options,
+            stack: Vec::new(),
+            skipped: 0,
+            root: None,
+        }
+    }
+
+    pub(crate) fn start(&mut self, state: &GameState) {
+        self.stack.clear();
+        self.skipped = 0;
+        self.root = None;
+        let root = SearchTreeNode::new(None, state.current_player, 0);
+        self.stack.push((root, state.board));
+    }
+
+    pub(crate) fn enter(&mut self, state: &GameState, alpha: f32, beta: f32) {
+        let depth = self.stack.len();
+        if self.skipped > 0 || depth == 0 || depth > self.options.max_depth {
+            self.skipped += 1;
+            return;
+        }
+
+        // The move is the one column that differs from the parent position
+        let parent_board = &self.stack[depth - 1].1;
+        let mv = (0..COLS)
+            .find(|&col| parent_board[col] != state.board[col])
+            .map(|col| col as u8);
+
+        let mut node = SearchTreeNode::new(mv, state.current_player, depth);
+        node.alpha = Some(alpha);
+        node.beta = Some(beta);
+        self.stack.push((node, state.board));
+    }
+
+    pub(crate) fn exit(&mut self, score: f32, cache_hit: bool, move_count: usize) {
+        if self.skipped > 0 {
+            self.skipped -= 1;
+            return;
+        }
+
+        if self.stack.len() < 2 {
+            return;
+        }
+        let (mut node, _) = self.stack.pop().unwrap();
+        node.score = Some(score);
+        node.cache_hit = cache_hit;
+        node.cutoff =
+            !cache_hit && node.children.len() < move_count && node.depth < self.options.max_depth;
+        self.stack.last_mut().unwrap().0.children.push(node);
+    }
+
+    pub(crate) fn finish(&mut self, score: Option<f32>) {
+        if let Some((mut root, _)) = self.stack.drain(..).next() {
+            root.score = score;
+            self.root = Some(root);
+        }
+    }
+
+    pub(crate) fn take_root(&mut self) -> Option<SearchTreeNode> {
+        if self.root.is_none() {
+            self.finish(None);
+        }
+        self.root.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_uses_move_key_and_skips_missing_stats() {
+        let mut root = SearchTreeNode::new(None, Player::Player1, 0);
+        let mut child = SearchTreeNode::new(Some(3), Player::Player2, 1);
+        child.visits = Some(10);
+        child.q_value = Some(0.25);
+        root.children.push(child);
+
+        let json: serde_json::Value = serde_json::from_str(&root.to_json()).unwrap();
+        assert!(json["move"].is_null());
+        assert_eq!(json["children"][0]["move"], 3);
+        assert_eq!(json["children"][0]["visits"], 10);
+        assert_eq!(json["children"][0]["qValue"], 0.25);
+        assert!(json["children"][0].get("alpha").is_none());
+        assert_eq!(root.node_count(), 2);
+    }
+
+    #[test]
+    fn test_dot_has_a_node_and_edge_per_child() {
+        let mut root = SearchTreeNode::new(None, Player::Player1, 0);
+        for col in [2, 3, 4] {
+            let mut child = SearchTreeNode::new(Some(col), Player::Player2, 1);
+            child.cutoff = col == 4;
+            root.children.push(child);
+        }
+
+        let dot = root.to_dot();
+        assert!(dot.starts_with("digraph search_tree {"));
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        assert!(dot.contains("n0 -> n3 [label=\"4\"]"));
+        assert!(dot.contains("style=dashed"));
+    }
+}
@@ -1,19 +1,45 @@
-use crate::features::GameFeatures;
-use crate::{mcts::MCTS, ml_ai::MLAI, GameState, Player};
+use crate::dataset::{
+    Dataset, DatasetMetadata, SampleMetadata, ShardWriter, ShardedDataset, TrainingSample,
+};
+use crate::mcts::{RaveSchedule, RolloutPolicy, MCTS};
+use crate::ml_ai::{DEFAULT_MODEL_PATH, MLAI};
+use crate::{GameState, Player};
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-use serde_json;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-#[derive(Clone)]
+// Samples per shard of the game log kept beside a non-streaming checkpoint
+const GAME_LOG_SHARD_SAMPLES: usize = 10_000;
+
+#[derive(Clone, Debug)]
 pub struct SelfPlayConfig {
     pub num_games: usize,
     pub mcts_simulations: usize,
+    /// Leaves gathered per batched network evaluation during MCTS.
+    pub leaf_batch_size: usize,
+    /// `ValueOnly` scores leaves with batched network calls; any other
+    /// policy searches one leaf at a time with that playout.
+    pub rollout_policy: RolloutPolicy,
+    pub rave: Option<RaveSchedule>,
     pub exploration_constant: f32,
+    /// Move-sampling temperature for the first `temperature_moves` plies;
+    /// later moves are greedy.
     pub temperature: f32,
+    pub temperature_moves: usize,
     pub dirichlet_alpha: f32,
     pub dirichlet_epsilon: f32,
+    /// Ends decided games early; `None` plays every game to the end.
+    pub adjudication: Option<Adjudication>,
+    /// Games between checkpoints, when the trainer has a checkpoint path.
     pub save_every: usize,
+    /// Seeds every game's first player and exploration noise; a random
+    /// seed is drawn when `None`.
+    pub seed: Option<u64>,
 }
 
 impl Default for SelfPlayConfig {
@@ -21,12 +47,193 @@ impl Default for SelfPlayConfig {
         Self {
             num_games: 1000,
             mcts_simulations: 800,
+            leaf_batch_size: 16,
+            rollout_policy: RolloutPolicy::ValueOnly,
+            rave: None,
             exploration_constant: 1.0,
             temperature: 1.0,
+            temperature_moves: 8,
             dirichlet_alpha: 0.3,
             dirichlet_epsilon: 0.25,
+            adjudication: None,
             save_every: 100,
+            seed: None,
+        }
+    }
+}
+
+impl SelfPlayConfig {
+    /// Temperature for the move at `ply`, 0 meaning greedy.
+    pub fn temperature_at(&self, ply: usize) -> f32 {
+        if ply < self.temperature_moves {
+            self.temperature
+        } else {
+            0.0
+        }
+    }
+}
+
+/// When to stop a game on the searched value of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjudication {
+    /// Resign once the value drops to minus this or below.
+    pub resign_threshold: Option<f32>,
+    /// Call a draw, from ply `draw_after` on, once the value is within
+    /// this of 0.
+    pub draw_threshold: Option<f32>,
+    pub draw_after: usize,
+    /// Share of games played out regardless, to check the calls they would
+    /// have made.
+    pub audit_fraction: f32,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            resign_threshold: Some(0.9),
+            draw_threshold: None,
+            draw_after: 30,
+            audit_fraction: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Verdict {
+    Resign,
+    Draw,
+}
+
+impl Adjudication {
+    fn call(&self, value: f32, ply: usize) -> Option<Verdict> {
+        if self.resign_threshold.is_some_and(|t| value <= -t) {
+            Some(Verdict::Resign)
+        } else if ply >= self.draw_after && self.draw_threshold.is_some_and(|t| value.abs() <= t) {
+            Some(Verdict::Draw)
+        } else {
+            None
+        }
+    }
+}
+
+/// How adjudication ended games, and how often audited games proved it
+/// wrong.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AdjudicationStats {
+    pub resigned: usize,
+    pub drawn: usize,
+    /// Games played out after a resignation would have been called.
+    pub audited_resigns: usize,
+    /// Audited resignations by a side that went on to win or draw.
+    pub false_resigns: usize,
+    pub audited_draws: usize,
+    /// Audited draw calls in games that went on to be won.
+    pub false_draws: usize,
+}
+
+impl AdjudicationStats {
+    pub fn add(&mut self, other: &AdjudicationStats) {
+        self.resigned += other.resigned;
+        self.drawn += other.drawn;
+        self.audited_resigns += other.audited_resigns;
+        self.false_resigns += other.false_resigns;
+        self.audited_draws += other.audited_draws;
+        self.false_draws += other.false_draws;
+    }
+
+    pub fn false_resign_rate(&self) -> Option<f32> {
+        (self.audited_resigns > 0).then(|| self.false_resigns as f32 / self.audited_resigns as f32)
+    }
+
+    pub fn false_draw_rate(&self) -> Option<f32> {
+        (self.audited_draws > 0).then(|| self.false_draws as f32 / self.audited_draws as f32)
+    }
+}
+
+// The result a game is scored with, as called unless the call was audited,
+// and how the call counts towards the adjudication stats
+fn adjudicate(
+    call: Option<(Verdict, Player)>,
+    audited: bool,
+    played_winner: Option<Player>,
+) -> (Option<Player>, AdjudicationStats) {
+    let mut adjudication = AdjudicationStats::default();
+    let winner = match call {
+        Some((Verdict::Resign, resigner)) if audited => {
+            adjudication.audited_resigns = 1;
+            if played_winner != Some(resigner.opponent()) {
+                adjudication.false_resigns = 1;
+            }
+            played_winner
+        }
+        Some((Verdict::Draw, _)) if audited => {
+            adjudication.audited_draws = 1;
+            if played_winner.is_some() {
+                adjudication.false_draws = 1;
+            }
+            played_winner
+        }
+        Some((Verdict::Resign, resigner)) => {
+            adjudication.resigned = 1;
+            Some(resigner.opponent())
+        }
+        Some((Verdict::Draw, _)) => {
+            adjudication.drawn = 1;
+            None
         }
+        None => played_winner,
+    };
+    (winner, adjudication)
+}
+
+// A finished game's samples and what adjudication made of it
+struct PlayedGame {
+    samples: Vec<TrainingSample>,
+    adjudication: AdjudicationStats,
+}
+
+/// Progress of a self-play run, saved every `save_every` games so a stopped
+/// run can pick up where it left off.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SelfPlayCheckpoint {
+    /// Run seed; with the game index it fixes each game's randomness.
+    pub seed: u64,
+    pub games_completed: usize,
+    /// Complete shards when streaming. Shards after these hold games that
+    /// are replayed on resume, so they are dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<usize>,
+    /// Complete shards of the game log when not streaming. The completed
+    /// games are appended to it rather than saved in the checkpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_log: Option<usize>,
+    #[serde(default)]
+    pub adjudication: AdjudicationStats,
+}
+
+impl SelfPlayCheckpoint {
+    /// Shard directory beside the checkpoint at `path` that holds the games
+    /// of a run without its own shard directory.
+    pub fn game_log_dir(path: &Path) -> PathBuf {
+        path.with_extension("games")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("{}: invalid checkpoint: {}", path.display(), e))
+    }
+
+    /// Writes through a temporary file so a crash never leaves a torn
+    /// checkpoint behind.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let partial = path.with_extension("partial");
+        let json = serde_json::to_string(self).unwrap();
+        fs::write(&partial, json)
+            .map_err(|e| format!("failed to write {}: {}", partial.display(), e))?;
+        fs::rename(&partial, path)
+            .map_err(|e| format!("failed to rename {}: {}", partial.display(), e))
     }
 }
 
@@ -34,40 +241,65 @@ pub struct SelfPlayTrainer {
     pub config: SelfPlayConfig,
     pub ai: MLAI,
     pub mcts: MCTS,
+    pub seed: u64,
+    /// Adjudication over the last run, including any resumed part.
+    pub adjudication: AdjudicationStats,
+    checkpoint_path: Option<PathBuf>,
+    resumed: Option<SelfPlayCheckpoint>,
 }
 
 impl SelfPlayTrainer {
     pub fn new(config: SelfPlayConfig) -> Self {
-        // Optimize CPU usage for M1 Macs
-        SelfPlayTrainer::optimize_cpu_usage();
-
-        let mut ai = MLAI::new();
-
         // Try to load existing weights
-        if let Ok(weights) = fs::read_to_string("ml/data/weights/ml_ai_weights.json") {
-            if let Ok(weights_data) = serde_json::from_str::<serde_json::Value>(&weights) {
-                if let (Some(value_weights), Some(policy_weights)) = (
-                    weights_data["value_network"]["weights"].as_array(),
-                    weights_data["policy_network"]["weights"].as_array(),
-                ) {
-                    let value_weights: Vec<f32> = value_weights
-                        .iter()
-                        .filter_map(|w| w.as_f64().map(|x| x as f32))
-                        .collect();
-                    let policy_weights: Vec<f32> = policy_weights
-                        .iter()
-                        .filter_map(|w| w.as_f64().map(|x| x as f32))
-                        .collect();
-
-                    ai.load_weights(&value_weights, &policy_weights);
+        let ai = if Path::new(DEFAULT_MODEL_PATH).exists() {
+            match MLAI::load_model(DEFAULT_MODEL_PATH) {
+                Ok(ai) => {
                     println!("✅ Loaded existing weights for self-play training");
+                    ai
+                }
+                Err(e) => {
+                    println!("⚠️  Ignoring saved model: {}", e);
+                    MLAI::new()
                 }
             }
+        } else {
+            MLAI::new()
+        };
+
+        Self::with_ai(config, ai)
+    }
+
+    /// A trainer whose games are all played by `ai`.
+    pub fn with_ai(config: SelfPlayConfig, ai: MLAI) -> Self {
+        // Optimize CPU usage for M1 Macs
+        SelfPlayTrainer::optimize_cpu_usage();
+
+        let mut mcts = MCTS::new(config.exploration_constant, config.mcts_simulations);
+        mcts.rollout_policy = config.rollout_policy;
+        mcts.rave = config.rave;
+        let seed = config.seed.unwrap_or_else(rand::random);
+
+        Self {
+            config,
+            ai,
+            mcts,
+            seed,
+            adjudication: AdjudicationStats::default(),
+            checkpoint_path: None,
+            resumed: None,
         }
+    }
 
-        let mcts = MCTS::new(config.exploration_constant, config.mcts_simulations);
+    /// Saves a checkpoint to `path` every `save_every` games and after the
+    /// last one.
+    pub fn checkpoint_to(&mut self, path: &Path) {
+        self.checkpoint_path = Some(path.to_path_buf());
+    }
 
-        Self { config, ai, mcts }
+    /// Makes the next run carry on from `checkpoint`, under its seed.
+    pub fn resume(&mut self, checkpoint: SelfPlayCheckpoint) {
+        self.seed = checkpoint.seed;
+        self.resumed = Some(checkpoint);
     }
 
     fn optimize_cpu_usage() {
@@ -111,7 +343,172 @@ impl SelfPlayTrainer {
         });
     }
 
-    pub fn generate_training_data(&mut self) -> Vec<serde_json::Value> {
+    pub fn generate_training_data(&mut self) -> Result<Dataset, String> {
+        let mut progress = self.progress(false)?;
+        let metadata = self.dataset_metadata();
+        let (mut samples, mut log) = match &self.checkpoint_path {
+            Some(path) => {
+                let (samples, log) = Self::open_game_log(path, &metadata, progress.game_log)?;
+                (samples, Some(log))
+            }
+            None if progress.games_completed > 0 => {
+                return Err("resuming needs the checkpoint path for its game log".to_string());
+            }
+            None => (Vec::new(), None),
+        };
+        self.play_games(progress.games_completed, |game_idx, game| {
+            if let Some(log) = &mut log {
+                log.write_all(&game.samples)?;
+            }
+            samples.extend(game.samples);
+            progress.adjudication.add(&game.adjudication);
+            progress.games_completed = game_idx + 1;
+            if self.checkpoint_due(game_idx) {
+                if let Some(log) = &mut log {
+                    progress.game_log = Some(log.flush()?);
+                }
+                self.save_checkpoint(&progress)?;
+            }
+            Ok(())
+        })?;
+        if let Some(log) = log {
+            log.finish()?;
+        }
+        self.finish_adjudication(progress.adjudication);
+        Ok(Dataset { metadata, samples })
+    }
+
+    // Opens the game log beside the checkpoint at `path`, keeping the first
+    // `shards` shards (none for a fresh run) and reading back their samples
+    fn open_game_log(
+        path: &Path,
+        metadata: &DatasetMetadata,
+        shards: Option<usize>,
+    ) -> Result<(Vec<TrainingSample>, ShardWriter), String> {
+        let dir = SelfPlayCheckpoint::game_log_dir(path);
+        if shards.is_none() && dir.exists() {
+            fs::remove_dir_all(&dir)
+                .map_err(|e| format!("failed to remove {}: {}", dir.display(), e))?;
+        }
+        let mut log = ShardWriter::create(&dir, metadata, GAME_LOG_SHARD_SAMPLES)?;
+        log.rewind(shards.unwrap_or(0))?;
+        let samples = ShardedDataset::open(&dir)?
+            .samples()
+            .collect::<Result<_, _>>()?;
+        Ok((samples, log))
+    }
+
+    /// Plays the configured games like `generate_training_data`, but hands
+    /// each batch to `writer` as it completes instead of keeping every
+    /// sample in memory. Checkpoints complete the shard in progress.
+    /// Returns the number of samples written.
+    pub fn generate_to_shards(&mut self, writer: &mut ShardWriter) -> Result<usize, String> {
+        let mut progress = self.progress(true)?;
+        if let Some(shards) = progress.shards {
+            writer.rewind(shards)?;
+        }
+        let samples = self.play_games(progress.games_completed, |game_idx, game| {
+            writer.write_all(&game.samples)?;
+            progress.adjudication.add(&game.adjudication);
+            progress.games_completed = game_idx + 1;
+            if self.checkpoint_due(game_idx) {
+                progress.shards = Some(writer.flush()?);
+                self.save_checkpoint(&progress)?;
+            }
+            Ok(())
+        })?;
+        self.finish_adjudication(progress.adjudication);
+        Ok(samples)
+    }
+
+    fn finish_adjudication(&mut self, stats: AdjudicationStats) {
+        self.adjudication = stats;
+        if self.config.adjudication.is_none() {
+            return;
+        }
+        println!(
+            "⚖️  Adjudicated: {} resigned, {} drawn",
+            stats.resigned, stats.drawn
+        );
+        if let Some(rate) = stats.false_resign_rate() {
+            println!(
+                "🔍 False resignations: {}/{} audited ({:.1}%)",
+                stats.false_resigns,
+                stats.audited_resigns,
+                rate * 100.0
+            );
+        }
+        if let Some(rate) = stats.false_draw_rate() {
+            println!(
+                "🔍 False draws: {}/{} audited ({:.1}%)",
+                stats.false_draws,
+                stats.audited_draws,
+                rate * 100.0
+            );
+        }
+    }
+
+    // Where this run starts: the resumed checkpoint, or a fresh one
+    fn progress(&mut self, streaming: bool) -> Result<SelfPlayCheckpoint, String> {
+        let progress = self.resumed.take().unwrap_or(SelfPlayCheckpoint {
+            seed: self.seed,
+            ..SelfPlayCheckpoint::default()
+        });
+        let logged = if streaming {
+            progress.shards
+        } else {
+            progress.game_log
+        };
+        if progress.games_completed > 0 && logged.is_none() {
+            return Err(if streaming {
+                "checkpoint was taken without a shard directory".to_string()
+            } else {
+                "checkpoint belongs to a sharded run".to_string()
+            });
+        }
+        Ok(progress)
+    }
+
+    fn checkpoint_due(&self, game_idx: usize) -> bool {
+        self.checkpoint_path.is_some()
+            && ((game_idx + 1).is_multiple_of(self.config.save_every.max(1))
+                || game_idx + 1 == self.config.num_games)
+    }
+
+    fn save_checkpoint(&self, progress: &SelfPlayCheckpoint) -> Result<(), String> {
+        match &self.checkpoint_path {
+            Some(path) => progress.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Metadata describing data generated with this trainer's settings.
+    pub fn dataset_metadata(&self) -> DatasetMetadata {
+        let mut metadata = DatasetMetadata::new(self.ai.feature_set(), self.config.num_games);
+        metadata.version = Some("2.0".to_string());
+        let settings = [
+            ("mcts_simulations", self.config.mcts_simulations.into()),
+            (
+                "exploration_constant",
+                self.config.exploration_constant.into(),
+            ),
+            ("temperature", self.config.temperature.into()),
+            ("temperature_moves", self.config.temperature_moves.into()),
+            ("dirichlet_alpha", self.config.dirichlet_alpha.into()),
+            ("dirichlet_epsilon", self.config.dirichlet_epsilon.into()),
+        ];
+        for (key, value) in settings {
+            metadata.extra.insert(key.to_string(), value);
+        }
+        metadata
+    }
+
+    // Plays the games from `first_game` on in parallel batches, passing
+    // each finished game and its index to `sink` in order
+    fn play_games<F>(&self, first_game: usize, mut sink: F) -> Result<usize, String>
+    where
+        F: FnMut(usize, PlayedGame) -> Result<(), String>,
+    {
         println!(
             "🎮 Starting self-play training with {} games",
             self.config.num_games
@@ -121,6 +518,10 @@ impl SelfPlayTrainer {
             self.config.mcts_simulations
         );
         println!("Temperature: {}", self.config.temperature);
+        println!("Seed: {}", self.seed);
+        if first_game > 0 {
+            println!("⏩ Resuming after {} completed games", first_game);
+        }
         println!(
             "🔄 Using parallel processing with batch size: {}",
             std::cmp::min(10, self.config.num_games)
@@ -129,7 +530,7 @@ impl SelfPlayTrainer {
         let start_time = Instant::now();
 
         // Use parallel iterator for game generation
-        let mut training_data = Vec::new();
+        let mut total_samples = 0;
 
         // Process games in parallel batches
         let batch_size = std::cmp::min(10, self.config.num_games); // Adapt batch size to number of games
@@ -137,7 +538,10 @@ impl SelfPlayTrainer {
             "🔄 Starting batch processing with {} games per batch",
             batch_size
         );
-        for (batch_idx, batch_start) in (0..self.config.num_games).step_by(batch_size).enumerate() {
+        for (batch_idx, batch_start) in (first_game..self.config.num_games)
+            .step_by(batch_size)
+            .enumerate()
+        {
             let batch_end = std::cmp::min(batch_start + batch_size, self.config.num_games);
             // Only log batch start for every 10th batch or if total games <= 100
             if self.config.num_games <= 100 || batch_idx % 10 == 0 {
@@ -148,7 +552,7 @@ impl SelfPlayTrainer {
                     batch_end - 1
                 );
             }
-            let batch_games: Vec<Vec<serde_json::Value>> = (batch_start..batch_end)
+            let batch_games: Vec<(usize, PlayedGame)> = (batch_start..batch_end)
                 .into_par_iter()
                 .map(|game_idx| {
                     // Show progress for every game when total games is small, or every 10% otherwise
@@ -175,22 +579,25 @@ impl SelfPlayTrainer {
                     if self.config.num_games <= 100 || game_idx % 100 == 0 {
                         println!("🎮 Starting game {}", game_idx + 1);
                     }
-                    let mut thread_trainer = SelfPlayTrainer::new(self.config.clone());
+                    let mut thread_trainer =
+                        SelfPlayTrainer::with_ai(self.config.clone(), self.ai.clone());
+                    thread_trainer.seed = self.seed;
                     let result = thread_trainer.play_game(game_idx);
                     if self.config.num_games <= 100 || game_idx % 100 == 0 {
                         println!("✅ Completed game {}", game_idx + 1);
                     }
-                    result
+                    (game_idx, result)
                 })
                 .collect();
 
             // Flatten batch results
-            for game_data in batch_games {
-                training_data.extend(game_data);
+            for (game_idx, game_data) in batch_games {
+                total_samples += game_data.samples.len();
+                sink(game_idx, game_data)?;
             }
             // Only log batch completion for every 10th batch or if total games <= 100
             if self.config.num_games <= 100 || batch_idx % 10 == 0 {
-                println!("✅ Batch {} complete, total samples: {}", batch_idx + 1, training_data.len());
+                println!("✅ Batch {} complete, total samples: {}", batch_idx + 1, total_samples);
             }
         }
 
@@ -198,21 +605,39 @@ impl SelfPlayTrainer {
         println!("🎉 Self-play generation complete!");
         println!("⏱️  Total time: {:.2} seconds", total_time.as_secs_f64());
         println!("🎮 Games played: {}", self.config.num_games);
-        println!("📊 Training samples: {}", training_data.len());
+        println!("📊 Training samples: {}", total_samples);
         println!(
             "📈 Average samples per game: {:.1}",
-            training_data.len() as f64 / self.config.num_games as f64
+            total_samples as f64 / self.config.num_games as f64
         );
         println!("🚀 Ready for neural network training...");
 
-        training_data
+        Ok(total_samples)
     }
 
-    fn play_game(&mut self, game_idx: usize) -> Vec<serde_json::Value> {
+    fn play_game(&mut self, game_idx: usize) -> PlayedGame {
+        // Each game's randomness depends only on the run seed and its index,
+        // so a resumed run replays exactly the games it missed
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(game_idx as u64));
         let mut game_state = GameState::new();
-        let mut game_data = Vec::new();
+        game_state.current_player = if rng.gen_bool(0.5) {
+            Player::Player1
+        } else {
+            Player::Player2
+        };
+        self.mcts.seed_rollouts(rng.gen());
+        // Audited games play on past the first call to check it
+        let audited = self
+            .config
+            .adjudication
+            .is_some_and(|a| rng.gen::<f32>() < a.audit_fraction);
+        let mut call = None;
+        let mut positions = Vec::new();
         let mut move_count = 0;
 
+        // Start every game with an empty search tree
+        self.mcts.reset();
+
         // Only log game start for every 100th game or if total games <= 100
         if self.config.num_games <= 100 || game_idx % 100 == 0 {
             println!(
@@ -223,11 +648,10 @@ impl SelfPlayTrainer {
         }
 
         while !game_state.is_game_over() {
-            let features = GameFeatures::from_game_state(&game_state);
-            let features_array = features.to_array();
+            let features_array = self.ai.feature_set().encode(&game_state);
 
             // Get current policy (unused but kept for potential future use)
-            let _current_policy = self.get_policy(&game_state);
+            let _current_policy = self.ai.policy(&game_state);
 
             // Get MCTS move probabilities
             // Only log MCTS progress for every 50th move or if total games <= 10
@@ -242,35 +666,20 @@ impl SelfPlayTrainer {
             }
 
             let mcts_start = std::time::Instant::now();
-            let (best_move, move_probs) = {
+            let result = if self.config.rollout_policy == RolloutPolicy::ValueOnly {
+                let evaluate_batch = |states: &[GameState]| self.ai.evaluate_batch(states);
+                self.mcts.search_batched(
+                    game_state.clone(),
+                    &evaluate_batch,
+                    self.config.leaf_batch_size,
+                )
+            } else {
                 let value_fn = |state: &GameState| self.ai.evaluate_position(state);
-                let policy_fn = |state: &GameState| {
-                    let features = GameFeatures::from_game_state(state);
-                    let features_array = features.to_array();
-                    let (_, policy_network) = self.ai.get_networks();
-                    let policy_output = policy_network.forward(&features_array);
-
-                    let mut policy = Vec::new();
-                    let mut sum = 0.0;
-                    for i in 0..7 {
-                        let prob = policy_output[i].exp();
-                        policy.push(prob);
-                        sum += prob;
-                    }
-                    if sum > 0.0 {
-                        for prob in &mut policy {
-                            *prob /= sum;
-                        }
-                    } else {
-                        for prob in &mut policy {
-                            *prob = 1.0 / 7.0;
-                        }
-                    }
-                    policy
-                };
-
-                self.mcts.search(game_state.clone(), &value_fn, &policy_fn)
+                let policy_fn = |state: &GameState| self.ai.policy(state);
+                self.mcts
+                    .search_with_stats(game_state.clone(), &value_fn, &policy_fn)
             };
+            let move_probs = &result.move_probabilities;
             let mcts_duration = mcts_start.elapsed();
 
             // Only log MCTS completion for the same conditions as start
@@ -284,90 +693,81 @@ impl SelfPlayTrainer {
             }
 
             // Add Dirichlet noise for exploration
-            let noisy_probs = self.add_dirichlet_noise(&move_probs);
-
-            // Store training data
-            game_data.push(serde_json::json!({
-                "features": features_array.to_vec(),
-                "policy_target": noisy_probs,
-                "game_idx": game_idx,
-                "move_idx": move_count,
-                "player": if game_state.current_player == Player::Player1 { "player1" } else { "player2" }
-            }));
-
-            // Make the move
-            if game_state.make_move(best_move).is_err() {
-                break;
-            }
-            move_count += 1;
-        }
+            let noisy_probs = self.add_dirichlet_noise(move_probs, &mut rng);
+
+            // Value targets follow once the game is decided
+            positions.push((
+                features_array.to_vec(),
+                noisy_probs,
+                game_state.current_player,
+            ));
 
-        // Determine game result
-        let game_result = if let Some(winner) = game_state.get_winner() {
-            match winner {
-                Player::Player1 => 1.0,
-                Player::Player2 => -1.0,
+            // Stop at the first adjudication call unless auditing it
+            if let Some(adjudication) = self.config.adjudication.filter(|_| call.is_none()) {
+                let value = result.proven_value.unwrap_or(result.root_value);
+                if let Some(verdict) = adjudication.call(value, move_count) {
+                    call = Some((verdict, game_state.current_player));
+                    if !audited {
+                        break;
+                    }
+                }
             }
-        } else {
-            0.0 // Draw
-        };
 
-        // Update all training samples with the final result
-        for sample in &mut game_data {
-            let move_idx = sample["move_idx"].as_u64().unwrap_or(0) as usize;
-            let move_discount = 0.95_f32.powi(move_idx as i32);
-            let adjusted_value = game_result * move_discount;
+            let temperature = self.config.temperature_at(move_count);
+            let chosen_move = if temperature > 0.0 {
+                let weights = move_probs.iter().map(|p| p.powf(1.0 / temperature));
+                match WeightedIndex::new(weights) {
+                    Ok(dist) => rng.sample(dist) as u8,
+                    Err(_) => result.best_move,
+                }
+            } else {
+                result.best_move
+            };
 
-            sample["value_target"] = serde_json::Value::Number(
-                serde_json::Number::from_f64(adjusted_value as f64).unwrap(),
-            );
-            sample["game_result"] = serde_json::Value::Number(
-                serde_json::Number::from_f64(game_result as f64).unwrap(),
-            );
+            // Make the move and keep the searched subtree below it
+            if game_state.make_move(chosen_move).is_err() {
+                break;
+            }
+            self.mcts.advance_root(&[chosen_move]);
+            move_count += 1;
         }
 
-        game_data
-    }
-
-    fn get_policy(&self, state: &GameState) -> Vec<f32> {
-        let features = GameFeatures::from_game_state(state);
-        let features_array = features.to_array();
-
-        // Get policy from neural network
-        let (_, policy_network) = self.ai.get_networks();
-        let policy_output = policy_network.forward(&features_array);
-
-        // Convert to probabilities
-        let mut policy = Vec::new();
-        let mut sum = 0.0;
-
-        for i in 0..7 {
-            let prob = policy_output[i].exp();
-            policy.push(prob);
-            sum += prob;
-        }
+        let (winner, adjudication) = adjudicate(call, audited, game_state.get_winner());
+        let game_result: f32 = match winner {
+            Some(Player::Player1) => 1.0,
+            Some(Player::Player2) => -1.0,
+            None => 0.0, // Draw
+        };
 
-        // Normalize
-        if sum > 0.0 {
-            for prob in &mut policy {
-                *prob /= sum;
-            }
-        } else {
-            // Fallback to uniform distribution
-            for prob in &mut policy {
-                *prob = 1.0 / 7.0;
-            }
+        // Score every position with the final result, seen from the side
+        // the value network scores each position for
+        let feature_set = self.ai.feature_set();
+        let samples = positions
+            .into_iter()
+            .enumerate()
+            .map(|(ply, (features, policy_target, mover))| {
+                let move_discount = 0.95_f32.powi(ply as i32);
+                TrainingSample {
+                    features,
+                    policy_target,
+                    value_target: feature_set.value_target(winner, mover) * move_discount,
+                    game_id: game_idx,
+                    ply,
+                    metadata: SampleMetadata {
+                        player: Some(mover),
+                        game_result: Some(game_result),
+                        original_value: None,
+                    },
+                }
+            })
+            .collect();
+        PlayedGame {
+            samples,
+            adjudication,
         }
-
-        policy
     }
 
-    fn add_dirichlet_noise(&self, probs: &[f32]) -> Vec<f32> {
-        use rand::thread_rng;
-        use rand::Rng;
-
-        let mut rng = thread_rng();
-
+    fn add_dirichlet_noise<R: Rng>(&self, probs: &[f32], rng: &mut R) -> Vec<f32> {
         // Generate Dirichlet-like noise using gamma distribution approximation
         let mut noise = Vec::new();
         let mut sum = 0.0;
@@ -400,35 +800,6 @@ impl SelfPlayTrainer {
 
         result
     }
-
-    pub fn save_training_data(
-        &self,
-        training_data: &[serde_json::Value],
-        filename: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let output_data = serde_json::json!({
-            "metadata": {
-                "num_games": self.config.num_games,
-                "mcts_simulations": self.config.mcts_simulations,
-                "exploration_constant": self.config.exploration_constant,
-                "temperature": self.config.temperature,
-                "dirichlet_alpha": self.config.dirichlet_alpha,
-                "dirichlet_epsilon": self.config.dirichlet_epsilon,
-                "generated_at": chrono::Utc::now().to_rfc3339(),
-                "version": "2.0"
-            },
-            "training_data": training_data
-        });
-
-        if let Some(parent) = std::path::Path::new(filename).parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        fs::write(filename, serde_json::to_string_pretty(&output_data)?)?;
-        println!("💾 Training data saved to: {}", filename);
-
-        Ok(())
-    }
 }
 
 #[cfg(test)]
@@ -457,9 +828,238 @@ mod tests {
         let trainer = SelfPlayTrainer::new(config);
 
         let probs = vec![0.5, 0.3, 0.2];
-        let noisy_probs = trainer.add_dirichlet_noise(&probs);
+        let noisy_probs = trainer.add_dirichlet_noise(&probs, &mut rand::thread_rng());
 
         assert_eq!(noisy_probs.len(), 3);
         assert!((noisy_probs.iter().sum::<f32>() - 1.0).abs() < 0.001);
     }
+
+    #[test]
+    fn test_value_targets_are_for_the_side_to_move() {
+        let config = SelfPlayConfig {
+            num_games: 1,
+            mcts_simulations: 20,
+            ..SelfPlayConfig::default()
+        };
+        let mut trainer = SelfPlayTrainer::new(config);
+        trainer.ai = MLAI::new();
+
+        let samples = trainer.play_game(0).samples;
+        let result = samples[0].metadata.game_result.unwrap();
+        for (ply, sample) in samples.iter().enumerate() {
+            assert_eq!((sample.game_id, sample.ply), (0, ply));
+            let mover = if sample.metadata.player == Some(Player::Player1) {
+                1.0
+            } else {
+                -1.0
+            };
+            let target = sample.value_target;
+            if result == 0.0 {
+                assert_eq!(target, 0.0);
+            } else {
+                assert!(target * mover * result > 0.0, "{:?}", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resignations_end_games_unless_audited() {
+        // A threshold past the value range resigns at the first position
+        let adjudication = Adjudication {
+            resign_threshold: Some(-2.0),
+            audit_fraction: 0.0,
+            ..Adjudication::default()
+        };
+        assert_eq!(adjudication.call(0.0, 0), Some(Verdict::Resign));
+        let config = SelfPlayConfig {
+            num_games: 1,
+            mcts_simulations: 10,
+            adjudication: Some(adjudication),
+            seed: Some(5),
+            ..SelfPlayConfig::default()
+        };
+        assert_eq!(config.temperature_at(7), 1.0);
+        assert_eq!(config.temperature_at(8), 0.0);
+        let ai = MLAI::new();
+
+        let mut trainer = SelfPlayTrainer::with_ai(config.clone(), ai.clone());
+        let game = trainer.play_game(0);
+        assert_eq!(game.samples.len(), 1);
+        assert_eq!(game.adjudication.resigned, 1);
+        let resigner = game.samples[0].metadata.player.unwrap();
+        let expected = if resigner == Player::Player1 {
+            -1.0
+        } else {
+            1.0
+        };
+        assert_eq!(game.samples[0].metadata.game_result, Some(expected));
+
+        // Audited games are played out and scored as played. The shipped
+        // model keeps these games the same from run to run
+        let trained = MLAI::load_model("../../ml/data/weights/simple_model_enhanced.json")
+            .expect("the shipped simple model should load");
+        let audited = |adjudication| SelfPlayConfig {
+            num_games: 6,
+            adjudication: Some(Adjudication {
+                audit_fraction: 1.0,
+                ..adjudication
+            }),
+            seed: Some(4),
+            ..config.clone()
+        };
+        // Each game's first mover, who is the one to resign, and its result
+        let outcomes = |trainer: &mut SelfPlayTrainer| -> Vec<(Player, f32)> {
+            let samples = trainer.generate_training_data().unwrap().samples;
+            samples
+                .iter()
+                .filter(|sample| sample.ply == 0)
+                .map(|sample| {
+                    let metadata = &sample.metadata;
+                    (metadata.player.unwrap(), metadata.game_result.unwrap())
+                })
+                .collect()
+        };
+        let (p1, p2) = (Player::Player1, Player::Player2);
+        let games = vec![
+            (p2, -1.0),
+            (p1, -1.0),
+            (p1, 1.0),
+            (p1, 1.0),
+            (p1, -1.0),
+            (p2, 0.0),
+        ];
+
+        // Resigners win three games and draw one; the other two resigned
+        // rightly
+        let mut trainer = SelfPlayTrainer::with_ai(audited(adjudication), trained.clone());
+        assert_eq!(outcomes(&mut trainer), games);
+        let stats = trainer.adjudication;
+        assert_eq!((stats.resigned, stats.audited_resigns), (0, 6));
+        assert_eq!(stats.false_resigns, 4);
+        assert_eq!(stats.false_resign_rate(), Some(4.0 / 6.0));
+
+        // The same games with a draw called at the start: five are decided
+        let draws = Adjudication {
+            resign_threshold: None,
+            draw_threshold: Some(2.0),
+            draw_after: 0,
+            ..adjudication
+        };
+        let mut trainer = SelfPlayTrainer::with_ai(audited(draws), trained);
+        assert_eq!(outcomes(&mut trainer), games);
+        let stats = trainer.adjudication;
+        assert_eq!((stats.drawn, stats.audited_draws), (0, 6));
+        assert_eq!(stats.false_draws, 5);
+        assert_eq!(stats.false_draw_rate(), Some(5.0 / 6.0));
+    }
+
+    #[test]
+    fn test_audits_count_calls_the_played_result_contradicts() {
+        let p1 = Player::Player1;
+        let p2 = Player::Player2;
+        let resign = Some((Verdict::Resign, p1));
+        let draw = Some((Verdict::Draw, p1));
+        let games = [
+            // Player1 resigns, then wins, draws or loses as played
+            (resign, Some(p1)),
+            (resign, None),
+            (resign, Some(p2)),
+            // A draw is called, then the game is won or drawn
+            (draw, Some(p2)),
+            (draw, None),
+        ];
+
+        let mut stats = AdjudicationStats::default();
+        for (call, played_winner) in games {
+            let (winner, game) = adjudicate(call, true, played_winner);
+            assert_eq!(winner, played_winner);
+            stats.add(&game);
+        }
+        assert_eq!((stats.audited_resigns, stats.false_resigns), (3, 2));
+        assert_eq!(stats.false_resign_rate(), Some(2.0 / 3.0));
+        assert_eq!((stats.audited_draws, stats.false_draws), (2, 1));
+        assert_eq!(stats.false_draw_rate(), Some(0.5));
+        assert_eq!((stats.resigned, stats.drawn), (0, 0));
+
+        // Unaudited calls decide the game whatever would have happened
+        assert_eq!(adjudicate(resign, false, Some(p1)).0, Some(p2));
+        assert_eq!(adjudicate(draw, false, Some(p2)).0, None);
+    }
+
+    #[test]
+    fn test_games_stream_into_shards() {
+        let config = SelfPlayConfig {
+            num_games: 2,
+            mcts_simulations: 10,
+            ..SelfPlayConfig::default()
+        };
+        let mut trainer = SelfPlayTrainer::new(config);
+        trainer.ai = MLAI::new();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ShardWriter::create(dir.path(), &trainer.dataset_metadata(), 5).unwrap();
+        let written = trainer.generate_to_shards(&mut writer).unwrap();
+        writer.finish().unwrap();
+
+        let sharded = crate::dataset::ShardedDataset::open(dir.path()).unwrap();
+        assert_eq!(sharded.shards.len(), written.div_ceil(5));
+        let games: Vec<usize> = sharded.samples().map(|s| s.unwrap().game_id).collect();
+        assert_eq!(games.len(), written);
+        assert!(games.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(games.last(), Some(&1));
+    }
+
+    #[test]
+    fn test_resumed_runs_replay_the_missing_games() {
+        let config = |num_games| SelfPlayConfig {
+            num_games,
+            mcts_simulations: 10,
+            rollout_policy: RolloutPolicy::Uniform,
+            save_every: 1,
+            seed: Some(11),
+            ..SelfPlayConfig::default()
+        };
+        let ai = MLAI::new();
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = dir.path().join("checkpoint.json");
+
+        let mut full = SelfPlayTrainer::with_ai(config(3), ai.clone());
+        let expected = full.generate_training_data().unwrap().samples;
+
+        // A run stopped after two games...
+        let mut stopped = SelfPlayTrainer::with_ai(config(2), ai.clone());
+        stopped.checkpoint_to(&checkpoint);
+        stopped.generate_training_data().unwrap();
+        let saved = SelfPlayCheckpoint::load(&checkpoint).unwrap();
+        assert_eq!((saved.seed, saved.games_completed), (11, 2));
+        // The games went to the log, one shard per checkpoint
+        assert_eq!(saved.game_log, Some(2));
+
+        // ...finishes with the same games as one that never stopped
+        let mut resumed = SelfPlayTrainer::with_ai(config(3), ai.clone());
+        resumed.seed = 0;
+        resumed.checkpoint_to(&checkpoint);
+        resumed.resume(saved.clone());
+        assert_eq!(resumed.generate_training_data().unwrap().samples, expected);
+        assert_eq!(resumed.seed, 11);
+        let finished = SelfPlayCheckpoint::load(&checkpoint).unwrap();
+        assert_eq!((finished.games_completed, finished.game_log), (3, Some(3)));
+
+        // Games logged after the checkpoint are dropped and replayed
+        let mut rewound = SelfPlayTrainer::with_ai(config(3), ai.clone());
+        rewound.checkpoint_to(&checkpoint);
+        rewound.resume(SelfPlayCheckpoint {
+            games_completed: 1,
+            game_log: Some(1),
+            ..saved.clone()
+        });
+        assert_eq!(rewound.generate_training_data().unwrap().samples, expected);
+
+        let mut streaming = SelfPlayTrainer::with_ai(config(3), ai);
+        streaming.resume(saved);
+        let mut writer =
+            ShardWriter::create(&dir.path().join("shards"), &streaming.dataset_metadata(), 5)
+                .unwrap();
+        assert!(streaming.generate_to_shards(&mut writer).is_err());
+    }
 }
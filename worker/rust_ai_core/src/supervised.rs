@@ -0,0 +1,263 @@
+//! Supervised training of `MLAI`'s networks on self-play data.
+//!
+//! Reads the `Dataset` files written by self-play and fits a dense value and
+//! policy network to them, producing a `ModelFile` that `MLAI::load_model`
+//! accepts without any conversion step. The features are standardised by a
+//! `FeatureNormalizer` fitted to the dataset, which the model file carries so
+//! inference scales them the same way. `fine_tune_model` instead carries on
+//! training an existing model under its own normalizer.
+
+use crate::dataset::Dataset;
+use crate::features::FeatureSet;
+use crate::ml_ai::MLAI;
+use crate::model::{ModelFile, NetworkSpec, TrainingMetadata};
+use crate::neural_network::{
+    Architecture, Network, NetworkConfig, NeuralNetwork, OutputActivation, TrainNetworkConfig,
+};
+use crate::normalization::FeatureNormalizer;
+use crate::COLS;
+use ndarray::{Array2, ArrayView1};
+
+/// Encoded positions with their value and policy targets, one row each.
+#[derive(Clone, Debug)]
+pub struct SelfPlayDataset {
+    pub feature_set: FeatureSet,
+    pub features: Array2<f32>,
+    pub value_targets: Array2<f32>,
+    pub policy_targets: Array2<f32>,
+}
+
+impl SelfPlayDataset {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let dataset = Dataset::load(path)?;
+        Self::from_dataset(&dataset).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Stacks the samples of a validated `Dataset` into matrices.
+    pub fn from_dataset(dataset: &Dataset) -> Result<Self, String> {
+        let feature_set = dataset.validate()?;
+        let rows = dataset.len();
+        let mut features = Array2::zeros((rows, feature_set.size()));
+        let mut value_targets = Array2::zeros((rows, 1));
+        let mut policy_targets = Array2::zeros((rows, COLS));
+        for (idx, sample) in dataset.samples.iter().enumerate() {
+            features
+                .row_mut(idx)
+                .assign(&ArrayView1::from(&sample.features));
+            policy_targets
+                .row_mut(idx)
+                .assign(&ArrayView1::from(&sample.policy_target));
+            value_targets[[idx, 0]] = sample.value_target;
+        }
+
+        Ok(SelfPlayDataset {
+            feature_set,
+            features,
+            value_targets,
+            policy_targets,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Trains fresh value (tanh) and policy (softmax) networks with
+/// `hidden_sizes` on `dataset`. Both training histories are kept in the
+/// model's metadata.
+pub fn train_model(
+    dataset: &SelfPlayDataset,
+    hidden_sizes: &[usize],
+    config: &TrainNetworkConfig,
+) -> ModelFile {
+    let network = |targets: &Array2<f32>, output_activation| {
+        NeuralNetwork::new(NetworkConfig {
+            input_size: dataset.feature_set.size(),
+            hidden_sizes: hidden_sizes.to_vec(),
+            output_size: targets.ncols(),
+            output_activation,
+        })
+    };
+    train_networks(
+        dataset,
+        Some(FeatureNormalizer::fit(&dataset.features)),
+        network(&dataset.value_targets, OutputActivation::Tanh),
+        network(&dataset.policy_targets, OutputActivation::Softmax),
+        config,
+    )
+}
+
+/// Trains a copy of `parent`'s dense networks further on `dataset`, keeping
+/// its architecture and normalizer.
+pub fn fine_tune_model(
+    parent: &MLAI,
+    dataset: &SelfPlayDataset,
+    config: &TrainNetworkConfig,
+) -> Result<ModelFile, String> {
+    if parent.feature_set() != dataset.feature_set {
+        return Err(format!(
+            "model reads {:?} but the data is {:?}",
+            parent.feature_set(),
+            dataset.feature_set
+        ));
+    }
+    let dense = |network: &Network| match network {
+        Network::Dense(network) => Ok(network.clone()),
+        Network::Conv(_) => Err("only dense networks can be fine-tuned".to_string()),
+    };
+    let (value_network, policy_network) = parent.get_networks();
+    Ok(train_networks(
+        dataset,
+        parent.normalizer().cloned(),
+        dense(value_network)?,
+        dense(policy_network)?,
+        config,
+    ))
+}
+
+fn train_networks(
+    dataset: &SelfPlayDataset,
+    normalizer: Option<FeatureNormalizer>,
+    value_network: NeuralNetwork,
+    policy_network: NeuralNetwork,
+    config: &TrainNetworkConfig,
+) -> ModelFile {
+    let mut features = dataset.features.clone();
+    if let Some(normalizer) = &normalizer {
+        normalizer.apply_batch(&mut features);
+    }
+
+    let train = |mut network: NeuralNetwork, targets: &Array2<f32>| {
+        let history = network.train_network(&features, targets, config);
+        let spec = NetworkSpec {
+            architecture: Architecture::Dense(network.config().clone()),
+            weights: network.get_weights(),
+        };
+        (spec, history)
+    };
+    let (value_network, value_history) = train(value_network, &dataset.value_targets);
+    let (policy_network, policy_history) = train(policy_network, &dataset.policy_targets);
+
+    let mut metadata = TrainingMetadata {
+        trainer: Some("train_network".to_string()),
+        created_at: Some(chrono::Utc::now().to_rfc3339()),
+        epochs: Some(value_history.epochs().max(policy_history.epochs())),
+        samples: Some(dataset.len()),
+        final_loss: value_history
+            .best_validation_loss()
+            .zip(policy_history.best_validation_loss())
+            .map(|(value, policy)| value + policy),
+        ..TrainingMetadata::default()
+    };
+    metadata.extra.insert(
+        "value_history".to_string(),
+        serde_json::to_value(&value_history).unwrap(),
+    );
+    metadata.extra.insert(
+        "policy_history".to_string(),
+        serde_json::to_value(&policy_history).unwrap(),
+    );
+
+    let model = ModelFile::new(dataset.feature_set, value_network, policy_network, metadata);
+    match normalizer {
+        Some(normalizer) => model.with_normalizer(normalizer),
+        None => model,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{DatasetMetadata, SampleMetadata, TrainingSample};
+    use crate::ml_ai::MLAI;
+    use crate::GameState;
+
+    // Positions after one move in each column, valued by how central the
+    // move was and with the policy pointing at the centre
+    fn dataset() -> Dataset {
+        let samples = (0..COLS)
+            .map(|col| {
+                let mut state = GameState::new();
+                state.make_move(col as u8).unwrap();
+                let mut policy = vec![0.0; COLS];
+                policy[3] = 1.0;
+                TrainingSample {
+                    features: FeatureSet::GameFeatures.encode(&state).to_vec(),
+                    policy_target: policy,
+                    value_target: 1.0 - (col as f32 - 3.0).abs() / 3.0,
+                    game_id: col,
+                    ply: 0,
+                    metadata: SampleMetadata::default(),
+                }
+            })
+            .collect();
+        Dataset {
+            metadata: DatasetMetadata {
+                num_games: Some(COLS),
+                ..DatasetMetadata::default()
+            },
+            samples,
+        }
+    }
+
+    #[test]
+    fn test_dataset_loads_self_play_files() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        dataset().save(path).unwrap();
+
+        let loaded = SelfPlayDataset::load(path).unwrap();
+        assert_eq!(loaded.feature_set, FeatureSet::GameFeatures);
+        assert_eq!(loaded.len(), COLS);
+        assert_eq!(loaded.policy_targets.row(0)[3], 1.0);
+        assert_eq!(loaded.value_targets[[3, 0]], 1.0);
+
+        let mut short = dataset();
+        short.samples[2].features.pop();
+        let error = SelfPlayDataset::from_dataset(&short).unwrap_err();
+        assert!(error.contains("sample 2"), "{}", error);
+
+        let mut stale = dataset();
+        stale.metadata.feature_set = Some(FeatureSet::GameFeatures);
+        stale.metadata.feature_version = Some(0);
+        assert!(SelfPlayDataset::from_dataset(&stale)
+            .unwrap_err()
+            .contains("v0"));
+
+        let mut planes = dataset();
+        planes.metadata.feature_set = Some(FeatureSet::BoardPlanes);
+        assert!(SelfPlayDataset::from_dataset(&planes)
+            .unwrap_err()
+            .contains("features"));
+    }
+
+    #[test]
+    fn test_train_model_writes_a_loadable_model() {
+        let dataset = SelfPlayDataset::from_dataset(&dataset()).unwrap();
+        let config = TrainNetworkConfig {
+            epochs: 30,
+            batch_size: 4,
+            validation_split: 0.0,
+            ..TrainNetworkConfig::default()
+        };
+        let model = train_model(&dataset, &[16], &config);
+        assert_eq!(model.metadata.samples, Some(COLS));
+        assert!(model.metadata.extra.contains_key("policy_history"));
+        let normalizer = model.normalizer.as_ref().unwrap();
+        assert_eq!(normalizer.len(), FeatureSet::GameFeatures.size());
+
+        let ai = MLAI::from_model_file(&ModelFile::from_json(&model.to_json()).unwrap()).unwrap();
+        let mut state = GameState::new();
+        state.make_move(0).unwrap();
+        let policy = ai.policy(&state);
+        let best = (0..COLS)
+            .max_by(|&a, &b| policy[a].total_cmp(&policy[b]))
+            .unwrap();
+        assert_eq!(best, 3, "{:?}", policy);
+    }
+}
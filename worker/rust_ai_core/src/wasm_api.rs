@@ -1,14 +1,21 @@
 use super::genetic_params::GeneticParams;
+use super::mcts::{SearchLimits, MCTS};
 use super::{GameState, HeuristicAI, AI};
 use super::ml_ai::MLAI;
+use super::model::ModelFile;
 use wasm_bindgen::prelude::*;
 use serde_wasm_bindgen;
+use std::time::Duration;
+
+// Caps the persistent MCTS tree so it cannot grow without bound in the browser
+const MCTS_MAX_NODES: usize = 100_000;
 
 #[wasm_bindgen]
 pub struct ConnectFourAI {
     ai: AI,
     heuristic_ai: HeuristicAI,
     ml_ai: MLAI,
+    mcts: MCTS,
 }
 
 #[wasm_bindgen]
@@ -19,6 +26,7 @@ impl ConnectFourAI {
             ai: AI::new(),
             heuristic_ai: HeuristicAI::new(),
             ml_ai: MLAI::new(),
+            mcts: MCTS::new(1.0, 400),
         }
     }
 
@@ -96,6 +104,62 @@ impl ConnectFourAI {
         Ok(serde_wasm_bindgen::to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))?)
     }
 
+    pub fn get_mcts_move(
+        &mut self,
+        board_state: &JsValue,
+        simulations: usize,
+        time_budget_ms: u32,
+    ) -> Result<JsValue, JsValue> {
+        let state: GameState = serde_wasm_bindgen::from_value(board_state.clone())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        if state.is_game_over() {
+            return Err(JsValue::from_str("Game is already over"));
+        }
+
+        // Carry over the tree from the previous search if this position is in it
+        let reused_tree = self.mcts.advance_to(&state);
+        let reused_visits = self
+            .mcts
+            .root()
+            .map_or(0, |root| self.mcts.nodes[root].visits);
+
+        self.mcts.num_simulations = simulations;
+        self.mcts.limits = SearchLimits {
+            time_limit: (time_budget_ms > 0)
+                .then(|| Duration::from_millis(time_budget_ms as u64)),
+            max_nodes: Some(MCTS_MAX_NODES),
+            early_stop: true,
+        };
+        let ml_ai = &self.ml_ai;
+        let value_fn = |s: &GameState| ml_ai.evaluate_position(s);
+        let policy_fn = |s: &GameState| ml_ai.policy(s);
+        let search = self.mcts.search_with_stats(state, &value_fn, &policy_fn);
+
+        // Play our move in the tree so the opponent's reply can be found next time
+        self.mcts.advance_root(&[search.best_move]);
+
+        let result = serde_json::json!({
+            "move": search.best_move,
+            "moveProbabilities": search.move_probabilities,
+            "visitCounts": search.visit_counts,
+            "moveValues": search.move_values,
+            "rootValue": search.root_value,
+            "provenValue": search.proven_value,
+            "simulations": search.simulations,
+            "elapsedMs": search.elapsed.as_secs_f64() * 1000.0,
+            "stopReason": format!("{:?}", search.stop_reason),
+            "reusedTree": reused_tree,
+            "reusedVisits": reused_visits,
+        });
+
+        Ok(serde_wasm_bindgen::to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))?)
+    }
+
+    pub fn reset_mcts(&mut self) {
+        self.mcts.reset();
+    }
+
     pub fn evaluate_position(&self, board_state: &JsValue) -> Result<f32, JsValue> {
         let state: GameState = serde_wasm_bindgen::from_value(board_state.clone())
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
@@ -180,7 +244,32 @@ impl ConnectFourAI {
         let policy_weights: Vec<f32> = serde_wasm_bindgen::from_value(policy_weights.clone())
             .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
-        self.ml_ai.load_weights(&value_weights, &policy_weights);
+        match self.ml_ai.load_weights(&value_weights, &policy_weights) {
+            Ok(()) => Ok(()),
+            // Vectors that don't fit the current networks may be the legacy
+            // board layout of the shipped `ml_ai_weights_*.json` files
+            Err(e) => {
+                let model = ModelFile::from_legacy_weights(&value_weights, &policy_weights)
+                    .map_err(|legacy| {
+                        JsValue::from_str(&format!("{}; as legacy weights: {}", e, legacy))
+                    })?;
+                self.ml_ai = MLAI::from_model_file(&model).map_err(|e| JsValue::from_str(&e))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Replaces the ML AI with the networks described by a model file.
+    pub fn load_ml_model(&mut self, model_json: &str) -> Result<(), JsValue> {
+        let model = ModelFile::from_json(model_json).map_err(|e| JsValue::from_str(&e))?;
+        self.ml_ai = MLAI::from_model_file(&model).map_err(|e| JsValue::from_str(&e))?;
+        Ok(())
+    }
+
+    /// Replaces the ML AI with a binary model file, evaluated at the
+    /// precision its weights are stored at.
+    pub fn load_ml_model_binary(&mut self, model_bytes: &[u8]) -> Result<(), JsValue> {
+        self.ml_ai = MLAI::from_binary(model_bytes).map_err(|e| JsValue::from_str(&e))?;
         Ok(())
     }
 }
@@ -167,55 +167,17 @@ struct MLSimpleAI {
 
 impl MLSimpleAI {
     fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let mut ai = MLAI::new();
-
-        // Try to load simple model weights
+        // Panics rather than skipping the matchups: an untrained stand-in
+        // would pass without testing the model
         let weights_path = "../../ml/data/weights/simple_model_enhanced.json";
-        if let Ok(weights_data) = std::fs::read_to_string(weights_path) {
-            if let Ok(weights) = serde_json::from_str::<serde_json::Value>(&weights_data) {
-                if let (Some(value_network), Some(policy_network)) =
-                    (weights.get("value_network"), weights.get("policy_network"))
-                {
-                    // Extract weights from the network structure
-                    let value_weights = extract_weights_from_network(value_network);
-                    let policy_weights = extract_weights_from_network(policy_network);
-
-                    if !value_weights.is_empty() && !policy_weights.is_empty() {
-                        ai.load_weights(&value_weights, &policy_weights);
-                        println!("✅ Loaded simple model (297KB, 50 epochs, 1000 games)");
-                    }
-                }
-            }
-        }
+        let ai = MLAI::load_model(weights_path)
+            .unwrap_or_else(|e| panic!("failed to load {}: {}", weights_path, e));
+        println!("✅ Loaded simple model (297KB, 50 epochs, 1000 games)");
 
         Ok(Self { ai })
     }
 }
 
-fn extract_weights_from_network(network: &serde_json::Value) -> Vec<f32> {
-    let mut weights = Vec::new();
-
-    if let Some(layers) = network.as_object() {
-        for (layer_name, layer_data) in layers {
-            if layer_name.contains("weight") {
-                if let Some(weight_array) = layer_data.as_array() {
-                    for row in weight_array {
-                        if let Some(row_array) = row.as_array() {
-                            for weight in row_array {
-                                if let Some(weight_value) = weight.as_f64() {
-                                    weights.push(weight_value as f32);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    weights
-}
-
 impl AIPlayer for MLSimpleAI {
     fn get_move(&mut self, game_state: &GameState) -> Option<usize> {
         let response = self.ai.get_best_move(game_state);